@@ -52,3 +52,36 @@ __wfi:
   bx lr
   .cfi_endproc
   .size __wfi, . - __wfi
+
+  .global __primask
+  .cfi_sections .debug_frame
+  .section .text.__primask, "ax"
+  .thumb_func
+  .cfi_startproc
+__primask:
+  mrs r0, PRIMASK
+  bx lr
+  .cfi_endproc
+  .size __primask, . - __primask
+
+  .global __dsb
+  .cfi_sections .debug_frame
+  .section .text.__dsb, "ax"
+  .thumb_func
+  .cfi_startproc
+__dsb:
+  dsb sy
+  bx lr
+  .cfi_endproc
+  .size __dsb, . - __dsb
+
+  .global __isb
+  .cfi_sections .debug_frame
+  .section .text.__isb, "ax"
+  .thumb_func
+  .cfi_startproc
+__isb:
+  isb sy
+  bx lr
+  .cfi_endproc
+  .size __isb, . - __isb
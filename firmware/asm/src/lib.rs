@@ -22,6 +22,30 @@ pub fn enable_irq() {
     unsafe { __cpsiei() }
 }
 
+/// Data Synchronization Barrier
+pub fn dsb() {
+    extern "C" {
+        fn __dsb();
+    }
+    unsafe { __dsb() }
+}
+
+/// Instruction Synchronization Barrier
+pub fn isb() {
+    extern "C" {
+        fn __isb();
+    }
+    unsafe { __isb() }
+}
+
+/// Reads the PRIMASK register; returns `true` if interrupts are masked
+pub fn primask() -> bool {
+    extern "C" {
+        fn __primask() -> u32;
+    }
+    unsafe { __primask() & 1 == 1 }
+}
+
 /// Send EVent
 pub fn sev() {
     #[cfg(target_arch = "arm")]
@@ -0,0 +1,75 @@
+//! Critical sections
+//!
+//! Critical sections can be nested: `f` may call `free`
+
+/// Runs `f` with interrupts masked
+///
+/// Interrupts are unmasked when `f` returns only if they were unmasked when this function was
+/// called. The `Waker`s of `executor` can be woken up from here
+#[cfg(target_arch = "arm")]
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    use core::sync::atomic::{self, Ordering};
+
+    let masked = asm::primask();
+    asm::disable_irq();
+    atomic::compiler_fence(Ordering::SeqCst);
+    let r = f();
+    atomic::compiler_fence(Ordering::SeqCst);
+    if !masked {
+        asm::enable_irq();
+    }
+    r
+}
+
+/// Runs `f` while holding a global lock
+///
+/// On the host threads play the role of the interrupt handlers. The lock is not released when `f`
+/// returns if the calling thread was already holding it
+#[cfg(not(target_arch = "arm"))]
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    extern crate std;
+
+    use core::{
+        cell::Cell,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    static LOCKED: AtomicBool = AtomicBool::new(false);
+
+    std::thread_local! {
+        // whether this thread is holding the lock
+        static HOLDING: Cell<bool> = const { Cell::new(false) };
+    }
+
+    if HOLDING.with(|holding| holding.get()) {
+        return f();
+    }
+
+    while LOCKED
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop()
+    }
+    HOLDING.with(|holding| holding.set(true));
+    let r = f();
+    HOLDING.with(|holding| holding.set(false));
+    LOCKED.store(false, Ordering::Release);
+    r
+}
+
+/// Runs `f`; used by the primitives that don't need to be interrupt-safe
+pub(crate) fn nop<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn nested() {
+        assert_eq!(super::free(|| super::free(|| 1) + 1), 2);
+
+        // the outer critical section released the lock
+        assert_eq!(super::free(|| 3), 3);
+    }
+}
@@ -0,0 +1,263 @@
+//! Future combinators
+//!
+//! None of these allocate. All the futures passed to a combinator are polled with the same `Waker`
+//! so waking one of them polls all of them again
+
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The output of `select`
+pub enum Either<A, B> {
+    /// The first future completed first
+    First(A),
+    /// The second future completed first
+    Second(B),
+}
+
+/// The output of `select3`
+pub enum Either3<A, B, C> {
+    /// The first future completed first
+    First(A),
+    /// The second future completed first
+    Second(B),
+    /// The third future completed first
+    Third(C),
+}
+
+/// Waits for both futures to complete
+pub fn join<A, B>(a: A, b: B) -> impl Future<Output = (A::Output, B::Output)>
+where
+    A: Future,
+    B: Future,
+{
+    Join2 {
+        a: MaybeDone::Pending(a),
+        b: MaybeDone::Pending(b),
+    }
+}
+
+/// Waits for the three futures to complete
+pub fn join3<A, B, C>(a: A, b: B, c: C) -> impl Future<Output = (A::Output, B::Output, C::Output)>
+where
+    A: Future,
+    B: Future,
+    C: Future,
+{
+    Join3 {
+        a: MaybeDone::Pending(a),
+        b: MaybeDone::Pending(b),
+        c: MaybeDone::Pending(c),
+    }
+}
+
+/// Waits for either future to complete
+///
+/// If both futures are ready `a` wins. The future that didn't complete is dropped, which cancels
+/// whatever operation it was performing
+pub fn select<A, B>(a: A, b: B) -> impl Future<Output = Either<A::Output, B::Output>>
+where
+    A: Future,
+    B: Future,
+{
+    Select2 { a, b }
+}
+
+/// Waits for any of the three futures to complete
+///
+/// Ties are resolved in argument order. The futures that didn't complete are dropped, which cancels
+/// whatever operations they were performing
+pub fn select3<A, B, C>(
+    a: A,
+    b: B,
+    c: C,
+) -> impl Future<Output = Either3<A::Output, B::Output, C::Output>>
+where
+    A: Future,
+    B: Future,
+    C: Future,
+{
+    Select3 { a, b, c }
+}
+
+enum MaybeDone<F>
+where
+    F: Future,
+{
+    Pending(F),
+    Done(F::Output),
+    Taken,
+}
+
+impl<F> MaybeDone<F>
+where
+    F: Future,
+{
+    // returns `true` if the future has completed and its output has not been taken
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> bool {
+        // NOTE(unsafe) `f` is pinned because `self` is pinned; it's dropped in place by `set`
+        let output = match unsafe { self.as_mut().get_unchecked_mut() } {
+            MaybeDone::Pending(f) => match unsafe { Pin::new_unchecked(f) }.poll(cx) {
+                Poll::Ready(output) => output,
+                Poll::Pending => return false,
+            },
+            MaybeDone::Done(_) => return true,
+            MaybeDone::Taken => return false,
+        };
+
+        self.set(MaybeDone::Done(output));
+        true
+    }
+
+    // returns `None` if the future has not completed or its output has already been taken
+    fn take(self: Pin<&mut Self>) -> Option<F::Output> {
+        // NOTE(unsafe) the output is not pinned and a `Pending` future is never moved
+        let this = unsafe { self.get_unchecked_mut() };
+        if let MaybeDone::Done(_) = this {
+            if let MaybeDone::Done(output) = mem::replace(this, MaybeDone::Taken) {
+                return Some(output);
+            }
+        }
+
+        None
+    }
+}
+
+// NOTE(unsafe) structural pinning: the fields are never moved out of a pinned `Join*` or
+// `Select*` and these types implement neither `Drop` nor `Unpin`
+macro_rules! join {
+    ($Join:ident: $($f:ident: $F:ident),+) => {
+        struct $Join<$($F),+>
+        where
+            $($F: Future,)+
+        {
+            $($f: MaybeDone<$F>,)+
+        }
+
+        impl<$($F),+> Future for $Join<$($F),+>
+        where
+            $($F: Future,)+
+        {
+            type Output = ($($F::Output,)+);
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                let this = unsafe { self.get_unchecked_mut() };
+                $(let mut $f = unsafe { Pin::new_unchecked(&mut this.$f) };)+
+
+                let mut done = true;
+                $(done &= $f.as_mut().poll(cx);)+
+
+                if done {
+                    if let ($(Some($f),)+) = ($($f.take(),)+) {
+                        return Poll::Ready(($($f,)+));
+                    }
+                }
+
+                Poll::Pending
+            }
+        }
+    };
+}
+
+join!(Join2: a: A, b: B);
+join!(Join3: a: A, b: B, c: C);
+
+macro_rules! select {
+    ($Select:ident, $Either:ident: $($f:ident: $F:ident => $variant:ident),+) => {
+        struct $Select<$($F),+> {
+            $($f: $F,)+
+        }
+
+        impl<$($F),+> Future for $Select<$($F),+>
+        where
+            $($F: Future,)+
+        {
+            type Output = $Either<$($F::Output),+>;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                let this = unsafe { self.get_unchecked_mut() };
+
+                $(
+                    if let Poll::Ready(output) = unsafe { Pin::new_unchecked(&mut this.$f) }.poll(cx) {
+                        return Poll::Ready($Either::$variant(output));
+                    }
+                )+
+
+                Poll::Pending
+            }
+        }
+    };
+}
+
+select!(Select2, Either: a: A => First, b: B => Second);
+select!(Select3, Either3: a: A => First, b: B => Second, c: C => Third);
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        cell::Cell,
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use super::Either;
+    use crate::testing::block_on;
+
+    // completes after being polled `n` times, waking itself up in between; sets `dropped` when
+    // dropped
+    struct Countdown<'a> {
+        n: u32,
+        dropped: &'a Cell<bool>,
+    }
+
+    impl Future for Countdown<'_> {
+        type Output = u32;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            if self.n == 0 {
+                Poll::Ready(42)
+            } else {
+                self.n -= 1;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    impl Drop for Countdown<'_> {
+        fn drop(&mut self) {
+            self.dropped.set(true);
+        }
+    }
+
+    #[test]
+    fn join() {
+        let (a, b) = (Cell::new(false), Cell::new(false));
+        let (x, y) = block_on(super::join(
+            Countdown { n: 1, dropped: &a },
+            Countdown { n: 3, dropped: &b },
+        ));
+
+        assert_eq!((x, y), (42, 42));
+        // the first future is dropped as soon as it completes
+        assert!(a.get());
+        assert!(b.get());
+    }
+
+    #[test]
+    fn select() {
+        let (a, b) = (Cell::new(false), Cell::new(false));
+        let out = block_on(super::select(
+            Countdown { n: 3, dropped: &a },
+            Countdown { n: 1, dropped: &b },
+        ));
+
+        assert!(matches!(out, Either::Second(42)));
+        // the losing future has been cancelled
+        assert!(a.get());
+    }
+}
@@ -7,5 +7,15 @@
 #![deny(warnings)]
 #![no_std]
 
+#[macro_use]
+mod primitives;
+
+pub mod critical;
+pub mod future;
+pub mod sync;
 pub mod task;
+#[cfg(test)]
+mod testing;
 pub mod unsync;
+mod wait_list;
+pub mod waker;
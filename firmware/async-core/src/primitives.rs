@@ -0,0 +1,14 @@
+//! Synchronization primitives shared by the `sync` and `unsync` modules
+//!
+//! Each macro defines a primitive in the module where it's invoked. `$free` is the function that
+//! runs a closure in a critical section; all the `Cell`s of a primitive are only accessed from
+//! within such closures so the `sync` flavour can `unsafe impl Sync`
+
+#[macro_use]
+mod barrier;
+#[macro_use]
+mod rwlock;
+#[macro_use]
+mod semaphore;
+#[macro_use]
+mod signal;
@@ -0,0 +1,128 @@
+macro_rules! barrier {
+    ($free:path) => {
+        use core::{
+            cell::Cell,
+            future::Future,
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        use crate::wait_list::{WaitList, Waiter};
+
+        /// Lets a group of tasks wait for each other
+        ///
+        /// The barrier can be reused once all the tasks have been released
+        pub struct Barrier {
+            n: usize,
+            arrived: Cell<usize>,
+            // incremented each time the tasks are released
+            generation: Cell<usize>,
+            waiters: WaitList,
+        }
+
+        #[derive(Clone, Copy)]
+        enum State {
+            NotArrived,
+            Waiting { generation: usize },
+            Released,
+        }
+
+        impl Barrier {
+            /// Creates a barrier for a group of `n` tasks
+            pub const fn new(n: usize) -> Self {
+                Self {
+                    n,
+                    arrived: Cell::new(0),
+                    generation: Cell::new(0),
+                    waiters: WaitList::new(),
+                }
+            }
+
+            /// Waits until all the `n` tasks have called this method
+            ///
+            /// If this future is dropped before completion the task no longer counts as arrived
+            pub fn wait(&self) -> impl Future<Output = ()> + '_ {
+                struct Wait<'b> {
+                    barrier: &'b Barrier,
+                    state: Cell<State>,
+                    waiter: Waiter,
+                }
+
+                impl Future for Wait<'_> {
+                    type Output = ();
+
+                    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                        // NOTE(unsafe) `waiter` is never moved out of the pinned future
+                        let waiter = unsafe { self.as_ref().map_unchecked(|w| &w.waiter) };
+                        let barrier = self.barrier;
+
+                        let state = $free(|| match self.state.get() {
+                            State::NotArrived => {
+                                let arrived = barrier.arrived.get() + 1;
+
+                                if arrived >= barrier.n {
+                                    // last task: release everyone
+                                    barrier.arrived.set(0);
+                                    barrier
+                                        .generation
+                                        .set(barrier.generation.get().wrapping_add(1));
+                                    barrier.waiters.wake_all();
+                                    State::Released
+                                } else {
+                                    barrier.arrived.set(arrived);
+                                    // NOTE(unsafe) `waiter` is removed from the list on drop
+                                    unsafe { barrier.waiters.register(waiter, cx.waker()) }
+                                    State::Waiting {
+                                        generation: barrier.generation.get(),
+                                    }
+                                }
+                            }
+
+                            State::Waiting { generation } => {
+                                if generation == barrier.generation.get() {
+                                    // spurious wake-up
+                                    unsafe { barrier.waiters.register(waiter, cx.waker()) }
+                                    State::Waiting { generation }
+                                } else {
+                                    barrier.waiters.remove(&waiter);
+                                    State::Released
+                                }
+                            }
+
+                            State::Released => State::Released,
+                        });
+                        self.state.set(state);
+
+                        if let State::Released = state {
+                            Poll::Ready(())
+                        } else {
+                            Poll::Pending
+                        }
+                    }
+                }
+
+                impl Drop for Wait<'_> {
+                    fn drop(&mut self) {
+                        let barrier = self.barrier;
+
+                        $free(|| {
+                            barrier.waiters.cancel(&self.waiter);
+
+                            if let State::Waiting { generation } = self.state.get() {
+                                if generation == barrier.generation.get() {
+                                    barrier.arrived.set(barrier.arrived.get() - 1);
+                                }
+                            }
+                        })
+                    }
+                }
+
+                Wait {
+                    barrier: self,
+                    state: Cell::new(State::NotArrived),
+                    waiter: Waiter::new(),
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,242 @@
+macro_rules! rwlock {
+    ($free:path) => {
+        use core::{
+            cell::{Cell, UnsafeCell},
+            future::Future,
+            ops,
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        use crate::wait_list::{WaitList, Waiter};
+
+        /// `async`-aware reader-writer lock
+        ///
+        /// Several readers can hold the lock at the same time; a writer gets exclusive access.
+        /// Writers take precedence: once a writer is waiting no new readers are let in
+        pub struct RwLock<T> {
+            data: UnsafeCell<T>,
+            readers: Cell<usize>,
+            writer: Cell<bool>,
+            // the tasks waiting for read access
+            read_waiters: WaitList,
+            // the tasks waiting for write access
+            write_waiters: WaitList,
+        }
+
+        impl<T> RwLock<T> {
+            /// Creates a new lock
+            pub const fn new(data: T) -> Self {
+                Self {
+                    data: UnsafeCell::new(data),
+                    readers: Cell::new(0),
+                    writer: Cell::new(false),
+                    read_waiters: WaitList::new(),
+                    write_waiters: WaitList::new(),
+                }
+            }
+
+            /// Attempts to acquire read access
+            pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+                if $free(|| self.take_read()) {
+                    Some(RwLockReadGuard { lock: self })
+                } else {
+                    None
+                }
+            }
+
+            /// Attempts to acquire write access
+            pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+                if $free(|| self.take_write()) {
+                    Some(RwLockWriteGuard { lock: self })
+                } else {
+                    None
+                }
+            }
+
+            /// Acquires read access
+            pub fn read(&self) -> impl Future<Output = RwLockReadGuard<'_, T>> {
+                struct Read<'l, T> {
+                    lock: &'l RwLock<T>,
+                    waiter: Waiter,
+                }
+
+                impl<'l, T> Future for Read<'l, T> {
+                    type Output = RwLockReadGuard<'l, T>;
+
+                    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                        // NOTE(unsafe) `waiter` is never moved out of the pinned future
+                        let waiter = unsafe { self.as_ref().map_unchecked(|r| &r.waiter) };
+                        let lock = self.lock;
+
+                        let acquired = $free(|| {
+                            if lock.take_read() {
+                                lock.read_waiters.remove(&waiter);
+                                true
+                            } else {
+                                // NOTE(unsafe) `waiter` is removed from the list on drop
+                                unsafe { lock.read_waiters.register(waiter, cx.waker()) }
+                                false
+                            }
+                        });
+
+                        if acquired {
+                            Poll::Ready(RwLockReadGuard { lock })
+                        } else {
+                            Poll::Pending
+                        }
+                    }
+                }
+
+                impl<T> Drop for Read<'_, T> {
+                    fn drop(&mut self) {
+                        $free(|| self.lock.read_waiters.cancel(&self.waiter))
+                    }
+                }
+
+                Read {
+                    lock: self,
+                    waiter: Waiter::new(),
+                }
+            }
+
+            /// Acquires write access
+            pub fn write(&self) -> impl Future<Output = RwLockWriteGuard<'_, T>> {
+                struct Write<'l, T> {
+                    lock: &'l RwLock<T>,
+                    waiter: Waiter,
+                }
+
+                impl<'l, T> Future for Write<'l, T> {
+                    type Output = RwLockWriteGuard<'l, T>;
+
+                    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                        // NOTE(unsafe) `waiter` is never moved out of the pinned future
+                        let waiter = unsafe { self.as_ref().map_unchecked(|w| &w.waiter) };
+                        let lock = self.lock;
+
+                        let acquired = $free(|| {
+                            if lock.take_write() {
+                                lock.write_waiters.remove(&waiter);
+                                true
+                            } else {
+                                // NOTE(unsafe) `waiter` is removed from the list on drop
+                                unsafe { lock.write_waiters.register(waiter, cx.waker()) }
+                                false
+                            }
+                        });
+
+                        if acquired {
+                            Poll::Ready(RwLockWriteGuard { lock })
+                        } else {
+                            Poll::Pending
+                        }
+                    }
+                }
+
+                impl<T> Drop for Write<'_, T> {
+                    fn drop(&mut self) {
+                        let lock = self.lock;
+
+                        $free(|| {
+                            lock.write_waiters.cancel(&self.waiter);
+
+                            // readers may have been held back only because this writer was waiting
+                            if lock.write_waiters.is_empty() && !lock.writer.get() {
+                                lock.read_waiters.wake_all();
+                            }
+                        })
+                    }
+                }
+
+                Write {
+                    lock: self,
+                    waiter: Waiter::new(),
+                }
+            }
+
+            // NOTE must be called from a critical section
+            fn take_read(&self) -> bool {
+                if self.writer.get() || !self.write_waiters.is_empty() {
+                    false
+                } else {
+                    self.readers.set(self.readers.get() + 1);
+                    true
+                }
+            }
+
+            // NOTE must be called from a critical section
+            fn take_write(&self) -> bool {
+                if self.writer.get() || self.readers.get() != 0 {
+                    false
+                } else {
+                    self.writer.set(true);
+                    true
+                }
+            }
+        }
+
+        /// Read access to the data protected by a `RwLock`
+        pub struct RwLockReadGuard<'l, T> {
+            lock: &'l RwLock<T>,
+        }
+
+        impl<T> ops::Deref for RwLockReadGuard<'_, T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                unsafe { &*self.lock.data.get() }
+            }
+        }
+
+        impl<T> Drop for RwLockReadGuard<'_, T> {
+            fn drop(&mut self) {
+                let lock = self.lock;
+
+                $free(|| {
+                    let readers = lock.readers.get() - 1;
+                    lock.readers.set(readers);
+
+                    if readers == 0 {
+                        lock.write_waiters.wake_one();
+                    }
+                })
+            }
+        }
+
+        /// Write access to the data protected by a `RwLock`
+        pub struct RwLockWriteGuard<'l, T> {
+            lock: &'l RwLock<T>,
+        }
+
+        impl<T> ops::Deref for RwLockWriteGuard<'_, T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                unsafe { &*self.lock.data.get() }
+            }
+        }
+
+        impl<T> ops::DerefMut for RwLockWriteGuard<'_, T> {
+            fn deref_mut(&mut self) -> &mut T {
+                unsafe { &mut *self.lock.data.get() }
+            }
+        }
+
+        impl<T> Drop for RwLockWriteGuard<'_, T> {
+            fn drop(&mut self) {
+                let lock = self.lock;
+
+                $free(|| {
+                    lock.writer.set(false);
+
+                    if lock.write_waiters.is_empty() {
+                        lock.read_waiters.wake_all();
+                    } else {
+                        lock.write_waiters.wake_one();
+                    }
+                })
+            }
+        }
+    };
+}
@@ -0,0 +1,139 @@
+macro_rules! semaphore {
+    ($free:path) => {
+        use core::{
+            cell::Cell,
+            future::Future,
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        use crate::wait_list::{WaitList, Waiter};
+
+        /// Counting semaphore
+        ///
+        /// Limits the number of tasks that can perform an operation at the same time
+        pub struct Semaphore {
+            permits: Cell<usize>,
+            waiters: WaitList,
+        }
+
+        impl Semaphore {
+            /// Creates a semaphore with the given number of permits
+            pub const fn new(permits: usize) -> Self {
+                Self {
+                    permits: Cell::new(permits),
+                    waiters: WaitList::new(),
+                }
+            }
+
+            /// Returns the number of permits that can be acquired right now
+            pub fn available_permits(&self) -> usize {
+                $free(|| self.permits.get())
+            }
+
+            /// Acquires a permit, if one is available
+            pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+                if $free(|| self.take()) {
+                    Some(SemaphorePermit { semaphore: self })
+                } else {
+                    None
+                }
+            }
+
+            /// Acquires a permit, waiting for one to be released if none is available
+            ///
+            /// Waiting tasks acquire the released permits in FIFO order: a released permit is
+            /// handed to the task at the front of the queue so neither `try_acquire` nor a new
+            /// `acquire` can take it first
+            pub fn acquire(&self) -> impl Future<Output = SemaphorePermit<'_>> {
+                struct Acquire<'s> {
+                    semaphore: &'s Semaphore,
+                    waiter: Waiter,
+                }
+
+                impl<'s> Future for Acquire<'s> {
+                    type Output = SemaphorePermit<'s>;
+
+                    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                        // NOTE(unsafe) `waiter` is never moved out of the pinned future
+                        let waiter = unsafe { self.as_ref().map_unchecked(|a| &a.waiter) };
+                        let semaphore = self.semaphore;
+
+                        let acquired = $free(|| {
+                            // the first check: `release` handed its permit to this task
+                            if waiter.is_woken() || semaphore.take() {
+                                semaphore.waiters.remove(&waiter);
+                                true
+                            } else {
+                                // NOTE(unsafe) `waiter` is removed from the list on drop
+                                unsafe { semaphore.waiters.register(waiter, cx.waker()) }
+                                false
+                            }
+                        });
+
+                        if acquired {
+                            Poll::Ready(SemaphorePermit { semaphore })
+                        } else {
+                            Poll::Pending
+                        }
+                    }
+                }
+
+                impl Drop for Acquire<'_> {
+                    fn drop(&mut self) {
+                        let semaphore = self.semaphore;
+
+                        $free(|| {
+                            if self.waiter.is_woken() {
+                                // this task was handed a permit but it won't use it
+                                semaphore.waiters.remove(&self.waiter);
+                                semaphore.release();
+                            } else {
+                                semaphore.waiters.cancel(&self.waiter);
+                            }
+                        })
+                    }
+                }
+
+                Acquire {
+                    semaphore: self,
+                    waiter: Waiter::new(),
+                }
+            }
+
+            // NOTE must be called from a critical section
+            //
+            // hands the permit to the task at the front of the queue, if any; this way `permits`
+            // is always zero while tasks are waiting
+            fn release(&self) {
+                if !self.waiters.wake_one() {
+                    self.permits.set(self.permits.get() + 1);
+                }
+            }
+
+            // NOTE must be called from a critical section
+            fn take(&self) -> bool {
+                let permits = self.permits.get();
+                if permits == 0 {
+                    false
+                } else {
+                    self.permits.set(permits - 1);
+                    true
+                }
+            }
+        }
+
+        /// A permit acquired from a `Semaphore`; it's released when dropped
+        pub struct SemaphorePermit<'s> {
+            semaphore: &'s Semaphore,
+        }
+
+        impl Drop for SemaphorePermit<'_> {
+            fn drop(&mut self) {
+                let semaphore = self.semaphore;
+
+                $free(|| semaphore.release())
+            }
+        }
+    };
+}
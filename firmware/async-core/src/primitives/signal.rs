@@ -0,0 +1,69 @@
+macro_rules! signal {
+    ($free:path) => {
+        use core::{
+            cell::Cell,
+            future::Future,
+            pin::Pin,
+            task::{Context, Poll},
+        };
+
+        use crate::waker::AtomicWaker;
+
+        /// A value that a task waits for
+        ///
+        /// `signal` stores the value and `wait` takes it. If `signal` is called again before the
+        /// value is taken the old value is replaced. Only one task should `wait` on a signal at
+        /// any time
+        pub struct Signal<T> {
+            value: Cell<Option<T>>,
+            waker: AtomicWaker,
+        }
+
+        impl<T> Signal<T> {
+            /// Creates a signal that's not set
+            pub const fn new() -> Self {
+                Self {
+                    value: Cell::new(None),
+                    waker: AtomicWaker::new(),
+                }
+            }
+
+            /// Sets the signal and wakes up the task waiting on it
+            pub fn signal(&self, value: T) {
+                let old = $free(|| self.value.replace(Some(value)));
+                self.waker.wake();
+
+                // drop the old value outside the critical section
+                drop(old);
+            }
+
+            /// Takes the value, if the signal is set
+            pub fn try_take(&self) -> Option<T> {
+                $free(|| self.value.take())
+            }
+
+            /// Waits until the signal is set and takes its value
+            pub fn wait(&self) -> impl Future<Output = T> + '_ {
+                struct Wait<'s, T> {
+                    signal: &'s Signal<T>,
+                }
+
+                impl<T> Future for Wait<'_, T> {
+                    type Output = T;
+
+                    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+                        self.signal.waker.register(cx.waker());
+
+                        if let Some(value) = self.signal.try_take() {
+                            Poll::Ready(value)
+                        } else {
+                            Poll::Pending
+                        }
+                    }
+                }
+
+                Wait { signal: self }
+            }
+        }
+    };
+}
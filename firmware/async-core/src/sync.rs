@@ -0,0 +1,16 @@
+//! `Sync` task synchronization primitives
+//!
+//! Unlike the ones in `unsync` these can be shared between tasks that run at different priorities
+//! and with interrupt handlers. Except for `spsc`, their state is updated in short critical
+//! sections (interrupts masked) so they must not be used while interrupts are masked
+
+mod barrier;
+mod rwlock;
+mod semaphore;
+mod signal;
+pub mod spsc;
+
+pub use barrier::Barrier;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
+pub use signal::Signal;
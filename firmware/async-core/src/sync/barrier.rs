@@ -0,0 +1,35 @@
+barrier!(crate::critical::free);
+
+// NOTE(unsafe) the counters and the wait list are only accessed from within critical sections
+unsafe impl Sync for Barrier {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::{thread, vec::Vec};
+
+    use super::Barrier;
+    use crate::testing;
+
+    #[test]
+    fn threads() {
+        static BARRIER: Barrier = Barrier::new(3);
+        static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+
+        let threads = (0..3)
+            .map(|_| {
+                thread::spawn(|| {
+                    ARRIVED.fetch_add(1, Ordering::Relaxed);
+                    testing::block_on(BARRIER.wait());
+                    assert_eq!(ARRIVED.load(Ordering::Relaxed), 3);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,48 @@
+rwlock!(crate::critical::free);
+
+// NOTE(unsafe) the lock state is only accessed from within critical sections; readers in different
+// contexts share `&T`
+unsafe impl<T> Sync for RwLock<T> where T: Send + Sync {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{thread, vec::Vec};
+
+    use super::RwLock;
+    use crate::testing;
+
+    #[test]
+    fn threads() {
+        static LOCK: RwLock<(u32, u32)> = RwLock::new((0, 0));
+
+        let writers = (0..2)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..100 {
+                        let mut guard = testing::block_on(LOCK.write());
+                        guard.0 += 1;
+                        thread::yield_now();
+                        guard.1 += 1;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let reader = thread::spawn(|| {
+            for _ in 0..100 {
+                let guard = testing::block_on(LOCK.read());
+                // never observes a write in progress
+                assert_eq!(guard.0, guard.1);
+            }
+        });
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        reader.join().unwrap();
+
+        assert_eq!(*LOCK.try_read().unwrap(), (200, 200));
+    }
+}
@@ -0,0 +1,43 @@
+semaphore!(crate::critical::free);
+
+// NOTE(unsafe) the permit count and the wait list are only accessed from within critical sections
+unsafe impl Sync for Semaphore {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::{thread, vec::Vec};
+
+    use super::Semaphore;
+    use crate::testing;
+
+    #[test]
+    fn threads() {
+        static SEMAPHORE: Semaphore = Semaphore::new(2);
+        static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+        static MAX: AtomicUsize = AtomicUsize::new(0);
+
+        let threads = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..100 {
+                        let _permit = testing::block_on(SEMAPHORE.acquire());
+                        let active = ACTIVE.fetch_add(1, Ordering::Relaxed) + 1;
+                        MAX.fetch_max(active, Ordering::Relaxed);
+                        thread::yield_now();
+                        ACTIVE.fetch_sub(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(MAX.load(Ordering::Relaxed) <= 2);
+        assert_eq!(SEMAPHORE.available_permits(), 2);
+    }
+}
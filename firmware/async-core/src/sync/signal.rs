@@ -0,0 +1,25 @@
+signal!(crate::critical::free);
+
+// NOTE(unsafe) the value is only accessed from within critical sections
+unsafe impl<T> Sync for Signal<T> where T: Send {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::thread;
+
+    use super::Signal;
+    use crate::testing;
+
+    #[test]
+    fn interrupt() {
+        static SIGNAL: Signal<u32> = Signal::new();
+
+        // the other thread plays the role of an interrupt handler
+        let handler = thread::spawn(|| SIGNAL.signal(42));
+        assert_eq!(testing::block_on(SIGNAL.wait()), 42);
+
+        handler.join().unwrap();
+    }
+}
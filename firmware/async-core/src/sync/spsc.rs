@@ -0,0 +1,298 @@
+//! Lock-free Single Producer Single Consumer channels
+
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    mem::MaybeUninit,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::waker::AtomicWaker;
+
+/// `async`-aware channel that can hold up to `N` messages
+///
+/// `N` must be greater than 0. The `Sender` and the `Receiver` can live in different execution
+/// contexts, e.g. an interrupt handler can `try_send` messages to a task that `recv`-s them
+pub struct Channel<T, const N: usize> {
+    buffer: UnsafeCell<MaybeUninit<[T; N]>>,
+    // NOTE both cursors are in the range `0..2*N` so that a full channel can be told apart from an
+    // empty one. `read` is only modified by the receiver and `write` by the sender
+    read: AtomicUsize,
+    write: AtomicUsize,
+    // the task waiting for the channel to have free space
+    sender: AtomicWaker,
+    // the task waiting for the channel to have messages
+    receiver: AtomicWaker,
+}
+
+unsafe impl<T, const N: usize> Sync for Channel<T, N> where T: Send {}
+
+impl<T, const N: usize> Channel<T, N> {
+    /// Creates a new channel
+    pub const fn new() -> Self {
+        Self {
+            buffer: UnsafeCell::new(MaybeUninit::uninit()),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+            sender: AtomicWaker::new(),
+            receiver: AtomicWaker::new(),
+        }
+    }
+
+    /// Splits the channel in `sender` and `receiver` endpoints
+    pub fn split(&mut self) -> (Sender<'_, T, N>, Receiver<'_, T, N>) {
+        let channel = &*self;
+        (Sender { channel }, Receiver { channel })
+    }
+
+    /// Returns the sending endpoint of a channel stored in a `static` variable
+    ///
+    /// # Safety
+    /// There must be at most one `Sender` per channel, e.g. this method must be called only once
+    pub unsafe fn sender(&self) -> Sender<'_, T, N> {
+        Sender { channel: self }
+    }
+
+    /// Returns the receiving endpoint of a channel stored in a `static` variable
+    ///
+    /// # Safety
+    /// There must be at most one `Receiver` per channel, e.g. this method must be called only once
+    pub unsafe fn receiver(&self) -> Receiver<'_, T, N> {
+        Receiver { channel: self }
+    }
+
+    fn len(read: usize, write: usize) -> usize {
+        if write >= read {
+            write - read
+        } else {
+            write + 2 * N - read
+        }
+    }
+
+    fn next(cursor: usize) -> usize {
+        if cursor + 1 == 2 * N {
+            0
+        } else {
+            cursor + 1
+        }
+    }
+
+    fn slot(&self, cursor: usize) -> *mut T {
+        let i = if cursor >= N { cursor - N } else { cursor };
+        // NOTE(unsafe) `i` is in bounds
+        unsafe { (self.buffer.get() as *mut T).add(i) }
+    }
+}
+
+impl<T, const N: usize> Drop for Channel<T, N> {
+    fn drop(&mut self) {
+        // NOTE(unsafe) `&mut self` means that the endpoints no longer exist
+        while unsafe { self.receiver() }.try_recv().is_some() {}
+    }
+}
+
+/// Sending side of a channel
+pub struct Sender<'c, T, const N: usize> {
+    channel: &'c Channel<T, N>,
+}
+
+impl<T, const N: usize> Sender<'_, T, N> {
+    /// Sends a message into the channel, waiting for free space if the channel is full
+    pub fn send<'s>(&'s mut self, msg: T) -> impl Future<Output = ()> + 's {
+        struct Send<'s, 'c, T, const N: usize> {
+            msg: Option<T>,
+            sender: &'s Sender<'c, T, N>,
+        }
+
+        // NOTE `msg` is never pinned
+        impl<T, const N: usize> Unpin for Send<'_, '_, T, N> {}
+
+        impl<T, const N: usize> Future for Send<'_, '_, T, N> {
+            type Output = ();
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                let this = &mut *self;
+
+                if let Some(msg) = this.msg.take() {
+                    this.sender.channel.sender.register(cx.waker());
+
+                    if let Err(msg) = this.sender.try_send_(msg) {
+                        this.msg = Some(msg);
+                        return Poll::Pending;
+                    }
+                }
+
+                Poll::Ready(())
+            }
+        }
+
+        Send {
+            msg: Some(msg),
+            sender: self,
+        }
+    }
+
+    /// Sends a message into the channel; returns the message back if the channel is full
+    ///
+    /// This can be called from an interrupt handler
+    pub fn try_send(&mut self, msg: T) -> Result<(), T> {
+        self.try_send_(msg)
+    }
+
+    fn try_send_(&self, msg: T) -> Result<(), T> {
+        let channel = self.channel;
+        let write = channel.write.load(Ordering::Relaxed);
+        let read = channel.read.load(Ordering::Acquire);
+
+        if Channel::<T, N>::len(read, write) == N {
+            return Err(msg);
+        }
+
+        // NOTE(unsafe) the receiver won't access this slot until `write` is updated
+        unsafe { channel.slot(write).write(msg) }
+        channel
+            .write
+            .store(Channel::<T, N>::next(write), Ordering::Release);
+
+        // wake up the receiver
+        channel.receiver.wake();
+
+        Ok(())
+    }
+}
+
+/// The receiving side of a channel
+pub struct Receiver<'c, T, const N: usize> {
+    channel: &'c Channel<T, N>,
+}
+
+impl<T, const N: usize> Receiver<'_, T, N> {
+    /// Receives a message from the channel, waiting for one if the channel is empty
+    pub fn recv<'r>(&'r mut self) -> impl Future<Output = T> + 'r {
+        struct Recv<'r, 'c, T, const N: usize> {
+            receiver: &'r Receiver<'c, T, N>,
+        }
+
+        impl<T, const N: usize> Future for Recv<'_, '_, T, N> {
+            type Output = T;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+                self.receiver.channel.receiver.register(cx.waker());
+
+                if let Some(msg) = self.receiver.try_recv_() {
+                    Poll::Ready(msg)
+                } else {
+                    Poll::Pending
+                }
+            }
+        }
+
+        Recv { receiver: self }
+    }
+
+    /// Receives a message from the channel, if there's any
+    ///
+    /// This can be called from an interrupt handler
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.try_recv_()
+    }
+
+    fn try_recv_(&self) -> Option<T> {
+        let channel = self.channel;
+        let read = channel.read.load(Ordering::Relaxed);
+        let write = channel.write.load(Ordering::Acquire);
+
+        if read == write {
+            return None;
+        }
+
+        // NOTE(unsafe) the sender won't access this slot until `read` is updated
+        let msg = unsafe { channel.slot(read).read() };
+        channel
+            .read
+            .store(Channel::<T, N>::next(read), Ordering::Release);
+
+        // wake up the sender
+        channel.sender.wake();
+
+        Some(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+    use std::thread;
+
+    use super::Channel;
+    use crate::testing;
+
+    #[test]
+    fn fifo() {
+        let mut ch = Channel::<i32, 3>::new();
+        let (mut s, mut r) = ch.split();
+
+        // go around the buffer a few times
+        for i in 0..10 {
+            assert_eq!(s.try_send(i), Ok(()));
+            assert_eq!(s.try_send(i + 1), Ok(()));
+            assert_eq!(s.try_send(i + 2), Ok(()));
+            assert_eq!(s.try_send(i + 3), Err(i + 3));
+
+            assert_eq!(r.try_recv(), Some(i));
+            assert_eq!(r.try_recv(), Some(i + 1));
+            assert_eq!(r.try_recv(), Some(i + 2));
+            assert_eq!(r.try_recv(), None);
+        }
+    }
+
+    #[test]
+    fn wake() {
+        static R: AtomicUsize = AtomicUsize::new(0);
+
+        let mut ch = Channel::<i32, 1>::new();
+        let (mut s, mut r) = ch.split();
+
+        {
+            let mut recv = r.recv();
+            let recv = unsafe { Pin::new_unchecked(&mut recv) };
+            assert_eq!(
+                recv.poll(&mut Context::from_waker(&testing::waker(&R))),
+                Poll::Pending
+            );
+        }
+
+        assert_eq!(s.try_send(0), Ok(()));
+        assert_eq!(R.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn threads() {
+        static CHANNEL: Channel<u32, 4> = Channel::new();
+        const COUNT: u32 = 10_000;
+
+        // the sender runs in a different context, like an interrupt handler would
+        let sender = thread::spawn(|| {
+            let mut s = unsafe { CHANNEL.sender() };
+            for i in 0..COUNT {
+                testing::block_on(s.send(i));
+            }
+        });
+
+        let mut r = unsafe { CHANNEL.receiver() };
+        for i in 0..COUNT {
+            assert_eq!(testing::block_on(r.recv()), i);
+        }
+
+        sender.join().unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Test helpers
+
+extern crate std;
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    time::Duration,
+};
+use std::{
+    boxed::Box,
+    sync::Arc,
+    task::Wake,
+    thread::{self, Thread},
+    time::Instant,
+    vec::Vec,
+};
+
+/// A task of the test executor
+pub type Task<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// Runs all the `tasks` to completion
+///
+/// Like `executor::run!` a task is only polled after it has been woken up. Panics if none of the
+/// pending tasks has been woken up, i.e. if they are deadlocked
+pub fn run(mut tasks: Vec<Task<'_>>) {
+    unsafe fn clone(ready: *const ()) -> RawWaker {
+        RawWaker::new(ready, &VTABLE)
+    }
+
+    unsafe fn wake(ready: *const ()) {
+        (*(ready as *const AtomicBool)).store(true, Ordering::Relaxed);
+    }
+
+    unsafe fn drop(_: *const ()) {}
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+    let ready = tasks
+        .iter()
+        .map(|_| AtomicBool::new(true))
+        .collect::<Vec<_>>();
+    let mut done = tasks.iter().map(|_| false).collect::<Vec<_>>();
+
+    while done.contains(&false) {
+        let mut progress = false;
+
+        for (i, task) in tasks.iter_mut().enumerate() {
+            if !done[i] && ready[i].swap(false, Ordering::Relaxed) {
+                progress = true;
+
+                let waker = unsafe {
+                    Waker::from_raw(RawWaker::new(&ready[i] as *const _ as *const (), &VTABLE))
+                };
+                done[i] = task
+                    .as_mut()
+                    .poll(&mut Context::from_waker(&waker))
+                    .is_ready();
+            }
+        }
+
+        assert!(progress, "deadlock: no task has been woken up");
+    }
+}
+
+/// A `Waker` that counts how many times it has been woken up
+pub fn waker(count: &'static AtomicUsize) -> Waker {
+    unsafe fn clone(count: *const ()) -> RawWaker {
+        RawWaker::new(count, &VTABLE)
+    }
+
+    unsafe fn wake(count: *const ()) {
+        (*(count as *const AtomicUsize)).fetch_add(1, Ordering::Relaxed);
+    }
+
+    unsafe fn drop(_: *const ()) {}
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+    unsafe { Waker::from_raw(RawWaker::new(count as *const _ as *const (), &VTABLE)) }
+}
+
+/// Runs `f` to completion on the current thread, which sleeps until `f`'s `Waker` is woken up
+///
+/// Panics if `f` is not woken up within `TIMEOUT`, e.g. if the thread that should wake it up is
+/// deadlocked or never calls `wake`
+pub fn block_on<F>(f: F) -> F::Output
+where
+    F: Future,
+{
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark()
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut f = f;
+    let mut f = unsafe { Pin::new_unchecked(&mut f) };
+    loop {
+        if let Poll::Ready(output) = f.as_mut().poll(&mut cx) {
+            return output;
+        }
+
+        // NOTE `park` may return spuriously; polling again is harmless
+        let start = Instant::now();
+        thread::park_timeout(TIMEOUT);
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "timeout: the future was not woken up within {:?}",
+            TIMEOUT
+        );
+    }
+}
@@ -1,6 +1,16 @@
 //! `!Sync` task synchronization primitives
 
+mod barrier;
+pub mod mpsc;
 mod mutex;
+mod queue;
+mod rwlock;
+mod semaphore;
+mod signal;
 pub mod spsc;
 
+pub use barrier::Barrier;
 pub use mutex::Mutex;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
+pub use signal::Signal;
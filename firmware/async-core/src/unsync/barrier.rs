@@ -0,0 +1,65 @@
+barrier!(crate::critical::nop);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        cell::Cell,
+        future::Future,
+        pin::Pin,
+        sync::atomic::AtomicUsize,
+        task::{Context, Poll},
+    };
+    use std::{boxed::Box, vec};
+
+    use super::Barrier;
+    use crate::{task, testing};
+
+    #[test]
+    fn barrier() {
+        let barrier = Barrier::new(3);
+        let arrived = Cell::new(0);
+
+        let task = || async {
+            arrived.set(arrived.get() + 1);
+            barrier.wait().await;
+            assert_eq!(arrived.get(), 3);
+
+            // the barrier can be reused
+            barrier.wait().await;
+        };
+
+        testing::run(vec![Box::pin(task()), Box::pin(task()), Box::pin(task())]);
+    }
+
+    #[test]
+    fn cancel() {
+        static W: AtomicUsize = AtomicUsize::new(0);
+
+        let barrier = Barrier::new(2);
+        let released = Cell::new(false);
+
+        {
+            let mut wait = barrier.wait();
+            let wait = unsafe { Pin::new_unchecked(&mut wait) };
+            assert_eq!(
+                wait.poll(&mut Context::from_waker(&testing::waker(&W))),
+                Poll::Pending
+            );
+        }
+
+        // the dropped `wait` no longer counts as arrived
+        testing::run(vec![
+            Box::pin(async {
+                barrier.wait().await;
+                released.set(true);
+            }),
+            Box::pin(async {
+                task::r#yield().await;
+                assert!(!released.get());
+                barrier.wait().await;
+            }),
+        ]);
+    }
+}
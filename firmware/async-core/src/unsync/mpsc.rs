@@ -0,0 +1,241 @@
+//! Multiple Producer Single Consumer channels
+
+use core::{
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::queue::Queue;
+use crate::{
+    wait_list::{WaitList, Waiter},
+    waker::AtomicWaker,
+};
+
+/// `async`-aware channel that can hold up to `N` messages
+///
+/// `N` must be greater than 0
+pub struct Channel<T, const N: usize> {
+    queue: Queue<T, N>,
+    // the tasks waiting for the channel to have free space
+    senders: WaitList,
+    // the task waiting for the channel to have messages
+    receiver: AtomicWaker,
+}
+
+impl<T, const N: usize> Channel<T, N> {
+    /// Creates a new channel
+    pub const fn new() -> Self {
+        Self {
+            queue: Queue::new(),
+            senders: WaitList::new(),
+            receiver: AtomicWaker::new(),
+        }
+    }
+
+    /// Splits the channel in `sender` and `receiver` endpoints
+    ///
+    /// The `Sender` can be cloned to give each producer task its own
+    pub fn split(&mut self) -> (Sender<'_, T, N>, Receiver<'_, T, N>) {
+        let channel = self;
+        (Sender { channel }, Receiver { channel })
+    }
+}
+
+/// Sending side of a channel
+pub struct Sender<'c, T, const N: usize> {
+    channel: &'c Channel<T, N>,
+}
+
+impl<T, const N: usize> Clone for Sender<'_, T, N> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel,
+        }
+    }
+}
+
+impl<T, const N: usize> Sender<'_, T, N> {
+    /// Sends a message into the channel, waiting for free space if the channel is full
+    ///
+    /// Senders waiting for free space are served in FIFO order
+    pub fn send<'s>(&'s mut self, msg: T) -> impl Future<Output = ()> + 's {
+        struct Send<'s, 'c, T, const N: usize> {
+            msg: Cell<Option<T>>,
+            sender: &'s Sender<'c, T, N>,
+            waiter: Waiter,
+        }
+
+        impl<T, const N: usize> Future for Send<'_, '_, T, N> {
+            type Output = ();
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                // NOTE(unsafe) `waiter` is never moved out of the pinned future
+                let waiter = unsafe { self.as_ref().map_unchecked(|send| &send.waiter) };
+                let senders = &self.sender.channel.senders;
+
+                if let Some(msg) = self.msg.take() {
+                    if let Err(msg) = self.sender.try_send_(msg) {
+                        self.msg.set(Some(msg));
+                        // NOTE(unsafe) `waiter` is removed from the list when the future is dropped
+                        unsafe { senders.register(waiter, cx.waker()) }
+                        return Poll::Pending;
+                    }
+                }
+
+                senders.remove(&waiter);
+                Poll::Ready(())
+            }
+        }
+
+        impl<T, const N: usize> Drop for Send<'_, '_, T, N> {
+            fn drop(&mut self) {
+                self.sender.channel.senders.cancel(&self.waiter);
+            }
+        }
+
+        Send {
+            msg: Cell::new(Some(msg)),
+            sender: self,
+            waiter: Waiter::new(),
+        }
+    }
+
+    /// Sends a message into the channel; returns the message back if the channel is full
+    pub fn try_send(&mut self, msg: T) -> Result<(), T> {
+        self.try_send_(msg)
+    }
+
+    fn try_send_(&self, msg: T) -> Result<(), T> {
+        self.channel.queue.push(msg)?;
+
+        // wake up the receiver
+        self.channel.receiver.wake();
+
+        Ok(())
+    }
+}
+
+/// The receiving side of a channel
+pub struct Receiver<'c, T, const N: usize> {
+    channel: &'c Channel<T, N>,
+}
+
+impl<T, const N: usize> Receiver<'_, T, N> {
+    /// Receives a message from the channel, waiting for one if the channel is empty
+    pub fn recv<'r>(&'r mut self) -> impl Future<Output = T> + 'r {
+        struct Recv<'r, 'c, T, const N: usize> {
+            receiver: &'r Receiver<'c, T, N>,
+        }
+
+        impl<T, const N: usize> Future for Recv<'_, '_, T, N> {
+            type Output = T;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+                if let Some(msg) = self.receiver.try_recv_() {
+                    Poll::Ready(msg)
+                } else {
+                    self.receiver.channel.receiver.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        }
+
+        Recv { receiver: self }
+    }
+
+    /// Receives a message from the channel, if there's any
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.try_recv_()
+    }
+
+    fn try_recv_(&self) -> Option<T> {
+        let msg = self.channel.queue.pop()?;
+
+        // wake up the sender that has been waiting the longest
+        self.channel.senders.wake_one();
+
+        Some(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+
+    use super::Channel;
+    use crate::testing;
+
+    #[test]
+    fn senders() {
+        static A: AtomicUsize = AtomicUsize::new(0);
+        static B: AtomicUsize = AtomicUsize::new(0);
+        let woken = || (A.load(Ordering::Relaxed), B.load(Ordering::Relaxed));
+
+        let mut ch = Channel::<i32, 1>::new();
+        let (mut a, mut r) = ch.split();
+        let mut b = a.clone();
+
+        assert_eq!(a.try_send(0), Ok(()));
+        assert_eq!(b.try_send(1), Err(1));
+
+        let (wa, wb) = (testing::waker(&A), testing::waker(&B));
+        let (mut ca, mut cb) = (Context::from_waker(&wa), Context::from_waker(&wb));
+        let mut sa = a.send(2);
+        let mut sa = unsafe { Pin::new_unchecked(&mut sa) };
+        let mut sb = b.send(3);
+        let mut sb = unsafe { Pin::new_unchecked(&mut sb) };
+
+        assert_eq!(sa.as_mut().poll(&mut ca), Poll::Pending);
+        assert_eq!(sb.as_mut().poll(&mut cb), Poll::Pending);
+        // waiting senders don't displace each other
+        assert_eq!(woken(), (0, 0));
+
+        // only the first sender is woken up
+        assert_eq!(r.try_recv(), Some(0));
+        assert_eq!(woken(), (1, 0));
+        assert_eq!(sa.poll(&mut ca), Poll::Ready(()));
+
+        assert_eq!(r.try_recv(), Some(2));
+        assert_eq!(woken(), (1, 1));
+        assert_eq!(sb.poll(&mut cb), Poll::Ready(()));
+        assert_eq!(r.try_recv(), Some(3));
+    }
+
+    #[test]
+    fn cancel() {
+        static A: AtomicUsize = AtomicUsize::new(0);
+        static B: AtomicUsize = AtomicUsize::new(0);
+
+        let mut ch = Channel::<i32, 1>::new();
+        let (mut a, mut r) = ch.split();
+        let mut b = a.clone();
+        assert_eq!(a.try_send(0), Ok(()));
+
+        let (wa, wb) = (testing::waker(&A), testing::waker(&B));
+        let mut cb = Context::from_waker(&wb);
+        let mut sb = b.send(2);
+        let mut sb = unsafe { Pin::new_unchecked(&mut sb) };
+
+        {
+            let mut sa = a.send(1);
+            let sa = unsafe { Pin::new_unchecked(&mut sa) };
+            assert_eq!(sa.poll(&mut Context::from_waker(&wa)), Poll::Pending);
+            assert_eq!(sb.as_mut().poll(&mut cb), Poll::Pending);
+
+            assert_eq!(r.try_recv(), Some(0));
+            assert_eq!(A.load(Ordering::Relaxed), 1);
+        }
+
+        // the first sender was dropped so its wake-up was passed to the second one
+        assert_eq!(B.load(Ordering::Relaxed), 1);
+        assert_eq!(sb.poll(&mut cb), Poll::Ready(()));
+        assert_eq!(r.try_recv(), Some(2));
+    }
+}
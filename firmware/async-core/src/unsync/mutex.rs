@@ -6,10 +6,13 @@ use core::{
     task::{Context, Poll},
 };
 
+use crate::waker::AtomicWaker;
+
 /// `async`-aware `Mutex`
 pub struct Mutex<T> {
     data: UnsafeCell<T>,
     locked: Cell<bool>,
+    waker: AtomicWaker,
 }
 
 impl<T> Mutex<T> {
@@ -18,6 +21,7 @@ impl<T> Mutex<T> {
         Self {
             data: UnsafeCell::new(data),
             locked: Cell::new(false),
+            waker: AtomicWaker::new(),
         }
     }
 
@@ -40,10 +44,11 @@ impl<T> Mutex<T> {
         impl<'m, T> Future for Lock<'m, T> {
             type Output = MutexGuard<'m, T>;
 
-            fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Self::Output> {
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
                 if let Some(guard) = self.mutex.try_lock() {
                     Poll::Ready(guard)
                 } else {
+                    self.mutex.waker.register(cx.waker());
                     Poll::Pending
                 }
             }
@@ -77,6 +82,6 @@ impl<T> Drop for MutexGuard<'_, T> {
         self.mutex.locked.set(false);
 
         // wake up a task waiting to claim this mutex
-        asm::sev();
+        self.mutex.waker.wake();
     }
 }
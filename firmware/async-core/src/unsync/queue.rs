@@ -0,0 +1,65 @@
+//! Fixed capacity FIFO queue
+
+use core::{
+    cell::{Cell, UnsafeCell},
+    mem::MaybeUninit,
+};
+
+/// The storage of the channels
+pub(crate) struct Queue<T, const N: usize> {
+    buffer: UnsafeCell<MaybeUninit<[T; N]>>,
+    // index of the oldest element
+    head: Cell<usize>,
+    len: Cell<usize>,
+}
+
+impl<T, const N: usize> Queue<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: UnsafeCell::new(MaybeUninit::uninit()),
+            head: Cell::new(0),
+            len: Cell::new(0),
+        }
+    }
+
+    /// Adds `item` to the back of the queue; returns it back if the queue is full
+    pub fn push(&self, item: T) -> Result<(), T> {
+        let len = self.len.get();
+        if len == N {
+            return Err(item);
+        }
+
+        let i = (self.head.get() + len) % N;
+        // NOTE(unsafe) `i` is in bounds and the slot is not initialized
+        unsafe { self.slot(i).write(item) }
+        self.len.set(len + 1);
+
+        Ok(())
+    }
+
+    /// Removes the item at the front of the queue
+    pub fn pop(&self) -> Option<T> {
+        let len = self.len.get();
+        if len == 0 {
+            return None;
+        }
+
+        let head = self.head.get();
+        // NOTE(unsafe) `head` is in bounds and the slot is initialized
+        let item = unsafe { self.slot(head).read() };
+        self.head.set((head + 1) % N);
+        self.len.set(len - 1);
+
+        Some(item)
+    }
+
+    unsafe fn slot(&self, i: usize) -> *mut T {
+        (self.buffer.get() as *mut T).add(i)
+    }
+}
+
+impl<T, const N: usize> Drop for Queue<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
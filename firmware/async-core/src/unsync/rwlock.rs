@@ -0,0 +1,54 @@
+rwlock!(crate::critical::nop);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::{boxed::Box, vec};
+
+    use super::RwLock;
+    use crate::{task, testing};
+
+    #[test]
+    fn readers() {
+        let lock = RwLock::new(0);
+        let readers = Cell::new(0);
+        let max = Cell::new(0);
+
+        let reader = || async {
+            let _guard = lock.read().await;
+            readers.set(readers.get() + 1);
+            max.set(max.get().max(readers.get()));
+            task::r#yield().await;
+            readers.set(readers.get() - 1);
+        };
+
+        testing::run(vec![Box::pin(reader()), Box::pin(reader())]);
+
+        assert_eq!(max.get(), 2);
+        assert!(lock.try_write().is_some());
+    }
+
+    #[test]
+    fn writer_first() {
+        let lock = RwLock::new(0);
+
+        testing::run(vec![
+            Box::pin(async {
+                let _guard = lock.read().await;
+                task::r#yield().await;
+                task::r#yield().await;
+            }),
+            Box::pin(async {
+                task::r#yield().await;
+                *lock.write().await += 1;
+            }),
+            Box::pin(async {
+                task::r#yield().await;
+                // a writer is waiting so this reader goes after it
+                assert_eq!(*lock.read().await, 1);
+            }),
+        ]);
+    }
+}
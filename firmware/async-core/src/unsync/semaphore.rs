@@ -0,0 +1,84 @@
+semaphore!(crate::critical::nop);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        cell::Cell,
+        future::Future,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+    use std::{boxed::Box, vec};
+
+    use super::Semaphore;
+    use crate::{task, testing};
+
+    #[test]
+    fn semaphore() {
+        let semaphore = Semaphore::new(2);
+        let active = Cell::new(0);
+        let max = Cell::new(0);
+
+        let task = || async {
+            let _permit = semaphore.acquire().await;
+            active.set(active.get() + 1);
+            max.set(max.get().max(active.get()));
+            task::r#yield().await;
+            active.set(active.get() - 1);
+        };
+
+        testing::run(vec![
+            Box::pin(task()),
+            Box::pin(task()),
+            Box::pin(task()),
+            Box::pin(task()),
+        ]);
+
+        assert_eq!(max.get(), 2);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn try_acquire() {
+        let semaphore = Semaphore::new(1);
+
+        let permit = semaphore.try_acquire();
+        assert!(permit.is_some());
+        assert!(semaphore.try_acquire().is_none());
+
+        drop(permit);
+        assert!(semaphore.try_acquire().is_some());
+    }
+
+    #[test]
+    fn fifo() {
+        static A: AtomicUsize = AtomicUsize::new(0);
+        static B: AtomicUsize = AtomicUsize::new(0);
+
+        let semaphore = Semaphore::new(1);
+        let permit = semaphore.try_acquire();
+
+        let (wa, wb) = (testing::waker(&A), testing::waker(&B));
+        let mut a = Box::pin(semaphore.acquire());
+        let mut b = Box::pin(semaphore.acquire());
+        assert!(a.as_mut().poll(&mut Context::from_waker(&wa)).is_pending());
+        assert!(b.as_mut().poll(&mut Context::from_waker(&wb)).is_pending());
+
+        // the released permit goes to the first waiting task; it can't be stolen
+        drop(permit);
+        assert_eq!(A.load(Ordering::Relaxed), 1);
+        assert!(semaphore.try_acquire().is_none());
+        let permit = match a.as_mut().poll(&mut Context::from_waker(&wa)) {
+            Poll::Ready(permit) => permit,
+            Poll::Pending => panic!("the permit was not handed to the first task"),
+        };
+
+        // a task that's handed a permit but is cancelled passes the permit on
+        drop(permit);
+        assert_eq!(B.load(Ordering::Relaxed), 1);
+        drop(b);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+}
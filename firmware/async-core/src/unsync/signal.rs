@@ -0,0 +1,29 @@
+signal!(crate::critical::nop);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{boxed::Box, vec};
+
+    use super::Signal;
+    use crate::testing;
+
+    #[test]
+    fn signal() {
+        let signal = Signal::new();
+
+        testing::run(vec![
+            Box::pin(async {
+                assert_eq!(signal.wait().await, 2);
+            }),
+            Box::pin(async {
+                signal.signal(1);
+                // replaces the value that has not been taken yet
+                signal.signal(2);
+            }),
+        ]);
+
+        assert_eq!(signal.try_take(), None);
+    }
+}
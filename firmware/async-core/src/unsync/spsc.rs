@@ -1,115 +1,113 @@
 //! Single Producer Single Consumer channels
 
 use core::{
-    cell::{Cell, UnsafeCell},
+    cell::Cell,
     future::Future,
-    mem::{ManuallyDrop, MaybeUninit},
     pin::Pin,
-    ptr,
     task::{Context, Poll},
 };
 
-/// `async`-aware channel
-// TODO user configurable capacity
-pub struct Channel<T> {
-    buffer: UnsafeCell<MaybeUninit<T>>,
-    full: Cell<bool>,
+use super::queue::Queue;
+use crate::waker::AtomicWaker;
+
+/// `async`-aware channel that can hold up to `N` messages
+///
+/// `N` must be greater than 0
+pub struct Channel<T, const N: usize> {
+    queue: Queue<T, N>,
+    // the task waiting for the channel to have free space
+    sender: AtomicWaker,
+    // the task waiting for the channel to have messages
+    receiver: AtomicWaker,
 }
 
-impl<T> Channel<T> {
+impl<T, const N: usize> Channel<T, N> {
     /// Creates a new channel
     pub const fn new() -> Self {
         Self {
-            buffer: UnsafeCell::new(MaybeUninit::uninit()),
-            full: Cell::new(false),
+            queue: Queue::new(),
+            sender: AtomicWaker::new(),
+            receiver: AtomicWaker::new(),
         }
     }
 
     /// Splits the channel in `sender` and `receiver` endpoints
-    pub fn split(&mut self) -> (Sender<'_, T>, Receiver<'_, T>) {
+    pub fn split(&mut self) -> (Sender<'_, T, N>, Receiver<'_, T, N>) {
         let channel = self;
         (Sender { channel }, Receiver { channel })
     }
 }
 
 /// Sending side of a channel
-pub struct Sender<'c, T> {
-    channel: &'c Channel<T>,
+pub struct Sender<'c, T, const N: usize> {
+    channel: &'c Channel<T, N>,
 }
 
-impl<T> Sender<'_, T> {
-    /// Sends a message into the channel
+impl<T, const N: usize> Sender<'_, T, N> {
+    /// Sends a message into the channel, waiting for free space if the channel is full
     pub fn send<'s>(&'s mut self, msg: T) -> impl Future<Output = ()> + 's {
-        struct Send<'s, 'c, T> {
-            msg: ManuallyDrop<T>,
-            sender: &'s Sender<'c, T>,
-            sent: Cell<bool>,
+        struct Send<'s, 'c, T, const N: usize> {
+            msg: Cell<Option<T>>,
+            sender: &'s Sender<'c, T, N>,
         }
 
-        impl<T> Future for Send<'_, '_, T> {
+        impl<T, const N: usize> Future for Send<'_, '_, T, N> {
             type Output = ();
 
-            fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
-                if !self.sender.channel.full.get() {
-                    let bufferp = self.sender.channel.buffer.get() as *mut T;
-                    unsafe { bufferp.write(ptr::read(&*self.msg)) }
-
-                    self.sent.set(true);
-                    self.sender.channel.full.set(true);
-
-                    // wake up the receiver
-                    asm::sev();
-
-                    Poll::Ready(())
-                } else {
-                    Poll::Pending
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if let Some(msg) = self.msg.take() {
+                    if let Err(msg) = self.sender.try_send_(msg) {
+                        self.msg.set(Some(msg));
+                        self.sender.channel.sender.register(cx.waker());
+                        return Poll::Pending;
+                    }
                 }
-            }
-        }
 
-        impl<T> Drop for Send<'_, '_, T> {
-            fn drop(&mut self) {
-                if !self.sent.get() {
-                    unsafe { ManuallyDrop::drop(&mut self.msg) }
-                }
+                Poll::Ready(())
             }
         }
 
         Send {
-            msg: ManuallyDrop::new(msg),
+            msg: Cell::new(Some(msg)),
             sender: self,
-            sent: Cell::new(false),
         }
     }
+
+    /// Sends a message into the channel; returns the message back if the channel is full
+    pub fn try_send(&mut self, msg: T) -> Result<(), T> {
+        self.try_send_(msg)
+    }
+
+    fn try_send_(&self, msg: T) -> Result<(), T> {
+        self.channel.queue.push(msg)?;
+
+        // wake up the receiver
+        self.channel.receiver.wake();
+
+        Ok(())
+    }
 }
 
 /// The receiving side of a channel
-pub struct Receiver<'c, T> {
-    channel: &'c Channel<T>,
+pub struct Receiver<'c, T, const N: usize> {
+    channel: &'c Channel<T, N>,
 }
 
-impl<T> Receiver<'_, T> {
-    /// Receives a message from the channel
+impl<T, const N: usize> Receiver<'_, T, N> {
+    /// Receives a message from the channel, waiting for one if the channel is empty
     pub fn recv<'r>(&'r mut self) -> impl Future<Output = T> + 'r {
-        struct Recv<'r, 'c, T> {
-            receiver: &'r Receiver<'c, T>,
+        struct Recv<'r, 'c, T, const N: usize> {
+            receiver: &'r Receiver<'c, T, N>,
         }
 
-        impl<T> Future for Recv<'_, '_, T> {
+        impl<T, const N: usize> Future for Recv<'_, '_, T, N> {
             type Output = T;
 
-            fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<T> {
-                if self.receiver.channel.full.get() {
-                    self.receiver.channel.full.set(false);
-
-                    let bufferp = self.receiver.channel.buffer.get() as *mut T;
-                    let val = unsafe { bufferp.read() };
-
-                    // wake up the sender
-                    asm::sev();
-
-                    Poll::Ready(val)
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+                if let Some(msg) = self.receiver.try_recv_() {
+                    Poll::Ready(msg)
                 } else {
+                    self.receiver.channel.receiver.register(cx.waker());
                     Poll::Pending
                 }
             }
@@ -117,4 +115,105 @@ impl<T> Receiver<'_, T> {
 
         Recv { receiver: self }
     }
+
+    /// Receives a message from the channel, if there's any
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.try_recv_()
+    }
+
+    fn try_recv_(&self) -> Option<T> {
+        let msg = self.channel.queue.pop()?;
+
+        // wake up the sender
+        self.channel.sender.wake();
+
+        Some(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+
+    use super::Channel;
+    use crate::testing;
+
+    #[test]
+    fn fifo() {
+        let mut ch = Channel::<i32, 2>::new();
+        let (mut s, mut r) = ch.split();
+
+        assert_eq!(s.try_send(0), Ok(()));
+        assert_eq!(s.try_send(1), Ok(()));
+        assert_eq!(s.try_send(2), Err(2));
+
+        assert_eq!(r.try_recv(), Some(0));
+        assert_eq!(s.try_send(3), Ok(()));
+        assert_eq!(r.try_recv(), Some(1));
+        assert_eq!(r.try_recv(), Some(3));
+        assert_eq!(r.try_recv(), None);
+    }
+
+    #[test]
+    fn wake() {
+        static R: AtomicUsize = AtomicUsize::new(0);
+        static S: AtomicUsize = AtomicUsize::new(0);
+
+        let mut ch = Channel::<i32, 1>::new();
+        let (mut s, mut r) = ch.split();
+
+        {
+            let mut recv = r.recv();
+            let recv = unsafe { Pin::new_unchecked(&mut recv) };
+            assert_eq!(
+                recv.poll(&mut Context::from_waker(&testing::waker(&R))),
+                Poll::Pending
+            );
+        }
+
+        assert_eq!(s.try_send(0), Ok(()));
+        assert_eq!(R.load(Ordering::Relaxed), 1);
+
+        {
+            let mut send = s.send(1);
+            let mut send = unsafe { Pin::new_unchecked(&mut send) };
+            let waker = testing::waker(&S);
+            let mut cx = Context::from_waker(&waker);
+            assert_eq!(send.as_mut().poll(&mut cx), Poll::Pending);
+
+            assert_eq!(r.try_recv(), Some(0));
+            assert_eq!(S.load(Ordering::Relaxed), 1);
+            assert_eq!(send.poll(&mut cx), Poll::Ready(()));
+        }
+
+        assert_eq!(r.try_recv(), Some(1));
+    }
+
+    #[test]
+    fn drop() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Msg;
+
+        impl Drop for Msg {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        {
+            let mut ch = Channel::<Msg, 3>::new();
+            let (mut s, _) = ch.split();
+            assert!(s.try_send(Msg).is_ok());
+            assert!(s.try_send(Msg).is_ok());
+        }
+
+        // messages left in the channel are dropped with it
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
 }
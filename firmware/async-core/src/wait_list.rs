@@ -0,0 +1,155 @@
+//! Tasks waiting on an event
+
+use core::{cell::Cell, marker::PhantomPinned, pin::Pin, ptr, task::Waker};
+
+/// FIFO list of tasks waiting on the same event
+///
+/// Unlike `AtomicWaker` this can hold any number of `Waker`s. The list is intrusive: its nodes
+/// (`Waiter`) live in the futures that are waiting so no allocation is needed
+///
+/// This is not `Sync`; the interrupt-safe primitives only access it from within critical sections
+pub(crate) struct WaitList {
+    head: Cell<*const Waiter>,
+    tail: Cell<*const Waiter>,
+}
+
+/// A node in a `WaitList`
+pub(crate) struct Waiter {
+    waker: Cell<Option<Waker>>,
+    prev: Cell<*const Waiter>,
+    next: Cell<*const Waiter>,
+    linked: Cell<bool>,
+    // woken up by `wake_one`
+    woken: Cell<bool>,
+    _pinned: PhantomPinned,
+}
+
+impl Waiter {
+    pub const fn new() -> Self {
+        Self {
+            waker: Cell::new(None),
+            prev: Cell::new(ptr::null()),
+            next: Cell::new(ptr::null()),
+            linked: Cell::new(false),
+            woken: Cell::new(false),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Whether `wake_one` removed this waiter from the list and `remove` hasn't been called since
+    pub fn is_woken(&self) -> bool {
+        self.woken.get()
+    }
+}
+
+impl WaitList {
+    pub const fn new() -> Self {
+        Self {
+            head: Cell::new(ptr::null()),
+            tail: Cell::new(ptr::null()),
+        }
+    }
+
+    /// Stores `waker` in `waiter` and adds `waiter` to the back of the list, if it's not already in
+    /// it
+    ///
+    /// # Safety
+    /// `cancel` must be called on `waiter` before it's deallocated
+    pub unsafe fn register(&self, waiter: Pin<&Waiter>, waker: &Waker) {
+        let waker = match waiter.waker.take() {
+            Some(old) if old.will_wake(waker) => old,
+            _ => waker.clone(),
+        };
+        waiter.waker.set(Some(waker));
+        waiter.woken.set(false);
+
+        if !waiter.linked.get() {
+            let node = &*waiter as *const Waiter;
+            let tail = self.tail.get();
+
+            waiter.prev.set(tail);
+            waiter.next.set(ptr::null());
+            if tail.is_null() {
+                self.head.set(node);
+            } else {
+                (*tail).next.set(node);
+            }
+            self.tail.set(node);
+            waiter.linked.set(true);
+        }
+    }
+
+    /// Returns `true` if no task is waiting
+    pub fn is_empty(&self) -> bool {
+        self.head.get().is_null()
+    }
+
+    /// Wakes up all the tasks in the list
+    pub fn wake_all(&self) {
+        while !self.is_empty() {
+            self.wake_one();
+        }
+    }
+
+    /// Wakes up the task at the front of the list, if any; returns `false` if the list was empty
+    pub fn wake_one(&self) -> bool {
+        let head = self.head.get();
+
+        if head.is_null() {
+            return false;
+        }
+
+        // NOTE(unsafe) nodes are removed from the list before they are deallocated
+        let waiter = unsafe { &*head };
+        self.unlink(waiter);
+        waiter.woken.set(true);
+
+        if let Some(waker) = waiter.waker.take() {
+            waker.wake();
+        }
+
+        true
+    }
+
+    /// Removes `waiter` from the list because the task is no longer waiting
+    pub fn remove(&self, waiter: &Waiter) {
+        if waiter.linked.get() {
+            self.unlink(waiter);
+        }
+        waiter.woken.set(false);
+    }
+
+    /// Removes `waiter` from the list because its future is being dropped
+    ///
+    /// If `waiter` had been woken up the wake-up is passed to the next task in the list so it's
+    /// not lost
+    pub fn cancel(&self, waiter: &Waiter) {
+        if waiter.linked.get() {
+            self.unlink(waiter);
+        } else if waiter.woken.replace(false) {
+            self.wake_one();
+        }
+    }
+
+    fn unlink(&self, waiter: &Waiter) {
+        let prev = waiter.prev.get();
+        let next = waiter.next.get();
+
+        // NOTE(unsafe) nodes are removed from the list before they are deallocated
+        unsafe {
+            if prev.is_null() {
+                self.head.set(next);
+            } else {
+                (*prev).next.set(next);
+            }
+
+            if next.is_null() {
+                self.tail.set(prev);
+            } else {
+                (*next).prev.set(prev);
+            }
+        }
+
+        waiter.linked.set(false);
+    }
+}
@@ -0,0 +1,144 @@
+//! Waker storage
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU8, Ordering},
+    task::Waker,
+};
+
+const WAITING: u8 = 0;
+const REGISTERING: u8 = 1;
+const WAKING: u8 = 2;
+
+/// Storage for the `Waker` of a task waiting on some event
+///
+/// The event source (e.g. an interrupt handler) calls `wake` to wake up the task. `register` and
+/// `wake` can preempt each other (e.g. a `wake` from an interrupt handler can interrupt a
+/// `register` in thread mode) without losing a wake-up.
+///
+/// Only one `Waker` is stored. When a different task registers its `Waker` the previous one is
+/// woken up (it will register itself again when it's polled) so several tasks can wait on the
+/// same event at the cost of some spurious polls
+pub struct AtomicWaker {
+    state: AtomicU8,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+unsafe impl Sync for AtomicWaker {}
+
+impl Default for AtomicWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtomicWaker {
+    /// Creates an empty `AtomicWaker`
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// Registers `waker` to be woken up by the next call to `wake`
+    ///
+    /// This must be called *before* checking whether the event has occurred
+    pub fn register(&self, waker: &Waker) {
+        match self.state.compare_exchange(
+            WAITING,
+            REGISTERING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                // NOTE(unsafe) the `REGISTERING` state grants us exclusive access to `waker`
+                let slot = unsafe { &mut *self.waker.get() };
+                let displaced = match slot {
+                    Some(old) if old.will_wake(waker) => None,
+                    _ => slot.replace(waker.clone()),
+                };
+
+                if self
+                    .state
+                    .compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    // `wake` was called while we were registering the waker; it couldn't take the
+                    // waker so we do its job
+                    let waker = slot.take();
+                    self.state.store(WAITING, Ordering::Release);
+
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+
+                if let Some(displaced) = displaced {
+                    displaced.wake();
+                }
+            }
+
+            // we preempted a `wake` (or another `register`); poll the task again
+            Err(_) => waker.wake_by_ref(),
+        }
+    }
+
+    /// Wakes up the registered task, if any
+    pub fn wake(&self) {
+        if self.state.fetch_or(WAKING, Ordering::AcqRel) == WAITING {
+            // NOTE(unsafe) the `WAKING` state grants us exclusive access to `waker`
+            let waker = unsafe { (*self.waker.get()).take() };
+            self.state.fetch_and(!WAKING, Ordering::Release);
+
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+
+        // otherwise `register` is in progress (it'll wake the task) or we preempted another `wake`
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::AtomicWaker;
+    use crate::testing::waker;
+
+    #[test]
+    fn wake() {
+        static A: AtomicUsize = AtomicUsize::new(0);
+
+        let aw = AtomicWaker::new();
+        aw.wake(); // no-op
+        aw.register(&waker(&A));
+        aw.register(&waker(&A)); // same task: not a spurious wake-up
+        assert_eq!(A.load(Ordering::Relaxed), 0);
+
+        aw.wake();
+        assert_eq!(A.load(Ordering::Relaxed), 1);
+
+        // the waker is consumed
+        aw.wake();
+        assert_eq!(A.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn displace() {
+        static A: AtomicUsize = AtomicUsize::new(0);
+        static B: AtomicUsize = AtomicUsize::new(0);
+
+        let aw = AtomicWaker::new();
+        aw.register(&waker(&A));
+        aw.register(&waker(&B));
+        // `A` is woken up so it can register itself again
+        assert_eq!(A.load(Ordering::Relaxed), 1);
+        assert_eq!(B.load(Ordering::Relaxed), 0);
+
+        aw.wake();
+        assert_eq!(A.load(Ordering::Relaxed), 1);
+        assert_eq!(B.load(Ordering::Relaxed), 1);
+    }
+}
@@ -5,6 +5,8 @@
 //! - No heap allocations
 //! - No trait objects
 //! - Tasks do NOT need to satisfy the `: 'static` bound
+//! - Only the tasks that have been woken up are polled
+//! - Tasks can run at different priorities: each software interrupt can run its own executor
 
 #![deny(missing_docs)]
 #![deny(rust_2018_idioms)]
@@ -12,10 +14,15 @@
 #![no_std]
 
 use core::{
+    cell::UnsafeCell,
     future::Future,
-    task::{RawWaker, RawWakerVTable, Waker},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, RawWaker, RawWakerVTable, Waker},
 };
 
+use cm::{nvic::IPR0, NVIC};
+
 use proc_macro_hack::proc_macro_hack;
 
 /// Implementation detail
@@ -25,6 +32,29 @@ pub use asm::wfe;
 /// Runs the given tasks concurrently
 ///
 /// This macro is divergent (`-> !`); the tasks should also be divergent
+///
+/// Each task has a ready bit that its `Waker` sets; the executor only polls tasks whose bit is set
+/// and sleeps (`WFE`) when none is. Waking a task also sends an event (`SEV`) so `Waker::wake` can
+/// be called from an interrupt handler
+///
+/// # Priorities
+///
+/// Tasks listed first run in thread mode. Tasks can also run from a software interrupt handler
+/// (`SWI0_EGU0` to `SWI5_EGU5`) at a priority between `1` (lowest) and `7` (highest); these
+/// preempt the thread mode tasks and the tasks that run at a lower priority. The HAL's interrupt
+/// handlers preempt all tasks.
+///
+/// ``` ignore
+/// executor::run!(
+///     a, b;
+///     SWI0_EGU0 (priority = 1): c;
+///     SWI1_EGU1 (priority = 2): d, e
+/// )
+/// ```
+///
+/// Thread mode tasks are futures. Interrupt tasks are closures that return the future; the closure
+/// is called from the interrupt handler so it must be `Send` but the future it returns doesn't need
+/// to be. Waking an interrupt task pends its interrupt
 #[proc_macro_hack(support_nested)]
 pub use executor_macros::run;
 
@@ -40,19 +70,194 @@ where
 
 /// Implementation detail
 #[doc(hidden)]
+pub struct Ready {
+    ready: AtomicBool,
+    // the interrupt whose handler polls this task; `None` if the task runs in thread mode
+    interrupt: Option<u8>,
+}
+
+impl Ready {
+    /// Implementation detail
+    #[doc(hidden)]
+    // NOTE all tasks are polled at least once
+    pub const fn new() -> Self {
+        Self {
+            ready: AtomicBool::new(true),
+            interrupt: None,
+        }
+    }
+
+    /// Implementation detail
+    #[doc(hidden)]
+    pub const fn interrupt(nr: u8) -> Self {
+        Self {
+            ready: AtomicBool::new(true),
+            interrupt: Some(nr),
+        }
+    }
+
+    /// Implementation detail
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn take(&self) -> bool {
+        // NOTE the bit is cleared *before* the task is polled so a `wake` that occurs while the task
+        // is being polled is not lost
+        self.ready.swap(false, Ordering::Acquire)
+    }
+}
+
+/// Implementation detail
+///
+/// # Safety
+///
+/// `ready` must never be deallocated
+#[doc(hidden)]
 #[inline(always)]
-pub fn waker() -> Waker {
-    unsafe fn clone(_: *const ()) -> RawWaker {
-        loop {
-            continue;
+pub unsafe fn waker(ready: &Ready) -> Waker {
+    unsafe fn clone(ready: *const ()) -> RawWaker {
+        RawWaker::new(ready, &VTABLE)
+    }
+
+    unsafe fn wake(ready: *const ()) {
+        let ready = &*(ready as *const Ready);
+        ready.ready.store(true, Ordering::Release);
+
+        if let Some(nr) = ready.interrupt {
+            // the interrupt handler will poll the task
+            pend(nr)
+        } else {
+            // wake up the executor if it's sleeping in `WFE`
+            asm::sev();
         }
     }
 
-    unsafe fn wake(_: *const ()) {}
-    unsafe fn wake_by_ref(_: *const ()) {}
     unsafe fn drop(_: *const ()) {}
 
-    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+    Waker::from_raw(RawWaker::new(ready as *const Ready as *const (), &VTABLE))
+}
+
+/// Implementation detail
+#[doc(hidden)]
+pub struct Task<C, F> {
+    constructor: Option<C>,
+    future: Option<F>,
+}
+
+impl<C, F> Task<C, F>
+where
+    C: FnOnce() -> F,
+    F: Future,
+{
+    /// Implementation detail
+    #[doc(hidden)]
+    // NOTE(C: Send) the future will be created and polled from the interrupt handler
+    pub fn new(constructor: C) -> Self
+    where
+        C: Send,
+    {
+        Self {
+            constructor: Some(constructor),
+            future: None,
+        }
+    }
+
+    /// Implementation detail
+    ///
+    /// # Safety
+    ///
+    /// `self` must not be moved after the first call to this method
+    #[doc(hidden)]
+    #[inline(always)]
+    pub unsafe fn poll(&mut self, cx: &mut Context<'_>) {
+        if let Some(constructor) = self.constructor.take() {
+            self.future = Some(constructor());
+        }
+
+        if let Some(future) = self.future.as_mut() {
+            if Pin::new_unchecked(future).poll(cx).is_ready() {
+                // drop the future so it's never polled beyond completion
+                self.future = None;
+            }
+        }
+    }
+}
+
+// a type-erased `&mut impl FnMut()`: (`call::<F>`, `&mut F`)
+type Poll = (unsafe fn(*mut ()), *mut ());
+
+/// Implementation detail
+#[doc(hidden)]
+pub struct Handler {
+    poll: UnsafeCell<Option<Poll>>,
+}
+
+unsafe impl Sync for Handler {}
+
+impl Handler {
+    /// Implementation detail
+    #[doc(hidden)]
+    pub const fn new() -> Self {
+        Self {
+            poll: UnsafeCell::new(None),
+        }
+    }
+
+    /// Implementation detail
+    ///
+    /// # Safety
+    ///
+    /// `poll` must never be deallocated or accessed by the caller again. This must be called
+    /// before the interrupt is unmasked
+    #[doc(hidden)]
+    pub unsafe fn install<F>(&self, poll: &mut F)
+    where
+        F: FnMut(),
+    {
+        unsafe fn call<F>(poll: *mut ())
+        where
+            F: FnMut(),
+        {
+            (*(poll as *mut F))()
+        }
+
+        *self.poll.get() = Some((call::<F>, poll as *mut F as *mut ()));
+    }
+
+    /// Implementation detail
+    ///
+    /// # Safety
+    ///
+    /// Must only be called from the interrupt handler
+    #[doc(hidden)]
+    #[inline(always)]
+    pub unsafe fn run(&self) {
+        if let Some((call, poll)) = *self.poll.get() {
+            call(poll)
+        }
+    }
+}
+
+/// Implementation detail
+///
+/// # Safety
+///
+/// The interrupt handler must have been installed
+#[doc(hidden)]
+pub unsafe fn enable(nr: u8, hw_priority: u8) {
+    // NOTE the priority registers are byte accessible
+    (IPR0::address() as *mut u8)
+        .add(usize::from(nr))
+        .write_volatile(hw_priority);
+    // NOTE(borrow_unchecked) single-instruction write
+    NVIC::borrow_unchecked(|nvic| nvic.ISER0.write(1 << nr));
+
+    // poll the tasks for the first time
+    pend(nr)
+}
 
-    unsafe { Waker::from_raw(RawWaker::new(&(), &VTABLE)) }
+fn pend(nr: u8) {
+    // NOTE(borrow_unchecked) single-instruction write
+    NVIC::borrow_unchecked(|nvic| nvic.ISPR0.write(1 << nr));
 }
@@ -39,6 +39,14 @@ SECTIONS
     _einit = .;
   } > FLASH
 
+  .tests :
+  {
+    _stests = .;
+    KEEP(*(SORT(.tests.*)));
+    . = ALIGN(4);
+    _etests = .;
+  } > FLASH
+
   .uninit __ram_start__ (NOLOAD) :
   {
     *(.uninit.*);
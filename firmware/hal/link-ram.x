@@ -43,7 +43,15 @@ SECTIONS
     _einit = .;
   } > RAM
 
-  .rodata ADDR(.init) + SIZEOF(.init) :
+  .tests ADDR(.init) + SIZEOF(.init) :
+  {
+    _stests = .;
+    KEEP(*(SORT(.tests.*)));
+    . = ALIGN(4);
+    _etests = .;
+  } > RAM
+
+  .rodata ADDR(.tests) + SIZEOF(.tests) :
   {
     *(.rodata .rodata.*);
     . = ALIGN(4);
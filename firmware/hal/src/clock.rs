@@ -1,5 +1,6 @@
 use core::sync::atomic::AtomicBool;
 
+use async_core::waker::AtomicWaker;
 use pac::CLOCK;
 
 #[tasks::declare]
@@ -10,7 +11,7 @@ mod task {
 
     use crate::Interrupt0;
 
-    use super::{Event, STARTED};
+    use super::{Event, STARTED, WAKER};
 
     fn init() {
         CLOCK::borrow_unchecked(|clock| {
@@ -30,6 +31,7 @@ mod task {
             Event::HFCLKSTARTED => {
                 semidap::info!("HFXO is stable");
                 STARTED.store(true, Ordering::Relaxed);
+                WAKER.wake();
             }
         }
 
@@ -38,12 +40,13 @@ mod task {
 }
 
 static STARTED: AtomicBool = AtomicBool::new(false);
+static WAKER: AtomicWaker = AtomicWaker::new();
 
 #[cfg(feature = "radio")]
 pub async fn has_stabilized() {
     use core::{sync::atomic::Ordering, task::Poll};
 
-    crate::poll_fn(|| {
+    crate::poll_fn(&WAKER, || {
         if STARTED.load(Ordering::Relaxed) {
             Poll::Ready(())
         } else {
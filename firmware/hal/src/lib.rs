@@ -12,7 +12,8 @@ use core::{
     task::{Context, Poll},
 };
 
-use cm::{DWT, NVIC};
+use async_core::waker::AtomicWaker;
+use cm::{nvic::IPR0, DWT, NVIC};
 use pac::FICR;
 
 #[cfg(any(feature = "radio", feature = "usb"))]
@@ -23,7 +24,7 @@ mod atomic;
 mod clock;
 mod errata;
 pub mod led;
-mod mem;
+pub mod mem;
 pub mod p0;
 #[cfg(feature = "radio")]
 pub mod radio;
@@ -92,27 +93,35 @@ fn dma_end() {
     sync::atomic::compiler_fence(Ordering::Acquire)
 }
 
+/// Polls `f` until it returns `Ready`
+///
+/// `waker` must be woken up (usually from an interrupt handler) when `f` may make progress
 #[allow(dead_code)]
-async fn poll_fn<T, F>(f: F) -> T
+async fn poll_fn<T, F>(waker: &AtomicWaker, f: F) -> T
 where
     F: FnMut() -> Poll<T> + Unpin,
 {
-    struct PollFn<F> {
+    struct PollFn<'w, F> {
         f: F,
+        waker: &'w AtomicWaker,
     }
 
-    impl<T, F> Future for PollFn<F>
+    impl<T, F> Future for PollFn<'_, F>
     where
         F: FnMut() -> Poll<T> + Unpin,
     {
         type Output = T;
 
-        fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<T> {
-            (self.get_mut().f)()
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+            let this = self.get_mut();
+            // NOTE register *before* `f` checks the state so an interrupt that fires in between
+            // is not missed
+            this.waker.register(cx.waker());
+            (this.f)()
         }
     }
 
-    PollFn { f }.await
+    PollFn { f, waker }.await
 }
 
 /// # Safety
@@ -195,6 +204,48 @@ unsafe fn unmask1(interrupts: &[Interrupt1]) {
     }
 }
 
+/// Aborts the program if the handler of interrupt `nr` can't preempt the current context
+///
+/// The cancellation (drop) code of some futures busy waits on state that one of the HAL's interrupt
+/// handlers updates; this would deadlock if the future was dropped while the interrupt is masked or
+/// from a context whose priority is not lower than the handler's
+fn assert_preemptible(nr: u8) {
+    const SCB_ICSR: *const u32 = 0xE000_ED04 as *const u32;
+    // priorities of the system exceptions 4..16
+    const SCB_SHPR1: *const u8 = 0xE000_ED18 as *const u8;
+
+    // NOTE(borrow_unchecked) single-instruction reads
+    let enabled = NVIC::borrow_unchecked(|nvic| {
+        if nr < 32 {
+            nvic.ISER0.read() & (1 << nr)
+        } else {
+            nvic.ISER1.read() & (1 << (nr - 32))
+        }
+    }) != 0;
+
+    // NOTE(unsafe) reads with no side effects; the priority registers are byte accessible
+    let preemptible = unsafe {
+        let ipr = |nr: usize| (IPR0::address() as *const u8).add(nr).read_volatile();
+        let priority = ipr(usize::from(nr));
+
+        // lower hardware value = more urgent
+        match SCB_ICSR.read_volatile() as usize & 0x1ff {
+            // thread mode
+            0 => true,
+            // Reset, NMI and HardFault have fixed priorities higher than any interrupt's
+            1..=3 => false,
+            vectactive @ 4..=15 => priority < SCB_SHPR1.add(vectactive - 4).read_volatile(),
+            vectactive => priority < ipr(vectactive - 16),
+        }
+    };
+
+    semidap::assert!(
+        enabled && !asm::primask() && preemptible,
+        "interrupt {} can't preempt the context that dropped the future",
+        nr
+    );
+}
+
 /// Interrupts 0..32
 #[allow(missing_docs)]
 #[allow(non_camel_case_types)]
@@ -1,4 +1,47 @@
-use pool::pool;
+//! Memory pools
+//!
+//! Memory blocks come in three size classes: `S`, `M` and `L`
+//!
+//! The pools start empty. The radio and USB drivers give the `L` and `M` pools the blocks they
+//! need; the application gives the pools any extra blocks it needs with `manage`
+//!
+//! ``` ignore
+//! #[tasks::declare]
+//! mod task {
+//!     use core::mem::MaybeUninit;
+//!
+//!     use hal::mem::{Node, S};
+//!
+//!     fn init() {
+//!         #[uninit(unsafe)]
+//!         static mut BLOCKS: [MaybeUninit<Node<[u8; S::SIZE]>>; 2] =
+//!             [MaybeUninit::uninit(), MaybeUninit::uninit()];
+//!
+//!         for block in BLOCKS {
+//!             S::manage(block)
+//!         }
+//!     }
+//! }
+//! ```
+
+use core::ops;
+
+pub use pool::Node;
+use pool::{pool, Box};
+
+pool!(
+    /// Small memory blocks
+    pub S: [u8; 32]
+);
+
+// for HID packets we'll use these blocks as:
+// { padding: 4B, data: 64B }
+//
+// the padding is needed because USB.data must be 4-byte aligned
+pool!(
+    /// Medium memory blocks
+    pub M: [u8; 68]
+);
 
 // for radio packets we'll use these blocks as:
 // { padding: 3B, len: 1B, data: 127B, LQI: 1B }
@@ -9,4 +52,136 @@ use pool::pool;
 // this let's us convert between them with zero copies
 //
 // the padding is needed because USB.data must be 4-byte aligned
-pool!(pub P: [u8; 132]);
+pool!(
+    /// Large memory blocks
+    pub L: [u8; 132]
+);
+
+/// A memory block from one of the size classes
+pub enum Block {
+    /// Small memory block
+    S(Box<S>),
+    /// Medium memory block
+    M(Box<M>),
+    /// Large memory block
+    L(Box<L>),
+}
+
+impl ops::Deref for Block {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Block::S(b) => &**b,
+            Block::M(b) => &**b,
+            Block::L(b) => &**b,
+        }
+    }
+}
+
+impl ops::DerefMut for Block {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Block::S(b) => &mut **b,
+            Block::M(b) => &mut **b,
+            Block::L(b) => &mut **b,
+        }
+    }
+}
+
+/// Tries to acquire a memory block of at least `size` bytes
+///
+/// The block comes from the smallest size class that fits `size` bytes; if that pool is empty the
+/// next size class is tried
+pub fn try_alloc(size: usize) -> Option<Block> {
+    if size <= S::SIZE {
+        if let Some(b) = S::try_alloc() {
+            return Some(Block::S(b));
+        }
+    }
+
+    if size <= M::SIZE {
+        if let Some(b) = M::try_alloc() {
+            return Some(Block::M(b));
+        }
+    }
+
+    if size <= L::SIZE {
+        if let Some(b) = L::try_alloc() {
+            return Some(Block::L(b));
+        }
+    }
+
+    None
+}
+
+/// Acquires a memory block of at least `size` bytes
+///
+/// If no block is available right away this waits for a block of the smallest size class that
+/// fits `size` bytes
+pub async fn alloc(size: usize) -> Block {
+    if let Some(b) = try_alloc(size) {
+        b
+    } else if size <= S::SIZE {
+        Block::S(S::alloc().await)
+    } else if size <= M::SIZE {
+        Block::M(M::alloc().await)
+    } else if size <= L::SIZE {
+        Block::L(L::alloc().await)
+    } else {
+        semidap::panic!("no size class can hold {} bytes", size as u32)
+    }
+}
+
+/// Logs the usage statistics of the memory pools
+pub fn log_stats() {
+    let s = S::stats();
+    semidap::info!(
+        "S: {} allocated, {} high-water mark, {} failed",
+        s.allocated() as u32,
+        s.high_water_mark() as u32,
+        s.failed() as u32
+    );
+
+    let m = M::stats();
+    semidap::info!(
+        "M: {} allocated, {} high-water mark, {} failed",
+        m.allocated() as u32,
+        m.high_water_mark() as u32,
+        m.failed() as u32
+    );
+
+    let l = L::stats();
+    semidap::info!(
+        "L: {} allocated, {} high-water mark, {} failed",
+        l.allocated() as u32,
+        l.high_water_mark() as u32,
+        l.failed() as u32
+    );
+}
+
+/// Reports the memory blocks that are still allocated
+///
+/// Call this at a point where the program is not expected to hold any `Box` (e.g. after all its
+/// tasks have finished their work). This is a no-op in release builds
+pub fn check_leaks() {
+    if cfg!(debug_assertions) {
+        let (s, m, l) = (
+            S::stats().allocated(),
+            M::stats().allocated(),
+            L::stats().allocated(),
+        );
+
+        if s != 0 {
+            semidap::error!("S: {} `Box`es still alive", s as u32);
+        }
+
+        if m != 0 {
+            semidap::error!("M: {} `Box`es still alive", m as u32);
+        }
+
+        if l != 0 {
+            semidap::error!("L: {} `Box`es still alive", l as u32);
+        }
+    }
+}
@@ -8,11 +8,12 @@ use core::{
     task::Poll,
 };
 
+use async_core::waker::AtomicWaker;
 use binfmt::derive::binDebug;
 use pac::RADIO;
 use pool::Box;
 
-use crate::{atomic::Atomic, clock, mem::P, Interrupt0, NotSendOrSync};
+use crate::{atomic::Atomic, clock, mem::L, util::OnDrop, Interrupt0, NotSendOrSync};
 
 /// IEEE 802.15.4 channel
 #[derive(Clone, Copy, PartialEq)]
@@ -109,21 +110,23 @@ mod task {
     use pac::RADIO;
     use pool::Node;
 
-    use crate::{mem::P, Interrupt0};
+    use crate::{mem::L, Interrupt0};
 
-    use super::{Event, Lock, Packet, RxState, TxState, LOCK, RX_STATE, TX_STATE};
+    use super::{
+        Event, Lock, Packet, RxState, TxState, LOCK, RX_STATE, RX_WAKER, TX_STATE, TX_WAKER,
+    };
 
     // NOTE(unsafe) all interrupts are still globally masked (`CPSID I`)
     fn init() {
         #[uninit(unsafe)]
-        static mut PACKETS: [MaybeUninit<Node<[u8; P::SIZE as usize]>>; 3] = [
+        static mut PACKETS: [MaybeUninit<Node<[u8; L::SIZE]>>; 3] = [
             MaybeUninit::uninit(),
             MaybeUninit::uninit(),
             MaybeUninit::uninit(),
         ];
 
         for packet in PACKETS {
-            P::manage(packet)
+            L::manage(packet)
         }
 
         // reserve peripherals for HAL use
@@ -261,6 +264,10 @@ mod task {
             },
         }
 
+        // any of the above state transitions may unblock either half of the radio
+        RX_WAKER.wake();
+        TX_WAKER.wake();
+
         None
     }
 }
@@ -384,6 +391,10 @@ derive!(TxState);
 static LOCK: Atomic<Lock> = Atomic::new();
 static RX_STATE: Atomic<RxState> = Atomic::new();
 static TX_STATE: Atomic<TxState> = Atomic::new();
+// the task blocked on `Rx.read`
+static RX_WAKER: AtomicWaker = AtomicWaker::new();
+// the task blocked on `Tx.write` or `Tx.flush`
+static TX_WAKER: AtomicWaker = AtomicWaker::new();
 
 /// IEEE 802.15.4 radio (receiver half)
 pub struct Rx {
@@ -399,13 +410,57 @@ impl Rx {
     }
 
     /// Reads one radio packet
+    ///
+    /// Dropping this future before it completes may block until the radio is done with `packet`;
+    /// that requires the `RADIO` interrupt handler to be able to preempt the context that drops the
+    /// future
     pub async fn read(&mut self, packet: &mut Packet) -> Result<u16, u16> {
         clock::has_stabilized().await;
 
+        // NOTE(cancellation) if this future is dropped while waiting for a frame we stop the
+        // receiver so the DMA won't write to `packet` after it has been handed back to the caller.
+        // A frame that's already being received is not aborted (the `END` handler expects the
+        // radio to still be locked) so in that case we block until its reception is over
+        let guard = OnDrop::new(|| {
+            crate::assert_preemptible(Interrupt0::RADIO as u8);
+
+            loop {
+                // NOTE(unsafe) not nested; this never runs from an `atomic0` section
+                let done = unsafe {
+                    crate::atomic0(Interrupt0::RADIO, || match RX_STATE.load() {
+                        RxState::Started => {
+                            if LOCK.load() == Lock::Free {
+                                TASKS_STOP();
+                                while STATE() == State::Rx {}
+                                // a frame may have been detected after the interrupt was masked
+                                RADIO::borrow_unchecked(|radio| radio.EVENTS_FRAMESTART.zero());
+                                RX_STATE.store(RxState::Idle);
+                                true
+                            } else {
+                                false
+                            }
+                        }
+
+                        RxState::Interrupted | RxState::Done => {
+                            RX_STATE.store(RxState::Idle);
+                            true
+                        }
+
+                        RxState::Idle => true,
+                    })
+                };
+
+                if done {
+                    crate::dma_end();
+                    break;
+                }
+            }
+        });
+
         let mut crcres = false;
         let mut retry = true;
         while retry {
-            crate::poll_fn(|| {
+            crate::poll_fn(&RX_WAKER, || {
                 match LOCK.load() {
                     // wait for TX lock to be released
                     Lock::Tx => Poll::Pending,
@@ -457,7 +512,7 @@ impl Rx {
             })
             .await;
 
-            crate::poll_fn(|| {
+            crate::poll_fn(&RX_WAKER, || {
                 match RX_STATE.load() {
                     RxState::Started => Poll::Pending,
 
@@ -490,6 +545,8 @@ impl Rx {
             .await;
         }
 
+        guard.defuse();
+
         let crc = RXCRC() as u16;
         if crcres {
             Ok(crc)
@@ -523,12 +580,55 @@ impl Tx {
     ///
     /// This method returns once `packet` can be used again but before the last bit of data has been
     /// transmitted
+    ///
+    /// Dropping this future before it completes may block until the radio is done with `packet`;
+    /// that requires the `RADIO` interrupt handler to be able to preempt the context that drops the
+    /// future
     pub async fn write(&mut self, packet: &Packet) -> Result<(), ()> {
         clock::has_stabilized().await;
 
         self.flush().await;
 
-        crate::poll_fn(|| unsafe {
+        // NOTE(cancellation) if this future is dropped after it claimed the radio but before the
+        // transfer started we release the radio. A started transfer can't be aborted so in that
+        // case we block until the DMA is done reading `packet`
+        let guard = OnDrop::new(|| {
+            crate::assert_preemptible(Interrupt0::RADIO as u8);
+
+            loop {
+                // NOTE(unsafe) not nested; this never runs from an `atomic0` section
+                let done = unsafe {
+                    crate::atomic0(Interrupt0::RADIO, || match TX_STATE.load() {
+                        TxState::TransferStart => false,
+
+                        // the `PHYEND` handler will release the radio
+                        TxState::TransferEnd => true,
+
+                        TxState::Idle | TxState::Done | TxState::Busy => {
+                            if LOCK.load() == Lock::Tx {
+                                INTENSET_FRAMESTART();
+                                RADIO::borrow_unchecked(|radio| {
+                                    radio.SHORTS.rmw(|_, w| w.PHYEND_DISABLE(0))
+                                });
+                                LOCK.store(Lock::Free);
+                                RX_WAKER.wake();
+
+                                semidap::info!("TX: cancelled -- releasing the radio");
+                            }
+
+                            true
+                        }
+                    })
+                };
+
+                if done {
+                    crate::dma_end();
+                    break;
+                }
+            }
+        });
+
+        crate::poll_fn(&TX_WAKER, || unsafe {
             // NOTE(atomic) because we may need to interrupt an RX task
             crate::atomic0(Interrupt0::RADIO, || {
                 let lock = LOCK.load();
@@ -556,10 +656,13 @@ impl Tx {
                             if rx_state == RxState::Started {
                                 RX_STATE.store(RxState::Interrupted);
                                 TASKS_STOP();
+                                RX_WAKER.wake();
 
                                 semidap::info!("TX: interrupted Rx.read");
 
                                 // wait until next state transition
+                                // NOTE STOP doesn't raise an interrupt so we wake ourselves
+                                TX_WAKER.wake();
                                 return Poll::Pending;
                             }
                         }
@@ -602,7 +705,7 @@ impl Tx {
         .await;
 
         // wait until END or CCABUSY
-        let ok = crate::poll_fn(|| {
+        let ok = crate::poll_fn(&TX_WAKER, || {
             let state = TX_STATE.load();
             if state != TxState::TransferStart {
                 Poll::Ready(state != TxState::Busy)
@@ -612,6 +715,8 @@ impl Tx {
         })
         .await;
 
+        guard.defuse();
+
         if ok {
             Ok(())
         } else {
@@ -621,7 +726,7 @@ impl Tx {
 
     /// Waits until any pending write has completed
     pub async fn flush(&mut self) {
-        crate::poll_fn(|| {
+        crate::poll_fn(&TX_WAKER, || {
             if TX_STATE.load() != TxState::TransferEnd {
                 Poll::Ready(())
             } else {
@@ -634,7 +739,7 @@ impl Tx {
 
 /// Radio packet
 pub struct Packet {
-    buffer: Box<P>,
+    buffer: Box<L>,
 }
 
 impl Packet {
@@ -644,7 +749,7 @@ impl Packet {
 
     /// Returns an empty IEEE 802.15.4 packet
     pub async fn new() -> Self {
-        let buffer = P::alloc().await;
+        let buffer = L::alloc().await;
         let mut packet = Packet { buffer };
         unsafe { packet.len_ptr_mut().write(2) }
         packet
@@ -685,7 +790,7 @@ impl Packet {
 
     #[cfg(feature = "usb")]
     #[cfg(TODO)]
-    pub(crate) fn from_parts(buffer: Box<P>, len: u8) -> Self {
+    pub(crate) fn from_parts(buffer: Box<L>, len: u8) -> Self {
         let mut packet = Packet { buffer };
         unsafe {
             packet.len_ptr_mut().write(len);
@@ -6,9 +6,10 @@ use core::{
     task::Poll,
 };
 
+use async_core::waker::AtomicWaker;
 use pac::{p0, SPIM0};
 
-use crate::{p0::Pin, Interrupt0, NotSendOrSync};
+use crate::{p0::Pin, util::OnDrop, Interrupt0, NotSendOrSync};
 
 // TODO hand out up to 3 SPIs
 static TAKEN: AtomicBool = AtomicBool::new(false);
@@ -20,9 +21,11 @@ fn SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0() {
 
     DONE.store(true, Ordering::Relaxed);
     SPIM0::borrow_unchecked(|spim| spim.EVENTS_END.zero());
+    WAKER.wake();
 }
 
 static DONE: AtomicBool = AtomicBool::new(false);
+static WAKER: AtomicWaker = AtomicWaker::new();
 
 /// Host-mode SPI
 pub struct Spi {
@@ -70,6 +73,9 @@ impl Spi {
     }
 
     /// Reads data from the device by sending it junk data
+    ///
+    /// Dropping this future before it completes blocks until the transfer is over; that requires
+    /// the `SPIM0` interrupt handler to be able to preempt the context that drops the future
     pub async fn read(&mut self, buf: &mut [u8]) {
         if let Some(len) = NonZeroU16::new(buf.len() as u16) {
             self.transfer(Transfer::Rx {
@@ -81,6 +87,9 @@ impl Spi {
     }
 
     /// Sends data to the device ignoring the data it sends to us
+    ///
+    /// Dropping this future before it completes blocks until the transfer is over; that requires
+    /// the `SPIM0` interrupt handler to be able to preempt the context that drops the future
     pub async fn write(&mut self, buf: &[u8]) {
         if let Some(len) = NonZeroU16::new(buf.len() as u16) {
             self.transfer(Transfer::Tx {
@@ -125,7 +134,16 @@ impl Spi {
             spim.TASKS_START.write(|w| w.TASKS_START(1));
         });
 
-        crate::poll_fn(|| {
+        // NOTE(cancellation) if this future is dropped before the transfer is over the DMA would
+        // still be accessing the buffers after they have been handed back to the caller so we
+        // block until the (short) transfer completes
+        let guard = OnDrop::new(|| {
+            crate::assert_preemptible(Interrupt0::SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0 as u8);
+            while !DONE.load(Ordering::Relaxed) {}
+            crate::dma_end();
+        });
+
+        crate::poll_fn(&WAKER, || {
             if DONE.load(Ordering::Relaxed) {
                 crate::dma_end();
                 Poll::Ready(())
@@ -134,6 +152,8 @@ impl Spi {
             }
         })
         .await;
+
+        guard.defuse();
     }
 }
 
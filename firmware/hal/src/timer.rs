@@ -9,6 +9,11 @@ use core::{
     time::Duration,
 };
 
+use async_core::{
+    future::{self, Either},
+    waker::AtomicWaker,
+};
+use binfmt::derive::binDebug;
 use pac::RTC0;
 
 use crate::{time, NotSync};
@@ -23,7 +28,7 @@ mod task {
 
     use crate::{led, Interrupt0};
 
-    use super::STEP;
+    use super::{STEP, WAKERS};
 
     fn init() {
         RTC0::borrow_unchecked(|rtc| unsafe {
@@ -67,16 +72,19 @@ mod task {
             if rtc.EVENTS_COMPARE1.read().EVENTS_COMPARE() != 0 {
                 rtc.EVENTS_COMPARE1.zero();
                 rtc.INTENCLR.write(|w| w.COMPARE1(1));
+                WAKERS[1].wake();
             }
 
             if rtc.EVENTS_COMPARE2.read().EVENTS_COMPARE() != 0 {
                 rtc.EVENTS_COMPARE2.zero();
                 rtc.INTENCLR.write(|w| w.COMPARE2(1));
+                WAKERS[2].wake();
             }
 
             if rtc.EVENTS_COMPARE3.read().EVENTS_COMPARE() != 0 {
                 rtc.EVENTS_COMPARE3.zero();
                 rtc.INTENCLR.write(|w| w.COMPARE3(1));
+                WAKERS[3].wake();
             }
         });
     }
@@ -92,6 +100,14 @@ pub struct Timer {
 // NOTE timer `0` is used for the "heartbeat" task
 static TAKEN: AtomicU8 = AtomicU8::new(1);
 
+// the tasks waiting on each timer; woken up by the `RTC0` interrupt handler
+static WAKERS: [AtomicWaker; 4] = [
+    AtomicWaker::new(),
+    AtomicWaker::new(),
+    AtomicWaker::new(),
+    AtomicWaker::new(),
+];
+
 impl Timer {
     /// Claims the `Timer`
     pub fn claim() -> Self {
@@ -128,8 +144,25 @@ impl Timer {
             },
         }
     }
+
+    /// Runs `f` to completion unless the specified duration elapses first
+    ///
+    /// On timeout `f` is dropped, which cancels the operation it was performing
+    pub async fn timeout<F>(&mut self, dur: Duration, f: F) -> Result<F::Output, Elapsed>
+    where
+        F: Future,
+    {
+        match future::select(f, self.wait(dur)).await {
+            Either::First(output) => Ok(output),
+            Either::Second(()) => Err(Elapsed),
+        }
+    }
 }
 
+/// Error returned by `Timer::timeout`
+#[derive(Clone, Copy, PartialEq, binDebug)]
+pub struct Elapsed;
+
 struct Wait<'a> {
     timer: &'a mut Timer,
     state: State,
@@ -144,7 +177,9 @@ enum State {
 impl Future for Wait<'_> {
     type Output = ();
 
-    fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        WAKERS[usize::from(self.timer.i)].register(cx.waker());
+
         match self.state {
             State::NotStarted { diff } => {
                 let i = self.timer.i;
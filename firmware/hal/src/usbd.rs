@@ -6,6 +6,7 @@ use core::{
     task::Poll,
 };
 
+use async_core::waker::AtomicWaker;
 use binfmt::derive::binDebug;
 use pac::{
     usbd::{epdatastatus, epinen, epouten, eventcause},
@@ -14,7 +15,7 @@ use pac::{
 use pool::Box;
 use usb2::{cdc::acm, hid, GetDescriptor, Request, StandardRequest};
 
-use crate::{atomic::Atomic, mem::P, Interrupt1, NotSendOrSync};
+use crate::{atomic::Atomic, mem::M, util::OnDrop, Interrupt1, NotSendOrSync};
 
 include!(concat!(env!("OUT_DIR"), "/descs.rs"));
 
@@ -70,25 +71,25 @@ mod task {
     use pac::{CLOCK, USBD};
     use pool::Node;
 
-    use crate::{clock, errata, mem::P, util::Align4, Interrupt0, Interrupt1};
+    use crate::{clock, errata, mem::M, util::Align4, Interrupt0, Interrupt1};
 
     use super::{
         Ep0State, Ep2InState, EpIn3State, EpOut3State, PowerEvent, PowerState, UsbdEvent,
-        EP2IN_STATE, EPIN3_STATE, EPOUT3_STATE, TX_BUF,
+        EP2IN_STATE, EPIN3_STATE, EPOUT3_STATE, HID_IN_WAKER, HID_OUT_WAKER, TX_BUF,
     };
 
     static mut PCSTATE: PowerState = PowerState::Off;
 
     // NOTE(unsafe) all interrupts are still globally masked (`CPSID I`)
     fn init() {
-        static mut PACKETS: [MaybeUninit<Node<[u8; P::SIZE as usize]>>; 3] = [
+        static mut PACKETS: [MaybeUninit<Node<[u8; M::SIZE]>>; 3] = [
             MaybeUninit::uninit(),
             MaybeUninit::uninit(),
             MaybeUninit::uninit(),
         ];
 
         for packet in PACKETS {
-            P::manage(packet)
+            M::manage(packet)
         }
 
         // reserve peripherals for HAL use
@@ -315,11 +316,13 @@ mod task {
                     if status.EPOUT3() != 0 {
                         semidap::info!("HID: received data");
                         EPOUT3_STATE.store(EpOut3State::DataReady);
+                        HID_OUT_WAKER.wake();
                     }
 
                     if status.EPIN3() != 0 {
                         semidap::info!("HID: data sent");
                         EPIN3_STATE.store(EpIn3State::Idle);
+                        HID_IN_WAKER.wake();
                     }
                 }
 
@@ -331,11 +334,13 @@ mod task {
                 UsbdEvent::ENDEPIN3 => {
                     semidap::info!("HID: data to send is ready");
                     EPIN3_STATE.store(EpIn3State::TransferEnd);
+                    HID_IN_WAKER.wake();
                 }
 
                 UsbdEvent::ENDEPOUT3 => {
                     semidap::info!("HID: received data has been copied");
                     EPOUT3_STATE.store(EpOut3State::Done);
+                    HID_OUT_WAKER.wake();
                 }
 
                 UsbdEvent::TxWrite => unsafe { super::start_epin2(&mut EP2IN_BUF.0) },
@@ -507,6 +512,7 @@ fn std_req(
                                 usbd.EPOUTEN.write(|w| w.OUT0(1).OUT3(1));
 
                                 EPIN3_STATE.store(EpIn3State::Idle);
+                                HID_IN_WAKER.wake();
 
                                 // start accepting data on EPOUT3
                                 usbd.SIZE_EPOUT3.write(|w| w.SIZE(0));
@@ -696,7 +702,7 @@ fn start_epin1(buf: &'static [u8]) {
 /// # Safety
 /// This hands `buf` to the DMA. Caller must manually enforce that aliasing rules are respected
 unsafe fn start_epin2(buf: &mut [u8; 63]) {
-    let n = TX_BUF.read(buf) as u8;
+    let n = TX_BUF.try_read(buf) as u8;
     if n != 0 {
         semidap::info!("EP2IN: sending {} bytes", n);
         USBD::borrow_unchecked(|usbd| {
@@ -786,19 +792,13 @@ pub fn serial() -> Tx {
     }
 }
 
-static TX_BUF: ring::Buffer = unsafe {
-    ring::Buffer::new({
-        #[link_section = ".uninit.TX_BUF"]
-        static TX_BUF: [u8; 256] = [0; 256];
-        &TX_BUF
-    })
-};
+static TX_BUF: ring::Buffer<256> = ring::Buffer::new();
 
 impl Tx {
     /// Sends data to the host
     pub fn write(&mut self, bytes: &[u8]) {
         // FIXME this should use `write_all`
-        TX_BUF.write(bytes);
+        TX_BUF.try_write(bytes);
         if !bytes.is_empty() {
             crate::pend1(Interrupt1::USBD);
         }
@@ -822,12 +822,17 @@ enum EpOut3State {
 }
 
 static EPOUT3_STATE: Atomic<EpOut3State> = Atomic::new();
+// the task blocked on `HidOut.recv`
+static HID_OUT_WAKER: AtomicWaker = AtomicWaker::new();
 
 impl HidOut {
     /// Receives a HID packet
+    ///
+    /// Dropping this future before it completes blocks until the transfer is over; that requires
+    /// the `USBD` interrupt handler to be able to preempt the context that drops the future
     pub async fn recv(&mut self, packet: &mut Packet) {
         // wait until the endpoint has received data
-        crate::poll_fn(|| {
+        crate::poll_fn(&HID_OUT_WAKER, || {
             if EPOUT3_STATE.load() == EpOut3State::DataReady {
                 Poll::Ready(())
             } else {
@@ -849,8 +854,16 @@ impl HidOut {
             size
         });
 
+        // NOTE(cancellation) the DMA must not write to `packet` after it has been handed back to
+        // the caller; the transfer is short so we block until it's done
+        let guard = OnDrop::new(|| {
+            crate::assert_preemptible(Interrupt1::USBD as u8);
+            while EPOUT3_STATE.load() != EpOut3State::Done {}
+            crate::dma_end();
+        });
+
         // wait until transfer is done
-        crate::poll_fn(|| {
+        crate::poll_fn(&HID_OUT_WAKER, || {
             if EPOUT3_STATE.load() == EpOut3State::Done {
                 crate::dma_end();
                 Poll::Ready(())
@@ -859,6 +872,8 @@ impl HidOut {
             }
         })
         .await;
+
+        guard.defuse();
     }
 }
 
@@ -879,15 +894,20 @@ enum EpIn3State {
 }
 
 static EPIN3_STATE: Atomic<EpIn3State> = Atomic::new();
+// the task blocked on `HidIn.send` or `HidIn.flush`
+static HID_IN_WAKER: AtomicWaker = AtomicWaker::new();
 
 impl HidIn {
     /// Sends a HID packet
     ///
     /// Note that this returns after `packet` can be used but before the data has been put "on the
     /// wire"
+    ///
+    /// Dropping this future before it completes blocks until the transfer is over; that requires
+    /// the `USBD` interrupt handler to be able to preempt the context that drops the future
     pub async fn send(&mut self, packet: &Packet) {
         // wait until the endpoint has been enabled
-        crate::poll_fn(|| {
+        crate::poll_fn(&HID_IN_WAKER, || {
             if EPIN3_STATE.load() == EpIn3State::Off {
                 Poll::Pending
             } else {
@@ -907,8 +927,16 @@ impl HidIn {
             usbd.TASKS_STARTEPIN3.write(|w| w.TASKS_STARTEPIN(1));
         });
 
+        // NOTE(cancellation) the DMA must not read `packet` after it has been handed back to the
+        // caller; the transfer is short so we block until it's done
+        let guard = OnDrop::new(|| {
+            crate::assert_preemptible(Interrupt1::USBD as u8);
+            while EPIN3_STATE.load() == EpIn3State::TransferStart {}
+            crate::dma_end();
+        });
+
         // wait until data has been transferred
-        crate::poll_fn(|| {
+        crate::poll_fn(&HID_IN_WAKER, || {
             let state = EPIN3_STATE.load();
             if state == EpIn3State::TransferEnd || state == EpIn3State::Idle {
                 crate::dma_end();
@@ -918,11 +946,13 @@ impl HidIn {
             }
         })
         .await;
+
+        guard.defuse();
     }
 
     /// Waits until the any pending write completes
     pub async fn flush(&mut self) {
-        crate::poll_fn(|| {
+        crate::poll_fn(&HID_IN_WAKER, || {
             if EPIN3_STATE.load() != EpIn3State::TransferEnd {
                 Poll::Ready(())
             } else {
@@ -935,7 +965,7 @@ impl HidIn {
 
 /// HID packet
 pub struct Packet {
-    buffer: Box<P>,
+    buffer: Box<M>,
     len: u8,
 }
 
@@ -948,7 +978,7 @@ impl Packet {
     /// Returns a new, empty HID packet with report ID set to 0
     pub async fn new() -> Self {
         Packet {
-            buffer: P::alloc().await,
+            buffer: M::alloc().await,
             len: 0,
         }
     }
@@ -1022,7 +1052,7 @@ pub fn hid() -> (HidOut, HidIn) {
 /// USB packet
 #[cfg(TODO)]
 pub struct Packet {
-    buffer: Box<P>,
+    buffer: Box<crate::mem::L>,
     len: u8,
 }
 
@@ -1036,7 +1066,7 @@ impl Packet {
     /// Returns an empty USB packet
     pub async fn new() -> Self {
         Self {
-            buffer: P::alloc().await,
+            buffer: crate::mem::L::alloc().await,
             len: 0,
         }
     }
@@ -1063,7 +1093,7 @@ impl Packet {
     }
 
     #[cfg(feature = "radio")]
-    pub(crate) unsafe fn from_parts(buffer: Box<P>, len: u8) -> Self {
+    pub(crate) unsafe fn from_parts(buffer: Box<crate::mem::L>, len: u8) -> Self {
         Self { buffer, len }
     }
 
@@ -1,4 +1,4 @@
-use core::ops;
+use core::{mem, ops};
 
 #[repr(align(4))]
 pub(crate) struct Align4<T>(pub T);
@@ -16,3 +16,37 @@ impl<T> ops::DerefMut for Align4<T> {
         &mut self.0
     }
 }
+
+/// Runs `f` when dropped
+///
+/// Used to clean up after a future that's dropped (cancelled) before it completes, e.g. by
+/// `select`
+pub(crate) struct OnDrop<F>
+where
+    F: FnMut(),
+{
+    f: F,
+}
+
+impl<F> OnDrop<F>
+where
+    F: FnMut(),
+{
+    pub fn new(f: F) -> Self {
+        Self { f }
+    }
+
+    /// Drops the guard without running `f`
+    pub fn defuse(self) {
+        mem::forget(self)
+    }
+}
+
+impl<F> Drop for OnDrop<F>
+where
+    F: FnMut(),
+{
+    fn drop(&mut self) {
+        (self.f)()
+    }
+}
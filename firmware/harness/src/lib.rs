@@ -0,0 +1,71 @@
+//! On-target test harness
+//!
+//! Functions declared in a `#[harness::tests]` module are collected into the `.tests` linker
+//! section; `harness::run` runs all of them in a single load of the program. Each test reports
+//! its start and its end to `semidap`; a test that aborts (e.g. panics) is reported as a failure
+//! -- or as a success if it has the `#[should_panic]` attribute -- and `semidap` resets the
+//! device and resumes execution from the next test. A test that calls `semidap::exit` fails and
+//! ends the run.
+//!
+//! See `firmware/tests/src/bin/harness.rs` for an example
+
+#![deny(missing_docs)]
+#![deny(warnings)]
+#![no_std]
+
+use core::{
+    mem::{self, MaybeUninit},
+    slice,
+};
+
+pub use harness_macros::tests;
+
+/// Implementation detail
+#[doc(hidden)]
+pub struct Test {
+    pub name: &'static str,
+    pub run: fn(),
+    pub should_panic: bool,
+}
+
+// Index of the next test to run. This variable lives in RAM that's not initialized by `Reset`
+// so its value is preserved when `semidap` resets the device after a test aborts. `semidap`
+// sets this variable to `0` when it loads the program
+#[link_section = ".uninit.HARNESS_NEXT"]
+#[no_mangle]
+static mut HARNESS_NEXT: MaybeUninit<usize> = MaybeUninit::uninit();
+
+/// Runs all the tests, in declaration order, and then exits the `semidap` process
+pub fn run() -> ! {
+    extern "C" {
+        static _stests: usize;
+        static _etests: usize;
+    }
+
+    let tests = unsafe {
+        let stests = &_stests as *const usize as *const Test;
+        let etests = &_etests as *const usize as *const Test;
+        slice::from_raw_parts(
+            stests,
+            (etests as usize - stests as usize) / mem::size_of::<Test>(),
+        )
+    };
+
+    loop {
+        let next = unsafe { HARNESS_NEXT.as_ptr().read_volatile() };
+        let test = if let Some(test) = tests.get(next) {
+            test
+        } else {
+            break;
+        };
+
+        // NOTE update the index *before* running the test in case it aborts
+        unsafe { HARNESS_NEXT.as_mut_ptr().write_volatile(next + 1) }
+
+        semidap::test_start(test.name, test.should_panic);
+        (test.run)();
+        semidap::test_end();
+    }
+
+    semidap::exit(0)
+}
@@ -2,12 +2,24 @@
 
 #![no_std]
 
+#[cfg(test)]
+extern crate std;
+
 use core::{
     mem, ops,
-    ptr::{self, NonNull},
-    sync::atomic::{AtomicPtr, Ordering},
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Waker,
 };
 
+use async_core::waker::AtomicWaker;
+
+use crate::stack::Stack;
+
+#[cfg(test)]
+mod sched;
+mod stack;
+
 #[doc(hidden)]
 pub trait Pool: 'static {
     // keep things simple
@@ -15,64 +27,95 @@ pub trait Pool: 'static {
 
     #[doc(hidden)]
     fn get() -> &'static PoolImpl<Self::T>;
+
+    #[doc(hidden)]
+    fn stats() -> &'static Stats;
+}
+
+/// Usage statistics of a memory pool
+///
+/// The counters are laid out as three `usize` values: allocated, high-water mark and failed
+/// `try_alloc` calls. The statistics of pool `P` live in the unmangled `POOL_P_STATS` symbol so
+/// they can be read with `semidap --watch-var POOL_P_STATS:u32`
+#[repr(C)]
+pub struct Stats {
+    allocated: AtomicUsize,
+    high_water_mark: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl Stats {
+    #[doc(hidden)]
+    pub const fn new() -> Self {
+        Self {
+            allocated: AtomicUsize::new(0),
+            high_water_mark: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of memory blocks that are currently allocated
+    pub fn allocated(&self) -> usize {
+        self.allocated.load(Ordering::Relaxed)
+    }
+
+    /// Returns the largest number of memory blocks that have been allocated at the same time
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of `try_alloc` calls that failed because the pool was empty
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    #[doc(hidden)]
+    pub fn on_alloc(&self) {
+        let allocated = self.allocated.fetch_add(1, Ordering::Relaxed) + 1;
+        self.high_water_mark.fetch_max(allocated, Ordering::Relaxed);
+    }
+
+    #[doc(hidden)]
+    pub fn on_fail(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_free(&self) {
+        self.allocated.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 #[doc(hidden)]
 pub struct PoolImpl<T> {
-    head: AtomicPtr<Node<T>>,
+    stack: Stack<T>,
+    // a task waiting for a memory block
+    waker: AtomicWaker,
 }
 
 impl<T> PoolImpl<T> {
     #[doc(hidden)]
     pub const fn new() -> Self {
         Self {
-            head: AtomicPtr::new(ptr::null_mut()),
+            stack: Stack::new(),
+            waker: AtomicWaker::new(),
         }
     }
 
+    #[doc(hidden)]
+    pub fn register(&self, waker: &Waker) {
+        self.waker.register(waker)
+    }
+
     #[doc(hidden)]
     pub fn pop(&self) -> Option<NonNull<Node<T>>> {
-        loop {
-            let head = self.head.load(Ordering::Relaxed);
-            if let Some(nn_head) = NonNull::new(head) {
-                let next = unsafe { node_next(head).read() };
-
-                match self.head.compare_exchange_weak(
-                    head,
-                    next,
-                    Ordering::Relaxed,
-                    Ordering::Relaxed,
-                ) {
-                    Ok(_) => break Some(nn_head),
-                    // interrupt occurred
-                    Err(_) => continue,
-                }
-            } else {
-                // stack is observed as empty
-                break None;
-            }
-        }
+        self.stack.pop()
     }
 
     #[doc(hidden)]
     pub unsafe fn push(&self, new_head: NonNull<Node<T>>) {
-        let mut head = self.head.load(Ordering::Relaxed);
-        loop {
-            node_next(new_head.as_ptr()).write(head);
-
-            if let Err(p) = self.head.compare_exchange_weak(
-                head,
-                new_head.as_ptr(),
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-            ) {
-                head = p
-            } else {
-                // memory block became available: wake up a task
-                asm::sev();
-                return;
-            }
-        }
+        self.stack.push(new_head);
+        // memory block became available: wake up a task
+        self.waker.wake();
     }
 }
 
@@ -91,6 +134,11 @@ macro_rules! pool {
                 static $ident: $crate::PoolImpl<[u8; $N]> = $crate::PoolImpl::new();
                 &$ident
             }
+
+            #[inline(always)]
+            fn stats() -> &'static $crate::Stats {
+                $ident::stats()
+            }
         }
 
         impl $ident {
@@ -100,10 +148,28 @@ macro_rules! pool {
             /// Tries to acquire a memory block
             #[allow(dead_code)]
             pub fn try_alloc() -> Option<$crate::Box<$ident>> {
+                let b = $ident::alloc_();
+                if b.is_none() {
+                    $ident::stats().on_fail();
+                }
+                b
+            }
+
+            /// Returns the usage statistics of this pool
+            #[allow(dead_code)]
+            pub fn stats() -> &'static $crate::Stats {
+                // NOTE the name of this symbol is part of the public API; see `Stats`
+                #[export_name = concat!("POOL_", stringify!($ident), "_STATS")]
+                static STATS: $crate::Stats = $crate::Stats::new();
+                &STATS
+            }
+
+            fn alloc_() -> Option<$crate::Box<$ident>> {
                 unsafe {
-                    <$ident as $crate::Pool>::get()
-                        .pop()
-                        .map(|n| $crate::Box::from_raw(n.as_ptr()))
+                    <$ident as $crate::Pool>::get().pop().map(|n| {
+                        $ident::stats().on_alloc();
+                        $crate::Box::from_raw(n.as_ptr())
+                    })
                 }
             }
 
@@ -117,9 +183,12 @@ macro_rules! pool {
 
                     fn poll(
                         self: core::pin::Pin<&mut Self>,
-                        _: &mut core::task::Context,
+                        cx: &mut core::task::Context,
                     ) -> core::task::Poll<Self::Output> {
-                        $ident::try_alloc()
+                        // NOTE register *before* checking so a block freed in between (e.g. by an
+                        // interrupt handler) is not missed
+                        <$ident as $crate::Pool>::get().register(cx.waker());
+                        $ident::alloc_()
                             .map(core::task::Poll::Ready)
                             .unwrap_or(core::task::Poll::Pending)
                     }
@@ -209,6 +278,7 @@ where
     P: Pool,
 {
     fn drop(&mut self) {
+        P::stats().on_free();
         unsafe { P::get().push(self.node) }
     }
 }
@@ -257,4 +327,28 @@ mod tests {
         core::mem::forget(y);
         assert!(B::try_alloc().is_none());
     }
+
+    #[test]
+    fn stats() {
+        static mut N: [MaybeUninit<Node<[u8; 1]>>; 2] =
+            [MaybeUninit::uninit(), MaybeUninit::uninit()];
+
+        pool!(pub C: [u8; 1]);
+        for n in unsafe { &mut N } {
+            C::manage(n);
+        }
+
+        let x = C::try_alloc().unwrap();
+        let y = C::try_alloc().unwrap();
+        assert!(C::try_alloc().is_none());
+        drop(x);
+
+        let stats = C::stats();
+        assert_eq!(stats.allocated(), 1);
+        assert_eq!(stats.high_water_mark(), 2);
+        assert_eq!(stats.failed(), 1);
+
+        drop(y);
+        assert_eq!(stats.allocated(), 0);
+    }
 }
@@ -0,0 +1,150 @@
+//! Deterministic scheduler for the tests
+//!
+//! The closures passed to `run` execute on their own threads but only one of them makes progress
+//! at any time. At every `preempt` point the `pick` function chooses the thread that runs next so
+//! an interleaving can be reproduced
+
+use std::{
+    boxed::Box,
+    cell::RefCell,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    vec::Vec,
+};
+
+/// A closure that runs on its own thread
+pub type Thread = Box<dyn FnOnce() + Send>;
+
+type Pick = Box<dyn FnMut(&[usize]) -> usize + Send>;
+
+struct State {
+    // the thread that's allowed to run
+    current: Option<usize>,
+    done: Vec<bool>,
+    // picks the next thread from the ones that haven't finished
+    pick: Pick,
+}
+
+impl State {
+    fn schedule(&mut self) {
+        let runnable = (0..self.done.len())
+            .filter(|i| !self.done[*i])
+            .collect::<Vec<_>>();
+
+        self.current = if runnable.is_empty() {
+            None
+        } else {
+            let next = (self.pick)(&runnable);
+            assert!(runnable.contains(&next), "thread {} can't run", next);
+            Some(next)
+        };
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    turn: Condvar,
+}
+
+impl Shared {
+    fn wait(&self, me: usize) {
+        let mut state = self.state.lock().unwrap();
+        while state.current != Some(me) {
+            state = self.turn.wait(state).unwrap();
+        }
+    }
+
+    // lets `pick` choose the next thread; `me` then waits for its turn unless it's done
+    fn switch(&self, me: usize, done: bool) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.done[me] |= done;
+            state.schedule();
+        }
+        self.turn.notify_all();
+
+        if !done {
+            self.wait(me)
+        }
+    }
+}
+
+std::thread_local! {
+    static CURRENT: RefCell<Option<(Arc<Shared>, usize)>> = const { RefCell::new(None) };
+}
+
+/// Gives the scheduler the chance to switch to a different thread
+///
+/// This is a no-op when not called from one of the threads spawned by `run`
+pub fn preempt() {
+    CURRENT.with(|current| {
+        if let Some((shared, me)) = &*current.borrow() {
+            shared.switch(*me, false)
+        }
+    })
+}
+
+/// Runs the `threads` to completion, interleaving them as dictated by `pick`
+///
+/// `pick` receives the identifiers (indices into `threads`) of the threads that haven't finished
+/// and returns the one that will run next
+pub fn run(threads: Vec<Thread>, pick: impl FnMut(&[usize]) -> usize + Send + 'static) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            current: None,
+            done: std::vec![false; threads.len()],
+            pick: Box::new(pick),
+        }),
+        turn: Condvar::new(),
+    });
+
+    let handles = threads
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                // hands control to the next thread even if `f` panics
+                struct Exit(Arc<Shared>, usize);
+
+                impl Drop for Exit {
+                    fn drop(&mut self) {
+                        CURRENT.with(|current| current.borrow_mut().take());
+                        self.0.switch(self.1, true);
+                    }
+                }
+
+                shared.wait(i);
+                CURRENT.with(|current| *current.borrow_mut() = Some((shared.clone(), i)));
+                let _exit = Exit(shared, i);
+                f()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    shared.state.lock().unwrap().schedule();
+    shared.turn.notify_all();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+/// Returns a `pick` function that makes pseudo-random choices from the given `seed`
+pub fn random(seed: u32) -> impl FnMut(&[usize]) -> usize + Send + 'static {
+    // xorshift32; the state must not be zero
+    let mut state = seed << 1 | 1;
+    move |runnable| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        runnable[state as usize % runnable.len()]
+    }
+}
+
+/// Returns a `pick` function that follows the `script` and then runs the remaining threads in
+/// order
+pub fn script(script: &'static [usize]) -> impl FnMut(&[usize]) -> usize + Send + 'static {
+    let mut script = script.iter();
+    move |runnable| script.next().copied().unwrap_or(runnable[0])
+}
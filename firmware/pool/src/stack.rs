@@ -0,0 +1,204 @@
+//! Lock-free stacks of memory blocks
+//!
+//! A Treiber stack `pop` reads `head`, then `head.next` and then swaps `head` for `head.next`
+//! with a compare-and-swap (CAS). If, in between, someone else pops `head`, pops `head.next` and
+//! pushes `head` back then the CAS succeeds but installs a `next` node that's in use. This is the
+//! ABA problem. There are two ways around it:
+//!
+//! - On single-core devices `pop` runs with interrupts masked so nothing can run between the
+//!   reads and the write
+//! - On multi-core systems `head` carries a tag that's incremented on every `pop` so the CAS
+//!   fails if `head` has been popped in the meantime, even if it was then pushed back
+//!
+//! `push` doesn't suffer from the ABA problem as it never reads through `head`
+
+#[cfg(any(target_arch = "arm", test))]
+mod single_core;
+#[cfg(target_has_atomic = "64")]
+mod tagged;
+
+#[cfg(target_arch = "arm")]
+pub(crate) use single_core::Stack;
+#[cfg(not(target_arch = "arm"))]
+pub(crate) use tagged::Stack;
+
+// a point where the deterministic scheduler of the tests may switch to a different thread
+#[inline(always)]
+fn preempt() {
+    #[cfg(test)]
+    crate::sched::preempt()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        mem::MaybeUninit,
+        ptr::{self, NonNull},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+    use std::{boxed::Box, vec::Vec};
+
+    use crate::{sched, Node};
+
+    use super::{single_core, tagged};
+
+    type N = Node<[u8; 8]>;
+
+    trait Lifo: Sync + 'static {
+        fn new() -> Self;
+        fn pop(&self) -> Option<NonNull<N>>;
+        unsafe fn push(&self, node: NonNull<N>);
+    }
+
+    macro_rules! lifo {
+        ($($stack:ty),*) => {
+            $(
+                impl Lifo for $stack {
+                    fn new() -> Self {
+                        <$stack>::new()
+                    }
+
+                    fn pop(&self) -> Option<NonNull<N>> {
+                        <$stack>::pop(self)
+                    }
+
+                    unsafe fn push(&self, node: NonNull<N>) {
+                        <$stack>::push(self, node)
+                    }
+                }
+            )*
+        };
+    }
+
+    lifo!(single_core::Stack<[u8; 8]>, tagged::Stack<[u8; 8]>);
+
+    // a stack and the nodes it manages
+    struct Fixture<S: 'static> {
+        stack: &'static S,
+        nodes: &'static [MaybeUninit<N>],
+        // whether a node has been popped and not yet pushed back
+        in_use: &'static [AtomicBool],
+    }
+
+    impl<S> Clone for Fixture<S> {
+        fn clone(&self) -> Self {
+            *self
+        }
+    }
+
+    impl<S> Copy for Fixture<S> {}
+
+    impl<S: Lifo> Fixture<S> {
+        fn new(n: usize) -> Self {
+            let stack = Box::leak(Box::new(S::new()));
+            let nodes = Box::leak((0..n).map(|_| MaybeUninit::uninit()).collect::<Box<[_]>>());
+            let in_use = Box::leak((0..n).map(|_| AtomicBool::new(false)).collect::<Box<[_]>>());
+
+            // the first node ends up on the top of the stack
+            for node in nodes.iter().rev() {
+                unsafe { stack.push(NonNull::from(node).cast()) }
+            }
+
+            Fixture {
+                stack,
+                nodes,
+                in_use,
+            }
+        }
+
+        fn index(&self, node: NonNull<N>) -> usize {
+            self.nodes
+                .iter()
+                .position(|n| ptr::eq(n.as_ptr(), node.as_ptr()))
+                .unwrap()
+        }
+
+        // pops a node and returns its index
+        fn take(&self) -> Option<usize> {
+            let i = self.index(self.stack.pop()?);
+            assert!(
+                !self.in_use[i].swap(true, Ordering::Relaxed),
+                "node {} was popped twice",
+                i
+            );
+            Some(i)
+        }
+
+        fn give(&self, i: usize) {
+            self.in_use[i].store(false, Ordering::Relaxed);
+            unsafe { self.stack.push(NonNull::from(&self.nodes[i]).cast()) }
+        }
+
+        // pops all the nodes; returns their indices
+        fn drain(&self) -> Vec<usize> {
+            let mut nodes = Vec::new();
+            while let Some(i) = self.take() {
+                nodes.push(i);
+            }
+            nodes
+        }
+    }
+
+    // several threads pop nodes and push them back
+    fn interleave<S: Lifo>(seed: u32) {
+        const NODES: usize = 3;
+        const THREADS: usize = 3;
+
+        let fixture = Fixture::<S>::new(NODES);
+
+        let threads = (0..THREADS)
+            .map(|_| {
+                Box::new(move || {
+                    for _ in 0..3 {
+                        if let Some(i) = fixture.take() {
+                            sched::preempt();
+                            fixture.give(i);
+                        }
+                    }
+                }) as sched::Thread
+            })
+            .collect();
+        sched::run(threads, sched::random(seed));
+
+        let mut nodes = fixture.drain();
+        nodes.sort();
+        assert_eq!(nodes, (0..NODES).collect::<Vec<_>>(), "seed: {}", seed);
+    }
+
+    #[test]
+    fn single_core() {
+        for seed in 0..200 {
+            interleave::<single_core::Stack<[u8; 8]>>(seed);
+        }
+    }
+
+    #[test]
+    fn tagged() {
+        for seed in 0..200 {
+            interleave::<tagged::Stack<[u8; 8]>>(seed);
+        }
+    }
+
+    #[test]
+    fn aba() {
+        // stack: 0 -> 1 -> 2
+        let fixture = Fixture::<tagged::Stack<[u8; 8]>>::new(3);
+
+        let threads = std::vec![
+            Box::new(move || {
+                fixture.take().unwrap();
+            }) as sched::Thread,
+            Box::new(move || {
+                let a = fixture.take().unwrap();
+                let _b = fixture.take().unwrap();
+                fixture.give(a);
+            }),
+        ];
+        // thread 0 is preempted right before its CAS (it has read `next` = 1); thread 1 then pops
+        // 0 and 1 and pushes 0 back (2 + 2 + 1 preemption points)
+        sched::run(threads, sched::script(&[0, 0, 1, 1, 1, 1, 1, 1]));
+
+        // node 1 is still in use by thread 1
+        assert_eq!(fixture.drain(), [2]);
+    }
+}
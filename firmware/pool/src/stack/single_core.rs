@@ -0,0 +1,55 @@
+//! Stack for single-core devices
+
+use core::{
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use async_core::critical;
+
+use crate::{node_next, Node};
+
+use super::preempt;
+
+pub(crate) struct Stack<T> {
+    head: AtomicPtr<Node<T>>,
+}
+
+impl<T> Stack<T> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub(crate) fn pop(&self) -> Option<NonNull<Node<T>>> {
+        // NOTE(ABA) interrupts are masked so no interrupt handler can pop (or push) nodes between
+        // the load of `head` and the store of `next`; this is sound because there's no other core
+        critical::free(|| {
+            let head = NonNull::new(self.head.load(Ordering::Relaxed))?;
+            let next = unsafe { node_next(head.as_ptr()).read() };
+            self.head.store(next, Ordering::Relaxed);
+            Some(head)
+        })
+    }
+
+    pub(crate) unsafe fn push(&self, new_head: NonNull<Node<T>>) {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            node_next(new_head.as_ptr()).write(head);
+            preempt();
+
+            if let Err(p) = self.head.compare_exchange_weak(
+                head,
+                new_head.as_ptr(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                // interrupt occurred
+                head = p
+            } else {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Stack for multi-core systems
+
+use core::{
+    marker::PhantomData,
+    ptr::NonNull,
+    sync::atomic::{AtomicPtr, AtomicU64, Ordering},
+};
+
+use crate::{node_next, Node};
+
+use super::preempt;
+
+// the tag is stored in the bits of `head` that the pointer doesn't use
+// NOTE on x86_64 and AArch64 user space addresses fit in 48 bits
+#[cfg(target_pointer_width = "64")]
+const TAG_SHIFT: u32 = 48;
+#[cfg(target_pointer_width = "32")]
+const TAG_SHIFT: u32 = 32;
+const PTR_MASK: u64 = (1 << TAG_SHIFT) - 1;
+
+pub(crate) struct Stack<T> {
+    // tag and pointer to the top node
+    head: AtomicU64,
+    _marker: PhantomData<AtomicPtr<Node<T>>>,
+}
+
+impl<T> Stack<T> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            head: AtomicU64::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn pop(&self) -> Option<NonNull<Node<T>>> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let nn_head = NonNull::new(ptr(head))?;
+            preempt();
+
+            // NOTE `nn_head` may have been popped by now; then `next` is stale but the CAS below
+            // fails because the tag has changed
+            let next = unsafe { node_next(nn_head.as_ptr()).read() };
+            preempt();
+
+            match self.head.compare_exchange_weak(
+                head,
+                pack(next, tag(head).wrapping_add(1)),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break Some(nn_head),
+                Err(h) => head = h,
+            }
+        }
+    }
+
+    pub(crate) unsafe fn push(&self, new_head: NonNull<Node<T>>) {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            node_next(new_head.as_ptr()).write(ptr(head));
+            preempt();
+
+            // NOTE the tag only needs to change on `pop`
+            if let Err(h) = self.head.compare_exchange_weak(
+                head,
+                pack(new_head.as_ptr(), tag(head)),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                head = h
+            } else {
+                return;
+            }
+        }
+    }
+}
+
+fn pack<T>(ptr: *mut Node<T>, tag: u64) -> u64 {
+    let addr = ptr as usize as u64;
+    assert_eq!(addr & !PTR_MASK, 0, "no room for the tag");
+
+    tag << TAG_SHIFT | addr
+}
+
+fn ptr<T>(head: u64) -> *mut Node<T> {
+    (head & PTR_MASK) as usize as *mut Node<T>
+}
+
+fn tag(head: u64) -> u64 {
+    (head >> TAG_SHIFT) & (u64::MAX >> TAG_SHIFT)
+}
@@ -1,85 +1,227 @@
 //! async-aware circular buffer
+//!
+//! This is a bip-buffer: besides copying data in and out (`try_write`, `try_read`) it can hand out
+//! contiguous regions of the buffer (`grant_write`, `grant_read`) so a DMA peripheral can write
+//! into (read from) it directly
 
+#![deny(missing_docs)]
 #![no_std]
 
 use core::{
-    cmp, ptr,
-    sync::atomic::{self, AtomicU16, Ordering},
+    cell::UnsafeCell,
+    cmp,
+    future::Future,
+    mem, ops,
+    pin::Pin,
+    slice,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll},
 };
 
-pub const N: u16 = 256;
+use async_core::waker::AtomicWaker;
 
-pub struct Buffer {
-    buffer: *mut u8,
-    read: AtomicU16,
-    write: AtomicU16,
+/// Single-producer single-consumer interrupt-safe circular buffer that holds `N` bytes
+///
+/// The producer (write) operations and the consumer (read) operations can run in different
+/// execution contexts (e.g. an interrupt handler and a task). Only one write grant and one read
+/// grant can exist at any time; trying to get a second one returns `None`
+pub struct Buffer<const N: usize> {
+    // NOTE zero initialized so grants never expose uninitialized memory
+    buffer: UnsafeCell<[u8; N]>,
+    // where the next byte will be written
+    write: AtomicUsize,
+    // where the next byte will be read
+    read: AtomicUsize,
+    // end of the readable data when the writer has wrapped around to the start of the buffer
+    last: AtomicUsize,
+    // end of the region handed out by the current write grant
+    reserve: AtomicUsize,
+    read_in_progress: AtomicBool,
+    write_in_progress: AtomicBool,
+    // the task waiting for data
+    reader: AtomicWaker,
 }
 
-unsafe impl Sync for Buffer {}
+unsafe impl<const N: usize> Sync for Buffer<N> {}
 
-impl Buffer {
-    /// # Safety
-    ///
-    /// This is a single-producer single-consumer interrupt-safe circular buffer. All producer
-    /// (`write`) operations  need be kept in a single execution context (interrupt priority); the
-    /// same requirement applies to the consumer (`read`) operations. Because `Buffer` can be placed
-    /// in a `static` variable this requirement needs to be enforced manually
-    pub const unsafe fn new(buffer: *const [u8; N as usize]) -> Self {
+impl<const N: usize> Buffer<N> {
+    /// Creates an empty buffer
+    pub const fn new() -> Self {
         Self {
-            buffer: buffer as *mut u8,
-            read: AtomicU16::new(0),
-            write: AtomicU16::new(0),
+            buffer: UnsafeCell::new([0; N]),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            last: AtomicUsize::new(0),
+            reserve: AtomicUsize::new(0),
+            read_in_progress: AtomicBool::new(false),
+            write_in_progress: AtomicBool::new(false),
+            reader: AtomicWaker::new(),
         }
     }
 
+    /// Returns the number of bytes that can be read
     pub fn bytes_to_read(&self) -> usize {
-        let read = self.read.load(Ordering::Relaxed);
-        let write = self.write.load(Ordering::Relaxed);
-        write.wrapping_sub(read).into()
+        let write = self.write.load(Ordering::Acquire);
+        let last = self.last.load(Ordering::Acquire);
+        let read = self.read.load(Ordering::Acquire);
+
+        if write >= read {
+            write - read
+        } else {
+            last - read + write
+        }
     }
 
-    pub fn read(&self, buf: &mut [u8]) -> usize {
-        if buf.is_empty() {
-            return 0;
+    /// Grants exclusive access to the next `n` bytes of free space
+    ///
+    /// Returns `None` if there aren't `n` *contiguous* free bytes or if a write grant already
+    /// exists
+    pub fn grant_write(&self, n: usize) -> Option<WriteGrant<'_, N>> {
+        self.grant(n, true)
+    }
+
+    /// Like `grant_write` but the grant may be smaller than `n` bytes
+    ///
+    /// Returns `None` if the buffer is full or if a write grant already exists
+    pub fn grant_write_max(&self, n: usize) -> Option<WriteGrant<'_, N>> {
+        self.grant(n, false)
+    }
+
+    fn grant(&self, mut n: usize, exact: bool) -> Option<WriteGrant<'_, N>> {
+        if n == 0 || self.write_in_progress.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        let write = self.write.load(Ordering::Acquire);
+        let read = self.read.load(Ordering::Acquire);
+
+        // NOTE in the inverted state (`write < read`) `write` must never catch up with `read`
+        // because then we couldn't tell a full buffer apart from an empty one
+        let start = if write < read {
+            // inverted: the free space is `write..read - 1`
+            let free = read - write - 1;
+            if (exact && n > free) || free == 0 {
+                None
+            } else {
+                n = cmp::min(n, free);
+                Some(write)
+            }
+        } else if write + n <= N || (!exact && write != N) {
+            // there's (some) room at the end of the buffer
+            n = cmp::min(n, N - write);
+            Some(write)
+        } else if (exact && n < read) || (!exact && read > 1) {
+            // wrap around to the start of the buffer
+            n = cmp::min(n, read - 1);
+            Some(0)
+        } else {
+            None
+        };
+
+        if let Some(start) = start {
+            self.reserve.store(start + n, Ordering::Release);
+
+            Some(WriteGrant {
+                buffer: self,
+                start,
+                len: n,
+            })
+        } else {
+            self.write_in_progress.store(false, Ordering::Release);
+            None
+        }
+    }
+
+    /// Grants exclusive access to the contiguous readable data
+    ///
+    /// Returns `None` if the buffer is empty or if a read grant already exists
+    pub fn grant_read(&self) -> Option<ReadGrant<'_, N>> {
+        if self.read_in_progress.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        let write = self.write.load(Ordering::Acquire);
+        let last = self.last.load(Ordering::Acquire);
+        let mut read = self.read.load(Ordering::Relaxed);
+
+        // all the data before the wrap-around point has been read
+        if read == last && write < read {
+            read = 0;
+            self.read.store(0, Ordering::Release);
+        }
+
+        let end = if write < read { last } else { write };
+        if end == read {
+            self.read_in_progress.store(false, Ordering::Release);
+            return None;
         }
 
-        let read = self.read.load(Ordering::Relaxed);
-        let write = self.write.load(Ordering::Relaxed);
-        atomic::compiler_fence(Ordering::Acquire);
+        Some(ReadGrant {
+            buffer: self,
+            start: read,
+            len: end - read,
+        })
+    }
 
-        let available = write.wrapping_sub(read);
-        if available == 0 {
-            return 0;
+    /// Copies as many `bytes` as possible into the buffer; returns the number of bytes copied
+    pub fn try_write(&self, bytes: &[u8]) -> usize {
+        let mut n = 0;
+        // the free space may wrap around the end of the buffer
+        while n < bytes.len() {
+            if let Some(mut grant) = self.grant_write_max(bytes.len() - n) {
+                let len = grant.len();
+                grant.copy_from_slice(&bytes[n..n + len]);
+                grant.commit(len);
+                n += len;
+            } else {
+                break;
+            }
         }
+        n
+    }
 
-        let cursor = read % N;
-        let len = cmp::min(buf.len(), available as usize) as u16;
-        unsafe {
-            if cursor + len > N {
-                // split memcpy
-                let pivot = N - cursor;
-                ptr::copy_nonoverlapping(
-                    self.buffer.add(cursor.into()),
-                    buf.as_mut_ptr(),
-                    pivot.into(),
-                );
-                ptr::copy_nonoverlapping(
-                    self.buffer,
-                    buf.as_mut_ptr().add(pivot.into()),
-                    (len - pivot).into(),
-                );
+    /// Copies as many bytes as possible into `buf`; returns the number of bytes copied
+    pub fn try_read(&self, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        // the data may wrap around the end of the buffer
+        while n < buf.len() {
+            if let Some(grant) = self.grant_read() {
+                let len = cmp::min(grant.len(), buf.len() - n);
+                buf[n..n + len].copy_from_slice(&grant[..len]);
+                grant.release(len);
+                n += len;
             } else {
-                // single memcpy
-                ptr::copy_nonoverlapping(
-                    self.buffer.add(cursor.into()),
-                    buf.as_mut_ptr(),
-                    len.into(),
-                );
+                break;
             }
         }
-        atomic::compiler_fence(Ordering::Release);
-        self.read.store(read.wrapping_add(len), Ordering::Relaxed);
-        len.into()
+        n
+    }
+
+    /// Waits until there's data in the buffer and then copies as much as possible into `buf`;
+    /// returns the number of bytes copied
+    pub fn read<'b>(&'b self, buf: &'b mut [u8]) -> impl Future<Output = usize> + 'b {
+        struct Read<'b, const N: usize> {
+            buffer: &'b Buffer<N>,
+            buf: &'b mut [u8],
+        }
+
+        impl<const N: usize> Future for Read<'_, N> {
+            type Output = usize;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<usize> {
+                let this = &mut *self;
+                this.buffer.reader.register(cx.waker());
+
+                let n = this.buffer.try_read(this.buf);
+                if n != 0 || this.buf.is_empty() {
+                    Poll::Ready(n)
+                } else {
+                    Poll::Pending
+                }
+            }
+        }
+
+        Read { buffer: self, buf }
     }
 
     #[cfg(TODO)]
@@ -87,47 +229,202 @@ impl Buffer {
         todo!()
     }
 
-    pub fn write(&self, bytes: &[u8]) -> usize {
-        if bytes.is_empty() {
-            return 0;
+    fn ptr(&self) -> *mut u8 {
+        self.buffer.get() as *mut u8
+    }
+}
+
+/// Exclusive access to free space in a `Buffer`
+///
+/// Dropping the grant is the same as committing 0 bytes
+pub struct WriteGrant<'b, const N: usize> {
+    buffer: &'b Buffer<N>,
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> WriteGrant<'_, N> {
+    /// Makes the first `used` bytes of the grant available to the reader
+    pub fn commit(self, used: usize) {
+        self.commit_(used);
+        mem::forget(self);
+    }
+
+    fn commit_(&self, used: usize) {
+        let buffer = self.buffer;
+        let used = cmp::min(used, self.len);
+
+        let write = buffer.write.load(Ordering::Acquire);
+        buffer.reserve.fetch_sub(self.len - used, Ordering::AcqRel);
+        let last = buffer.last.load(Ordering::Acquire);
+        let new_write = buffer.reserve.load(Ordering::Acquire);
+
+        if new_write < write && write != N {
+            // we have wrapped around; the readable data before the wrap-around point ends at
+            // `write`
+            buffer.last.store(write, Ordering::Release);
+        } else if new_write > last {
+            // we are about to pass the `last` marker; move it to the end of the buffer
+            buffer.last.store(N, Ordering::Release);
         }
 
-        let write = self.write.load(Ordering::Relaxed);
-        let read = self.read.load(Ordering::Relaxed);
-        atomic::compiler_fence(Ordering::Acquire);
+        buffer.write.store(new_write, Ordering::Release);
+        buffer.write_in_progress.store(false, Ordering::Release);
 
-        let available = read.wrapping_add(N).wrapping_sub(write);
-        if available == 0 {
-            return 0;
+        if used != 0 {
+            buffer.reader.wake();
         }
+    }
+}
 
-        let cursor = write % N;
-        let len = cmp::min(bytes.len(), available.into()) as u16;
-        unsafe {
-            if cursor + len > N {
-                // split memcpy
-                let pivot = N - cursor;
-                ptr::copy_nonoverlapping(
-                    bytes.as_ptr(),
-                    self.buffer.add(cursor.into()),
-                    pivot.into(),
-                );
-                ptr::copy_nonoverlapping(
-                    bytes.as_ptr().add(pivot.into()),
-                    self.buffer,
-                    (len - pivot).into(),
-                );
-            } else {
-                // single memcpy
-                ptr::copy_nonoverlapping(
-                    bytes.as_ptr(),
-                    self.buffer.add(cursor.into()),
-                    len.into(),
-                );
-            }
+impl<const N: usize> Drop for WriteGrant<'_, N> {
+    fn drop(&mut self) {
+        self.commit_(0)
+    }
+}
+
+impl<const N: usize> ops::Deref for WriteGrant<'_, N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // NOTE(unsafe) the grant has exclusive access to this region
+        unsafe { slice::from_raw_parts(self.buffer.ptr().add(self.start), self.len) }
+    }
+}
+
+impl<const N: usize> ops::DerefMut for WriteGrant<'_, N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // NOTE(unsafe) the grant has exclusive access to this region
+        unsafe { slice::from_raw_parts_mut(self.buffer.ptr().add(self.start), self.len) }
+    }
+}
+
+/// Exclusive access to readable data in a `Buffer`
+///
+/// Dropping the grant is the same as releasing 0 bytes
+pub struct ReadGrant<'b, const N: usize> {
+    buffer: &'b Buffer<N>,
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> ReadGrant<'_, N> {
+    /// Frees the first `used` bytes of the grant so the writer can reuse them
+    pub fn release(self, used: usize) {
+        self.release_(used);
+        mem::forget(self);
+    }
+
+    fn release_(&self, used: usize) {
+        let used = cmp::min(used, self.len);
+
+        self.buffer.read.fetch_add(used, Ordering::Release);
+        self.buffer.read_in_progress.store(false, Ordering::Release);
+    }
+}
+
+impl<const N: usize> Drop for ReadGrant<'_, N> {
+    fn drop(&mut self) {
+        self.release_(0)
+    }
+}
+
+impl<const N: usize> ops::Deref for ReadGrant<'_, N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // NOTE(unsafe) the writer won't modify this region until it's released
+        unsafe { slice::from_raw_parts(self.buffer.ptr().add(self.start), self.len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::Future,
+        pin::Pin,
+        ptr,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    };
+
+    use super::Buffer;
+
+    #[test]
+    fn grant() {
+        let buffer = Buffer::<8>::new();
+
+        let mut grant = buffer.grant_write(4).unwrap();
+        // only one write grant at a time
+        assert!(buffer.grant_write(1).is_none());
+        grant.copy_from_slice(&[0, 1, 2, 3]);
+        grant.commit(3);
+
+        let grant = buffer.grant_read().unwrap();
+        assert_eq!(*grant, [0, 1, 2]);
+        grant.release(2);
+
+        assert_eq!(buffer.bytes_to_read(), 1);
+    }
+
+    #[test]
+    fn wrap_around() {
+        let buffer = Buffer::<8>::new();
+
+        assert_eq!(buffer.try_write(&[0, 1, 2, 3, 4, 5]), 6);
+        let mut buf = [0; 4];
+        assert_eq!(buffer.try_read(&mut buf), 4);
+
+        // 2 bytes left at the end of the buffer; 3 contiguous bytes are at the start
+        let mut grant = buffer.grant_write(3).unwrap();
+        grant.copy_from_slice(&[6, 7, 8]);
+        grant.commit(3);
+        assert_eq!(buffer.bytes_to_read(), 5);
+
+        // data before the wrap-around point comes first
+        assert_eq!(*buffer.grant_read().unwrap(), [4, 5]);
+        let mut buf = [0; 8];
+        assert_eq!(buffer.try_read(&mut buf), 5);
+        assert_eq!(buf[..5], [4, 5, 6, 7, 8]);
+        assert_eq!(buffer.bytes_to_read(), 0);
+    }
+
+    #[test]
+    fn full() {
+        let buffer = Buffer::<4>::new();
+
+        assert_eq!(buffer.try_write(&[0, 1, 2, 3, 4]), 4);
+        assert!(buffer.grant_write_max(1).is_none());
+
+        let mut buf = [0; 2];
+        assert_eq!(buffer.try_read(&mut buf), 2);
+        // one byte must be left unused after wrapping around
+        assert_eq!(buffer.try_write(&[4, 5]), 1);
+    }
+
+    #[test]
+    fn read() {
+        unsafe fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+
+        unsafe fn noop(_: *const ()) {}
+
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        let waker = unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) };
+        let mut cx = Context::from_waker(&waker);
+        let buffer = Buffer::<4>::new();
+        let mut buf = [0; 4];
+
+        {
+            let mut read = buffer.read(&mut buf);
+            let mut read = unsafe { Pin::new_unchecked(&mut read) };
+            assert_eq!(read.as_mut().poll(&mut cx), Poll::Pending);
+
+            assert_eq!(buffer.try_write(&[1, 2]), 2);
+            assert_eq!(read.poll(&mut cx), Poll::Ready(2));
         }
-        atomic::compiler_fence(Ordering::Release);
-        self.write.store(write.wrapping_add(len), Ordering::Relaxed);
-        len.into()
+
+        assert_eq!(buf[..2], [1, 2]);
     }
 }
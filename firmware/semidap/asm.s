@@ -30,3 +30,27 @@ __abort:
   bkpt 0xaa
   .cfi_endproc
   .size __abort, . - __abort
+
+  /* fn __test(r0: u32, r1: *const u8, r2: usize, r3: bool) */
+  .global __test
+  .cfi_sections .debug_frame
+  .section .text.__test, "ax"
+  .thumb_func
+  .cfi_startproc
+__test:
+  bkpt 0xac
+  bx lr
+  .cfi_endproc
+  .size __test, . - __test
+
+  /* fn __syscall(r0: u32, r1: u32, r2: u32, r3: u32) -> i32 */
+  .global __syscall
+  .cfi_sections .debug_frame
+  .section .text.__syscall, "ax"
+  .thumb_func
+  .cfi_startproc
+__syscall:
+  bkpt 0xad
+  bx lr
+  .cfi_endproc
+  .size __syscall, . - __syscall
@@ -0,0 +1,32 @@
+//! Command-line arguments
+//!
+//! These are the arguments passed to `semidap` after the path to the ELF file, e.g. `cargo run
+//! -- foo bar`
+
+use core::str;
+
+use crate::syscall;
+
+// NOTE these must match the values used by the host
+const OP_ARGC: u32 = 5;
+const OP_ARGV: u32 = 6;
+
+/// Returns the number of arguments
+pub fn args_len() -> usize {
+    syscall(OP_ARGC, 0, 0, 0).map(|n| n as usize).unwrap_or(0)
+}
+
+/// Copies the `index`-th argument into `buf` and returns it
+///
+/// Returns `None` if there's no such argument or if `buf` is too small to hold it
+pub fn arg(index: usize, buf: &mut [u8]) -> Option<&str> {
+    let len = syscall(
+        OP_ARGV,
+        index as u32,
+        buf.as_mut_ptr() as u32,
+        buf.len() as u32,
+    )
+    .ok()?;
+    let bytes = buf.get(..len as usize)?;
+    str::from_utf8(bytes).ok()
+}
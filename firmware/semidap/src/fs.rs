@@ -0,0 +1,83 @@
+//! Access to the host's file system
+//!
+//! Paths are relative to the directory passed to `semidap` through its `--fs-root` flag.
+//! File system access is disabled if that flag is omitted.
+
+use crate::{syscall, Error};
+
+// NOTE these must match the values used by the host
+const OP_OPEN: u32 = 0;
+const OP_CLOSE: u32 = 1;
+const OP_READ: u32 = 2;
+const OP_WRITE: u32 = 3;
+
+const MODE_READ: u32 = 0;
+const MODE_WRITE: u32 = 1;
+const MODE_APPEND: u32 = 2;
+
+/// A file on the host
+///
+/// The file is closed when this handle is dropped
+pub struct File {
+    fd: u32,
+}
+
+impl File {
+    /// Opens a file in read-only mode
+    pub fn open(path: &str) -> Result<Self, Error> {
+        Self::open_with(path, MODE_READ)
+    }
+
+    /// Opens a file in write-only mode
+    ///
+    /// The file is created if it doesn't exist and truncated if it does
+    pub fn create(path: &str) -> Result<Self, Error> {
+        Self::open_with(path, MODE_WRITE)
+    }
+
+    /// Opens a file in append mode
+    ///
+    /// The file is created if it doesn't exist
+    pub fn append(path: &str) -> Result<Self, Error> {
+        Self::open_with(path, MODE_APPEND)
+    }
+
+    fn open_with(path: &str, mode: u32) -> Result<Self, Error> {
+        syscall(OP_OPEN, path.as_ptr() as u32, path.len() as u32, mode).map(|fd| File { fd })
+    }
+
+    /// Reads some bytes from the file into `buf`; returns how many bytes were read
+    ///
+    /// A return value of `0` indicates the end of the file
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        // NOTE(min) don't trust the host to stay within bounds
+        syscall(OP_READ, self.fd, buf.as_mut_ptr() as u32, buf.len() as u32)
+            .map(|n| (n as usize).min(buf.len()))
+    }
+
+    /// Writes some `bytes` into the file; returns how many bytes were written
+    pub fn write(&mut self, bytes: &[u8]) -> Result<usize, Error> {
+        // NOTE(min) don't trust the host to stay within bounds
+        syscall(OP_WRITE, self.fd, bytes.as_ptr() as u32, bytes.len() as u32)
+            .map(|n| (n as usize).min(bytes.len()))
+    }
+
+    /// Writes all `bytes` into the file
+    pub fn write_all(&mut self, mut bytes: &[u8]) -> Result<(), Error> {
+        while !bytes.is_empty() {
+            let n = self.write(bytes)?;
+            if n == 0 {
+                return Err(Error { _private: () });
+            }
+            bytes = bytes.get(n..).unwrap_or(&[]);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        syscall(OP_CLOSE, self.fd, 0, 0).ok();
+    }
+}
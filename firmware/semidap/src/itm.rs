@@ -0,0 +1,99 @@
+//! Log channels backed by the ITM's stimulus ports; the host captures their output over SWO
+//!
+//! # References
+//!
+//! - Appendix D4 and section C1.7 of the ARMv7-M Architecture Reference Manual (ARM DDI 0403E.b)
+
+// Stimulus Port registers; see 'ITM_STIMx' in the ARMv7-M ARM
+const ITM_STIM: *mut u32 = 0xE000_0000 as *mut u32;
+
+// Trace Enable Register; see 'ITM_TERx' in the ARMv7-M ARM
+const ITM_TER: *const u32 = 0xE000_0E00 as *const u32;
+
+// Trace Control Register; see 'ITM_TCR' in the ARMv7-M ARM
+const ITM_TCR: *const u32 = 0xE000_0E80 as *const u32;
+const ITM_TCR_ITMENA: u32 = 1;
+
+// NOTE the host reads the number of channels from the size of this symbol; the contents are the
+// stimulus port used by each channel
+#[no_mangle]
+static SEMIDAP_ITM: [u8; 2] = [0, 1];
+
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct Channel {
+    port: u8,
+}
+
+impl Channel {
+    pub(crate) fn get(i: usize) -> Self {
+        // NOTE volatile read to keep `SEMIDAP_ITM` in the final binary
+        Self {
+            port: unsafe { (&SEMIDAP_ITM[i] as *const u8).read_volatile() },
+        }
+    }
+
+    // Returns the address of the stimulus port or `None` if the host is not capturing its output
+    fn stim(&self) -> Option<*mut u32> {
+        unsafe {
+            if ITM_TCR.read_volatile() & ITM_TCR_ITMENA == 0
+                || ITM_TER.read_volatile() & (1 << self.port) == 0
+            {
+                // NOTE writes to a disabled port are ignored but reads of a disabled port may
+                // never report that the port is ready; drop the data
+                None
+            } else {
+                Some(ITM_STIM.add(self.port.into()))
+            }
+        }
+    }
+
+    fn push(&self, byte: u8) {
+        if let Some(stim) = self.stim() {
+            unsafe {
+                wait(stim);
+                // 8-bit write: 1-byte payload
+                (stim as *mut u8).write_volatile(byte);
+            }
+        }
+    }
+
+    fn extend_from_slice(&self, bytes: &[u8]) {
+        if let Some(stim) = self.stim() {
+            // NOTE 32-bit writes result in the smallest overhead: one header byte per 4 bytes of
+            // payload
+            let mut chunks = bytes.chunks_exact(4);
+            for chunk in &mut chunks {
+                unsafe {
+                    wait(stim);
+                    stim.write_volatile(u32::from_le_bytes([
+                        chunk[0], chunk[1], chunk[2], chunk[3],
+                    ]));
+                }
+            }
+
+            for byte in chunks.remainder() {
+                unsafe {
+                    wait(stim);
+                    (stim as *mut u8).write_volatile(*byte);
+                }
+            }
+        }
+    }
+}
+
+// waits until the stimulus port can accept more data
+unsafe fn wait(stim: *mut u32) {
+    // FIFOREADY bit
+    while stim.read_volatile() & 1 == 0 {}
+}
+
+impl binfmt::binWrite for Channel {
+    fn write_byte(&mut self, byte: u8) {
+        self.push(byte)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes)
+    }
+}
@@ -4,14 +4,25 @@
 #![deny(warnings)]
 #![no_std]
 
-use core::{
-    cell::{Cell, UnsafeCell},
-    mem::MaybeUninit,
-};
-
 #[doc(hidden)]
 pub use binfmt::{binWrite, binwrite, Level};
 
+pub mod env;
+pub mod fs;
+#[cfg(feature = "itm")]
+mod itm;
+#[cfg(not(feature = "itm"))]
+mod ram;
+pub mod stdin;
+pub mod time;
+
+#[cfg(feature = "itm")]
+#[doc(hidden)]
+pub use itm::Channel;
+#[cfg(not(feature = "itm"))]
+#[doc(hidden)]
+pub use ram::Channel;
+
 /// Logs the formatted string at the `Debug` log level
 ///
 /// A newline will be appended to the end of the format string
@@ -135,133 +146,58 @@ pub fn exit(code: i32) -> ! {
     unsafe { __exit(code) }
 }
 
+/// Implementation detail of the `harness` crate
+///
+/// Reports to the host that the test named `name` is about to start
 #[doc(hidden)]
-#[derive(Clone, Copy)]
-pub struct Channel {
-    // the host never modifies these fields
-    bufferp: *mut u8,
-    write: &'static Cell<u16>,
-    // NOTE the `read` pointer is maintained in host memory
+#[inline(always)]
+pub fn test_start(name: &str, should_panic: bool) {
+    unsafe { __test(0, name.as_ptr(), name.len(), should_panic) }
 }
 
-/// Implementation detail
-/// # Safety
-/// None of `Channel` methods are re-entrant safe
+/// Implementation detail of the `harness` crate
+///
+/// Reports to the host that the current test ran to completion
 #[doc(hidden)]
-pub fn stdout() -> Channel {
-    if in_thread_mode() {
-        unsafe { CHANNELS[0] }
-    } else {
-        // TODO one channel per priority level
-        unsafe { CHANNELS[1] }
-    }
+#[inline(always)]
+pub fn test_end() {
+    unsafe { __test(1, core::ptr::null(), 0, false) }
 }
 
-// NOTE per HID transaction the host will drain 37-48B from the circular buffer (assuming 64B HID
-// packets)
-// NOTE for performance `CAPACITY` should be a power of 2
-const CAPACITY: u16 = 4096;
-
-#[no_mangle]
-static mut SEMIDAP_CURSOR: [Cell<u16>; 2] = [Cell::new(0), Cell::new(0)];
-// NOTE buffers must be aligned so that they never span over a 4KB address boundary
-// for example we don't want a buffer with this address range: `0x2000_0fe0..0x2000_1020`
-#[repr(align(4096))]
-struct Align<T>(T);
-#[link_section = ".uninit.SEMIDAP_BUFFER"]
-#[no_mangle]
-static mut SEMIDAP_BUFFER: [UnsafeCell<MaybeUninit<Align<[u8; CAPACITY as usize]>>>; 2] = [
-    UnsafeCell::new(MaybeUninit::uninit()),
-    UnsafeCell::new(MaybeUninit::uninit()),
-];
-
-static mut CHANNELS: [Channel; 2] = unsafe {
-    [
-        Channel {
-            write: &SEMIDAP_CURSOR[0],
-            bufferp: &SEMIDAP_BUFFER[0] as *const _ as *mut u8,
-        },
-        Channel {
-            write: &SEMIDAP_CURSOR[1],
-            bufferp: &SEMIDAP_BUFFER[1] as *const _ as *mut u8,
-        },
-    ]
-};
-
-impl Channel {
-    fn push(&self, byte: u8) {
-        let write = self.write.get();
-        let cursor = write % CAPACITY;
-        unsafe { self.bufferp.add(cursor.into()).write(byte) }
-        self.write.set(write.wrapping_add(1));
-    }
-
-    fn extend_from_slice(&self, bytes: &[u8]) {
-        // NOTE we assume that `bytes.len` is less than `u16::max_value` which
-        // is very likely to be the case as logs are compressed
-        let len = bytes.len() as u16;
-        let write = self.write.get();
-        let cursor = write % CAPACITY;
-
-        // NOTE it might be worth the do writes in `HID_PACKET_SIZE` chunks to
-        // improve the chances of the host advancing its `read` pointer during
-        // the execution of this method. OTOH, it's very unlikely that
-        // `bytes.len()` will be greater than `HID_PACKET_SIZE`
-        if cursor + len > CAPACITY {
-            // split memcpy
-            // NOTE here we assume that `bytes.len()` is less than `CAPACITY`.
-            // When that's not the case the second `memcpy` could result in an
-            // out of bounds write. It's very unlikely that `bytes.len()` will
-            // ever be greater than `CAPACITY` because logs are compressed
-            let pivot = CAPACITY.wrapping_sub(cursor);
-            unsafe {
-                memcpy(
-                    bytes.as_ptr(),
-                    self.bufferp.add(cursor.into()),
-                    pivot.into(),
-                );
-                memcpy(
-                    bytes.as_ptr().add(pivot.into()),
-                    self.bufferp,
-                    (len - pivot).into(),
-                );
-            }
-        } else {
-            // single memcpy
-            unsafe { memcpy(bytes.as_ptr(), self.bufferp.add(cursor.into()), len.into()) }
-        }
+extern "C" {
+    fn __test(event: u32, name: *const u8, len: usize, should_panic: bool);
+}
 
-        // NOTE we want the `write` cursor to always be updated after `bufferp`.
-        // we may need a compiler barrier here
-        self.write.set(write.wrapping_add(len));
-    }
+/// Error returned by the host I/O operations
+pub struct Error {
+    _private: (),
 }
 
-impl binfmt::binWrite for Channel {
-    fn write_byte(&mut self, byte: u8) {
-        self.push(byte)
+// Host I/O system call; pointers and lengths are passed in registers, the host reads and writes
+// the data they point to directly from / into target memory
+fn syscall(op: u32, r1: u32, r2: u32, r3: u32) -> Result<u32, Error> {
+    extern "C" {
+        fn __syscall(r0: u32, r1: u32, r2: u32, r3: u32) -> i32;
     }
 
-    fn write(&mut self, bytes: &[u8]) {
-        self.extend_from_slice(bytes)
+    let r0 = unsafe { __syscall(op, r1, r2, r3) };
+    if r0 < 0 {
+        Err(Error { _private: () })
+    } else {
+        Ok(r0 as u32)
     }
 }
 
-// opt-level = 3
-#[cfg(not(debug_assertions))]
-unsafe fn memcpy(src: *const u8, dst: *mut u8, len: usize) {
-    // lowers to `__aebi_memcpy`
-    core::ptr::copy_nonoverlapping(src, dst, len);
-}
-
-// opt-level = 'z'
-#[cfg(debug_assertions)]
-unsafe fn memcpy(mut src: *const u8, mut dst: *mut u8, len: usize) {
-    // lowers to a tight loop (less instructions than `__aeabi_memcpy`)
-    for _ in 0..len {
-        dst.write_volatile(src.read_volatile());
-        dst = dst.add(1);
-        src = src.add(1);
+/// Implementation detail
+/// # Safety
+/// None of `Channel` methods are re-entrant safe
+#[doc(hidden)]
+pub fn stdout() -> Channel {
+    if in_thread_mode() {
+        Channel::get(0)
+    } else {
+        // TODO one channel per priority level
+        Channel::get(1)
     }
 }
 
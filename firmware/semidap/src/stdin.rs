@@ -0,0 +1,128 @@
+//! Data sent from the host's standard input
+//!
+//! `semidap` writes the bytes it reads from its standard input into a circular buffer in target
+//! memory. The host owns the write cursor; the target owns the read cursor.
+//!
+//! After it writes data into the buffer the host pends the interrupt passed to `take`; that
+//! interrupt's handler must call `wake`
+
+use core::{
+    cell::UnsafeCell,
+    cmp,
+    future::Future,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    pin::Pin,
+    sync::atomic::{self, AtomicBool, AtomicU32, Ordering},
+    task::{Context, Poll},
+};
+
+use async_core::waker::AtomicWaker;
+
+// NOTE must be a power of 2
+const CAPACITY: u32 = 256;
+
+// [write (host), read (target)]
+#[no_mangle]
+static SEMIDAP_STDIN_CURSOR: [AtomicU32; 2] = [AtomicU32::new(0), AtomicU32::new(0)];
+#[link_section = ".uninit.SEMIDAP_STDIN_BUFFER"]
+#[no_mangle]
+static mut SEMIDAP_STDIN_BUFFER: UnsafeCell<MaybeUninit<[u8; CAPACITY as usize]>> =
+    UnsafeCell::new(MaybeUninit::uninit());
+// the interrupt that the host pends after it writes data into the buffer
+#[no_mangle]
+static SEMIDAP_STDIN_IRQ: AtomicU32 = AtomicU32::new(NO_IRQ);
+
+// NOTE must match the value used by the host
+const NO_IRQ: u32 = u32::MAX;
+
+// the task waiting for data
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Handle to the host's standard input
+pub struct Stdin {
+    _not_send_or_sync: PhantomData<*mut ()>,
+}
+
+/// Claims the handle to the host's standard input
+///
+/// The host pends interrupt number `irq` after it writes data into the buffer. The caller must
+/// install a handler for that interrupt that calls `wake` and unmask the interrupt. This returns
+/// `Some` at most once
+pub fn take(irq: u16) -> Option<Stdin> {
+    static TAKEN: AtomicBool = AtomicBool::new(false);
+
+    if TAKEN.swap(true, Ordering::Relaxed) {
+        None
+    } else {
+        SEMIDAP_STDIN_IRQ.store(u32::from(irq), Ordering::Relaxed);
+        Some(Stdin {
+            _not_send_or_sync: PhantomData,
+        })
+    }
+}
+
+/// Wakes up the task waiting for data
+///
+/// This must be called from the handler of the interrupt passed to `take`
+pub fn wake() {
+    WAKER.wake()
+}
+
+impl Stdin {
+    /// Moves the data that's currently available into `buf`; returns how many bytes were read
+    ///
+    /// This returns `0` if no data is available
+    pub fn try_read(&mut self, buf: &mut [u8]) -> usize {
+        let write = SEMIDAP_STDIN_CURSOR[0].load(Ordering::Relaxed);
+        let read = SEMIDAP_STDIN_CURSOR[1].load(Ordering::Relaxed);
+
+        // NOTE the host writes the data *before* it updates the write cursor
+        atomic::compiler_fence(Ordering::Acquire);
+
+        let available = write.wrapping_sub(read);
+        let n = cmp::min(available, buf.len() as u32);
+        let bufferp = unsafe { SEMIDAP_STDIN_BUFFER.get() as *const u8 };
+        for (i, byte) in buf.iter_mut().take(n as usize).enumerate() {
+            let cursor = read.wrapping_add(i as u32) % CAPACITY;
+            *byte = unsafe { bufferp.add(cursor as usize).read_volatile() };
+        }
+
+        // NOTE the data must be read *before* the space is released to the host
+        atomic::compiler_fence(Ordering::Release);
+        SEMIDAP_STDIN_CURSOR[1].store(read.wrapping_add(n), Ordering::Relaxed);
+
+        n as usize
+    }
+
+    /// Waits until data is available and then moves it into `buf`; returns how many bytes were
+    /// read
+    ///
+    /// The task waiting on this future is woken up by `wake`
+    pub fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> Read<'a> {
+        Read { stdin: self, buf }
+    }
+}
+
+/// Future returned by `Stdin::read`
+pub struct Read<'a> {
+    buf: &'a mut [u8],
+    stdin: &'a mut Stdin,
+}
+
+impl Future for Read<'_> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<usize> {
+        let this = self.get_mut();
+        if this.buf.is_empty() {
+            return Poll::Ready(0);
+        }
+
+        WAKER.register(cx.waker());
+        match this.stdin.try_read(this.buf) {
+            0 => Poll::Pending,
+            n => Poll::Ready(n),
+        }
+    }
+}
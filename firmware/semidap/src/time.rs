@@ -0,0 +1,17 @@
+//! Host wall-clock time
+
+use core::time::Duration;
+
+use crate::syscall;
+
+// NOTE this must match the value used by the host
+const OP_TIME: u32 = 4;
+
+/// Returns the host's wall-clock time as a duration since the UNIX epoch
+pub fn now() -> Duration {
+    // (seconds, nanoseconds)
+    let mut time = [0u32; 2];
+    // NOTE this operation can't fail
+    syscall(OP_TIME, time.as_mut_ptr() as u32, 0, 0).ok();
+    Duration::new(time[0].into(), time[1])
+}
@@ -1,3 +1,118 @@
+//! Interrupt handlers declared as a module
+//!
+//! `#[tasks::declare]` turns the functions of a module into interrupt handlers plus an `init`
+//! function that runs before interrupts are enabled. The `static mut` variables declared at the
+//! module level are shared by the functions that use them.
+//!
+//! # Priorities
+//!
+//! Interrupt handlers can declare a priority between `1` (lowest) and `8` (highest) with the
+//! `#[priority = N]` attribute; the default is `8`. `init` sets the NVIC priorities.
+//!
+//! A function gets a mutable reference to a shared static if no function that runs at a higher
+//! priority uses it; otherwise it gets a `Lock`. The data behind the `Lock` can only be accessed
+//! while the higher priority handlers that use it are masked. Statics shared between functions
+//! that run at different priorities must be `Send`
+//!
+//! A function uses a static if the static's name appears in its body. The check is syntactic: a
+//! local variable or a path segment that happens to have the same name as a static also counts
+//! as a use. This errs on the safe side -- the function may get a `Lock` it doesn't need or make
+//! another function use a `Lock` -- because a function can only reach a static through its name
+//!
+//! ``` ignore
+//! #[tasks::declare]
+//! mod task {
+//!     static mut COUNT: u32 = 0;
+//!
+//!     #[priority = 1]
+//!     fn RTC0() {
+//!         let count = COUNT.lock(|count| *count);
+//!         // ..
+//!     }
+//!
+//!     #[priority = 2]
+//!     fn TIMER0() {
+//!         *COUNT += 1;
+//!     }
+//! }
+//! ```
+
+#![deny(missing_docs)]
 #![no_std]
 
+use core::{
+    marker::PhantomData,
+    sync::atomic::{self, Ordering},
+};
+
+use cm::{nvic::IPR0, NVIC};
+
+/// Declares a set of interrupt handlers that share state
 pub use tasks_macros::declare;
+
+/// Access to a shared static that higher priority interrupt handlers also use
+pub struct Lock<'a, T> {
+    data: *mut T,
+    // the higher priority interrupts that use the data: interrupts 0..32 and 32..64
+    masks: [u32; 2],
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Lock<'a, T> {
+    /// Implementation detail
+    ///
+    /// # Safety
+    ///
+    /// `masks` must include all the higher priority interrupts that access `data`
+    #[doc(hidden)]
+    pub unsafe fn new(data: *mut T, masks: [u32; 2]) -> Self {
+        Self {
+            data,
+            masks,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Runs `f` with the higher priority interrupts that access the data masked
+    pub fn lock<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        let [mask0, mask1] = self.masks;
+
+        // NOTE(borrow_unchecked) single-instruction reads and writes
+        // only unmask the interrupts that were unmasked; this makes nested locks work
+        let (unmask0, unmask1) = NVIC::borrow_unchecked(|nvic| {
+            let enabled = (nvic.ISER0.read() & mask0, nvic.ISER1.read() & mask1);
+            nvic.ICER0.write(mask0);
+            nvic.ICER1.write(mask1);
+            enabled
+        });
+        // the writes to ICER take effect some cycles later; make sure the interrupts are masked
+        // before the data is accessed
+        asm::dsb();
+        asm::isb();
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        // NOTE(unsafe) the interrupts that access `data` can't preempt this context
+        let r = f(unsafe { &mut *self.data });
+
+        atomic::compiler_fence(Ordering::SeqCst);
+        NVIC::borrow_unchecked(|nvic| unsafe {
+            nvic.ISER0.write(unmask0);
+            nvic.ISER1.write(unmask1);
+        });
+
+        r
+    }
+}
+
+/// Implementation detail
+///
+/// # Safety
+///
+/// Changing the priority of an interrupt can break priority-based critical sections
+#[doc(hidden)]
+pub unsafe fn set_priority(nr: u8, hw_priority: u8) {
+    // NOTE the priority registers are byte accessible
+    (IPR0::address() as *mut u8)
+        .add(usize::from(nr))
+        .write_volatile(hw_priority);
+}
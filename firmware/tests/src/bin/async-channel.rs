@@ -7,7 +7,7 @@ use panic_never as _; // this program contains zero core::panic* calls
 
 #[no_mangle]
 fn main() -> ! {
-    let mut ch = Channel::new();
+    let mut ch = Channel::<_, 1>::new();
     let (mut s, mut r) = ch.split();
 
     let a = async {
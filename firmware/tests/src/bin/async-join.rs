@@ -0,0 +1,38 @@
+//! (test) `join` waits for all the futures to complete
+
+#![no_main]
+#![no_std]
+
+use core::time::Duration;
+
+use async_core::future;
+use hal::timer::Timer;
+use panic_never as _; // this program contains zero core::panic* calls
+
+#[no_mangle]
+fn main() -> ! {
+    let mut short = Timer::claim();
+    let mut long = Timer::claim();
+
+    let a = async {
+        let (x, y): (u32, u32) = future::join(
+            async {
+                long.wait(Duration::from_millis(100)).await;
+                semidap::info!("long timer expired");
+                1
+            },
+            async {
+                short.wait(Duration::from_millis(10)).await;
+                semidap::info!("short timer expired");
+                2
+            },
+        )
+        .await;
+
+        semidap::info!("joined: {} {}", x, y);
+
+        semidap::exit(0)
+    };
+
+    executor::run!(a)
+}
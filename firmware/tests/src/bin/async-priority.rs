@@ -0,0 +1,59 @@
+//! (test) A task running at a higher priority preempts the thread mode task that wakes it
+
+#![no_main]
+#![no_std]
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use async_core::{task, waker::AtomicWaker};
+use hal as _; // memory layout
+use panic_never as _; // this program contains zero core::panic* calls
+
+static READY: AtomicBool = AtomicBool::new(false);
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+#[no_mangle]
+fn main() -> ! {
+    let a = async {
+        semidap::info!("A: before wake");
+        READY.store(true, Ordering::Relaxed);
+        WAKER.wake();
+        // unreachable: `B` ends the program
+        semidap::info!("A: after wake");
+
+        loop {
+            task::r#yield().await;
+        }
+    };
+
+    let b = || async {
+        semidap::info!("B: before wait");
+        Wait.await;
+        semidap::info!("B: after wait");
+
+        semidap::exit(0)
+    };
+
+    executor::run!(a; SWI0_EGU0 (priority = 1): b)
+}
+
+struct Wait;
+
+impl Future for Wait {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        WAKER.register(cx.waker());
+
+        if READY.load(Ordering::Relaxed) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
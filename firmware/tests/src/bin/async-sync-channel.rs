@@ -0,0 +1,40 @@
+//! (test) A task running from an interrupt handler sends messages to a thread mode task
+
+#![no_main]
+#![no_std]
+
+use core::future;
+
+use async_core::sync::spsc::Channel;
+use hal as _; // memory layout
+use panic_never as _; // this program contains zero core::panic* calls
+
+static CHANNEL: Channel<i32, 2> = Channel::new();
+
+#[no_mangle]
+fn main() -> ! {
+    // NOTE(unsafe) the only `Sender` and `Receiver`
+    let mut s = unsafe { CHANNEL.sender() };
+    let mut r = unsafe { CHANNEL.receiver() };
+
+    let a = async move {
+        for _ in 0..3 {
+            let m = r.recv().await;
+            semidap::info!("A: received `{}`", m);
+        }
+
+        semidap::exit(0)
+    };
+
+    let b = move || async move {
+        for m in 0..3 {
+            // blocks on the third message until `A` makes room for it
+            s.send(m).await;
+            semidap::info!("B: sent `{}`", m);
+        }
+
+        future::pending::<()>().await
+    };
+
+    executor::run!(a; SWI0_EGU0 (priority = 1): b)
+}
@@ -0,0 +1,32 @@
+//! (test) `Timer::timeout` cancels the operation if the timeout elapses first
+
+#![no_main]
+#![no_std]
+
+use core::time::Duration;
+
+use hal::timer::Timer;
+use panic_never as _; // this program contains zero core::panic* calls
+
+#[no_mangle]
+fn main() -> ! {
+    let mut timer = Timer::claim();
+    let mut other = Timer::claim();
+
+    let a = async {
+        let short = Duration::from_millis(10);
+        let long = Duration::from_millis(100);
+
+        if timer.timeout(short, other.wait(long)).await.is_err() {
+            semidap::info!("timed out");
+        }
+
+        if timer.timeout(long, other.wait(short)).await.is_ok() {
+            semidap::info!("completed");
+        }
+
+        semidap::exit(0)
+    };
+
+    executor::run!(a)
+}
@@ -0,0 +1,34 @@
+//! (test) On-target test harness
+
+#![no_main]
+#![no_std]
+
+use hal as _; // memory layout
+use panic_abort as _; // panic handler
+
+#[harness::tests]
+mod tests {
+    fn assert() {
+        semidap::assert!(1 + 1 == 2, "math is broken");
+    }
+
+    #[should_panic]
+    fn core_panic() {
+        let x: Option<u8> = None;
+        x.unwrap();
+    }
+
+    #[should_panic]
+    fn semidap_panic() {
+        semidap::panic!("bye")
+    }
+
+    fn after_panic() {
+        semidap::info!("tests that run after a panic start from a clean state");
+    }
+}
+
+#[no_mangle]
+fn main() -> ! {
+    harness::run()
+}
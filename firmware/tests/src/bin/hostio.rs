@@ -0,0 +1,46 @@
+//! (test) Host I/O: command-line arguments, wall-clock time and file system access
+//!
+//! Run with `cargo run --bin hostio -- foo bar` after adding `--fs-root .` to the runner
+
+#![no_main]
+#![no_std]
+
+use hal as _; // memory layout
+use panic_never as _; // this program contains zero core::panic* calls
+use semidap::{env, fs::File, time};
+
+#[no_mangle]
+fn main() -> ! {
+    semidap::info!(
+        "seconds since the UNIX epoch: {}",
+        time::now().as_secs() as u32
+    );
+
+    let mut file = if let Ok(file) = File::create("hostio.txt") {
+        file
+    } else {
+        semidap::error!("could not create file; is `--fs-root` set?");
+        semidap::exit(1)
+    };
+
+    // write the arguments into the file, one per line
+    let mut buf = [0; 32];
+    for i in 0..env::args_len() {
+        if let Some(arg) = env::arg(i, &mut buf) {
+            if file.write_all(arg.as_bytes()).is_err() || file.write_all(b"\n").is_err() {
+                semidap::error!("write failed");
+                semidap::exit(1)
+            }
+        }
+    }
+    drop(file);
+
+    if let Ok(mut file) = File::open("hostio.txt") {
+        let mut buf = [0; 64];
+        if let Some(bytes) = file.read(&mut buf).ok().and_then(|n| buf.get(..n)) {
+            semidap::info!("read back: {}", bytes);
+        }
+    }
+
+    semidap::exit(0)
+}
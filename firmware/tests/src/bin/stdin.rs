@@ -0,0 +1,45 @@
+//! (test) Reads the host's standard input
+//!
+//! Try `echo hello | cargo run --bin stdin`
+
+#![no_main]
+#![no_std]
+
+use cm::NVIC;
+use hal as _; // memory layout
+use panic_never as _; // this program contains zero core::panic* calls
+
+// the interrupt that the host pends when new data arrives
+const SWI0: u16 = 20;
+
+#[no_mangle]
+fn main() -> ! {
+    // NOTE(borrow_unchecked) single-instruction write
+    NVIC::borrow_unchecked(|nvic| unsafe { nvic.ISER0.write(1 << SWI0) });
+
+    let a = async {
+        let mut stdin = if let Some(stdin) = semidap::stdin::take(SWI0) {
+            stdin
+        } else {
+            semidap::abort()
+        };
+
+        let mut buf = [0; 64];
+        loop {
+            let n = stdin.read(&mut buf).await;
+            semidap::info!("received: {}", &buf[..n]);
+
+            if buf[..n].contains(&b'\n') {
+                semidap::exit(0)
+            }
+        }
+    };
+
+    executor::run!(a)
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+fn SWI0_EGU0() {
+    semidap::stdin::wake()
+}
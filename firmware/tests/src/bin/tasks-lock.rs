@@ -0,0 +1,61 @@
+//! (test) A low priority handler `lock`s a static that a higher priority handler updates
+
+#![no_main]
+#![no_std]
+
+use cm::NVIC;
+use hal as _; // memory layout
+use panic_never as _; // this program contains zero core::panic* calls
+
+// interrupt numbers
+const SWI0: u8 = 20;
+const SWI1: u8 = 21;
+
+fn pend(nr: u8) {
+    // NOTE(borrow_unchecked) single-instruction write
+    NVIC::borrow_unchecked(|nvic| nvic.ISPR0.write(1 << nr));
+}
+
+#[no_mangle]
+fn main() -> ! {
+    pend(SWI0);
+
+    // unreachable: `SWI0_EGU0` ends the program
+    loop {
+        asm::wfi()
+    }
+}
+
+#[tasks::declare]
+mod task {
+    use cm::NVIC;
+
+    use super::{pend, SWI0, SWI1};
+
+    static mut COUNT: u32 = 0;
+
+    fn init() {
+        // NOTE(borrow_unchecked) single-instruction write
+        NVIC::borrow_unchecked(|nvic| unsafe { nvic.ISER0.write(1 << SWI0 | 1 << SWI1) });
+    }
+
+    #[priority = 1]
+    fn SWI0_EGU0() {
+        let locked = COUNT.lock(|count| {
+            // `SWI1_EGU1` is masked so it can't run until the lock is released
+            pend(SWI1);
+            semidap::info!("locked: COUNT = {}", *count);
+            *count
+        });
+
+        let released = COUNT.lock(|count| *count);
+        semidap::info!("released: COUNT = {}", released);
+
+        semidap::exit(if locked == 0 && released == 1 { 0 } else { 1 })
+    }
+
+    #[priority = 2]
+    fn SWI1_EGU1() {
+        *COUNT += 1;
+    }
+}
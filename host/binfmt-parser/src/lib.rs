@@ -53,6 +53,17 @@ impl Message<'_> {
             }
         }
     }
+
+    /// Formats the message without its timestamp and log level
+    pub fn text(&self) -> String {
+        let args = self
+            .args
+            .iter()
+            .map(|node| node.to_string())
+            .collect::<Vec<_>>();
+
+        dynfmt(self.footprint, &args)
+    }
 }
 
 impl fmt::Display for Message<'_> {
@@ -82,13 +93,7 @@ impl fmt::Display for Message<'_> {
             Level::Warn => write!(f, "{}  ", "WARN".yellow())?,
         }
 
-        let args = self
-            .args
-            .iter()
-            .map(|node| node.to_string())
-            .collect::<Vec<_>>();
-
-        f.write_str(&dynfmt(self.footprint, &args))
+        f.write_str(&self.text())
     }
 }
 
@@ -64,8 +64,113 @@ pub enum Register {
     /// Program Status Register
     XPSR = 0b10000,
 
+    /// Main Stack Pointer
+    MSP = 0b10001,
+
+    /// Process Stack Pointer
+    PSP = 0b10010,
+
     /// CONTROL - FAULTMASK - BASEPRI - PRIMASK
     CFBP = 0b10100,
+
+    /// Floating-point Status and Control Register
+    FPSCR = 0b100001,
+
+    /// FP register S0
+    S0 = 0b1000000,
+
+    /// FP register S1
+    S1 = 0b1000001,
+
+    /// FP register S2
+    S2 = 0b1000010,
+
+    /// FP register S3
+    S3 = 0b1000011,
+
+    /// FP register S4
+    S4 = 0b1000100,
+
+    /// FP register S5
+    S5 = 0b1000101,
+
+    /// FP register S6
+    S6 = 0b1000110,
+
+    /// FP register S7
+    S7 = 0b1000111,
+
+    /// FP register S8
+    S8 = 0b1001000,
+
+    /// FP register S9
+    S9 = 0b1001001,
+
+    /// FP register S10
+    S10 = 0b1001010,
+
+    /// FP register S11
+    S11 = 0b1001011,
+
+    /// FP register S12
+    S12 = 0b1001100,
+
+    /// FP register S13
+    S13 = 0b1001101,
+
+    /// FP register S14
+    S14 = 0b1001110,
+
+    /// FP register S15
+    S15 = 0b1001111,
+
+    /// FP register S16
+    S16 = 0b1010000,
+
+    /// FP register S17
+    S17 = 0b1010001,
+
+    /// FP register S18
+    S18 = 0b1010010,
+
+    /// FP register S19
+    S19 = 0b1010011,
+
+    /// FP register S20
+    S20 = 0b1010100,
+
+    /// FP register S21
+    S21 = 0b1010101,
+
+    /// FP register S22
+    S22 = 0b1010110,
+
+    /// FP register S23
+    S23 = 0b1010111,
+
+    /// FP register S24
+    S24 = 0b1011000,
+
+    /// FP register S25
+    S25 = 0b1011001,
+
+    /// FP register S26
+    S26 = 0b1011010,
+
+    /// FP register S27
+    S27 = 0b1011011,
+
+    /// FP register S28
+    S28 = 0b1011100,
+
+    /// FP register S29
+    S29 = 0b1011101,
+
+    /// FP register S30
+    S30 = 0b1011110,
+
+    /// FP register S31
+    S31 = 0b1011111,
 }
 
 impl Register {
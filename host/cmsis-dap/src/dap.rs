@@ -4,7 +4,7 @@ use core::{cmp::Ordering, fmt};
 
 use anyhow::anyhow;
 use arrayref::array_ref;
-use log::debug;
+use log::{debug, trace};
 
 use crate::{adiv5, sealed::Data as _};
 
@@ -25,6 +25,13 @@ pub(crate) enum Command {
     DAP_SWJ_Clock = 0x11,
     DAP_SWJ_Sequence = 0x12,
 
+    DAP_SWO_Transport = 0x17,
+    DAP_SWO_Mode = 0x18,
+    DAP_SWO_Baudrate = 0x19,
+    DAP_SWO_Control = 0x1A,
+    DAP_SWO_Status = 0x1B,
+    DAP_SWO_Data = 0x1C,
+
     DAP_ExecuteCommands = 0x7F,
 }
 
@@ -46,6 +53,7 @@ impl PartialEq<Command> for u8 {
 
 const DAP_INFO_CAPABILITIES: u8 = 0xf0;
 pub(crate) const CAPABILITIES_SWD: u8 = 1;
+pub(crate) const CAPABILITIES_SWO_UART: u8 = 1 << 2;
 pub(crate) const CAPABILITIES_ATOMIC: u8 = 1 << 4;
 
 // const DAP_INFO_PACKET_COUNT: u8 = 0xfe;
@@ -185,6 +193,153 @@ impl crate::Dap {
     }
 }
 
+/* # SWO commands */
+
+/* ## DAP_SWO_Transport */
+// const DAP_SWO_TRANSPORT_NONE: u8 = 0;
+const DAP_SWO_TRANSPORT_DATA: u8 = 1;
+// const DAP_SWO_TRANSPORT_ENDPOINT: u8 = 2;
+
+/* ## DAP_SWO_Mode */
+const DAP_SWO_MODE_OFF: u8 = 0;
+const DAP_SWO_MODE_UART: u8 = 1;
+// const DAP_SWO_MODE_MANCHESTER: u8 = 2;
+
+/* ## DAP_SWO_Control */
+const DAP_SWO_CONTROL_STOP: u8 = 0;
+const DAP_SWO_CONTROL_START: u8 = 1;
+
+/* ## DAP_SWO_Status */
+/// Trace capture is active
+pub const SWO_STATUS_ACTIVE: u8 = 1;
+/// Trace stream error
+pub const SWO_STATUS_ERROR: u8 = 1 << 6;
+/// Trace buffer overrun
+pub const SWO_STATUS_OVERRUN: u8 = 1 << 7;
+
+impl crate::Dap {
+    /// Selects the `DAP_SWO_Data` command as the transport of the captured SWO data
+    pub fn swo_transport(&mut self) -> Result<(), anyhow::Error> {
+        const CMD: Command = Command::DAP_SWO_Transport;
+
+        debug!("{:?} DAP_SWO_Data", CMD);
+        self.hid_push(CMD);
+        self.hid_push(DAP_SWO_TRANSPORT_DATA);
+        self.hid_flush()?;
+        self.check_response(CMD)
+    }
+
+    /// Selects the UART (NRZ) mode, if `uart` is `true`, or turns SWO capture off
+    pub fn swo_mode(&mut self, uart: bool) -> Result<(), anyhow::Error> {
+        const CMD: Command = Command::DAP_SWO_Mode;
+
+        let mode = if uart {
+            DAP_SWO_MODE_UART
+        } else {
+            DAP_SWO_MODE_OFF
+        };
+        debug!("{:?} {}", CMD, mode);
+        self.hid_push(CMD);
+        self.hid_push(mode);
+        self.hid_flush()?;
+        self.check_response(CMD)
+    }
+
+    /// Sets the SWO baud rate; returns the actual baud rate, which may differ from the requested
+    /// one
+    pub fn swo_baudrate(&mut self, baudrate: u32) -> Result<u32, anyhow::Error> {
+        const CMD: Command = Command::DAP_SWO_Baudrate;
+
+        debug!("{:?} {}", CMD, baudrate);
+        self.hid_push(CMD);
+        self.hid_push(baudrate);
+        self.hid_flush()?;
+
+        // CMD_DAP_SWO_BAUDRATE: u8 - Baudrate: u32
+        let resp = self.hid_read(5)?;
+        if resp[0] != CMD {
+            return Err(anyhow!("`{:?}` failed", CMD));
+        }
+        let actual = u32::from_le_bytes(*array_ref!(resp, 1, 4));
+        if actual == 0 {
+            return Err(anyhow!(
+                "DAP does not support a SWO baud rate of {}",
+                baudrate
+            ));
+        }
+
+        debug!("... {}", actual);
+
+        Ok(actual)
+    }
+
+    /// Starts (`true`) or stops (`false`) the SWO capture
+    pub fn swo_control(&mut self, start: bool) -> Result<(), anyhow::Error> {
+        const CMD: Command = Command::DAP_SWO_Control;
+
+        let control = if start {
+            DAP_SWO_CONTROL_START
+        } else {
+            DAP_SWO_CONTROL_STOP
+        };
+        debug!("{:?} {}", CMD, control);
+        self.hid_push(CMD);
+        self.hid_push(control);
+        self.hid_flush()?;
+        self.check_response(CMD)
+    }
+
+    /// Returns the trace status (see `SWO_STATUS_*`) and the number of bytes in the DAP's trace
+    /// buffer
+    pub fn swo_status(&mut self) -> Result<(u8, u32), anyhow::Error> {
+        const CMD: Command = Command::DAP_SWO_Status;
+
+        self.hid_push(CMD);
+        self.hid_flush()?;
+
+        // CMD_DAP_SWO_STATUS: u8 - Trace Status: u8 - Trace Count: u32
+        let resp = self.hid_read(6)?;
+        if resp[0] != CMD {
+            return Err(anyhow!("`{:?}` failed", CMD));
+        }
+        let status = resp[1];
+        let count = u32::from_le_bytes(*array_ref!(resp, 2, 4));
+
+        Ok((status, count))
+    }
+
+    /// Reads captured SWO data from the DAP's trace buffer; returns the trace status (see
+    /// `SWO_STATUS_*`) and the data
+    pub fn swo_data(&mut self) -> Result<(u8, Vec<u8>), anyhow::Error> {
+        const CMD: Command = Command::DAP_SWO_Data;
+
+        // CMD_DAP_SWO_DATA: u8 - Trace Status: u8 - Trace Count: u16 - Trace Data: [u8]
+        const HEADER: u16 = 4;
+        let max = self.packet_size - HEADER;
+
+        self.hid_push(CMD);
+        self.hid_push(max);
+        self.hid_flush()?;
+
+        let resp = self.hid_read(self.packet_size)?;
+        if resp[0] != CMD {
+            return Err(anyhow!("`{:?}` failed", CMD));
+        }
+        let status = resp[1];
+        let count = u16::from_le_bytes(*array_ref!(resp, 2, 2));
+        if count > max {
+            return Err(anyhow!("`{:?}` returned a malformed response", CMD));
+        }
+        let data = resp[usize::from(HEADER)..usize::from(HEADER + count)].to_owned();
+
+        if count != 0 {
+            trace!("{:?} <{} bytes> (status = {:#04x})", CMD, count, status);
+        }
+
+        Ok((status, data))
+    }
+}
+
 /* # Transfer commands */
 
 /* ## DAP_TransferConfigure */
@@ -13,7 +13,7 @@
 use core::time::Duration;
 use std::thread;
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use hidapi::{HidApi, HidDevice};
 use log::{debug, info};
 
@@ -49,6 +49,45 @@ pub struct Dap {
     debugen: Option<bool>,
 }
 
+/// A CMSIS-DAP Debug Unit connected to the host
+#[derive(Clone, Debug, PartialEq)]
+pub struct Probe {
+    /// USB vendor ID
+    pub vendor: u16,
+    /// USB product ID
+    pub product: u16,
+    /// USB serial number
+    pub serial_number: Option<String>,
+    /// USB product string
+    pub product_string: Option<String>,
+}
+
+fn probes(hid: &HidApi) -> Vec<Probe> {
+    let mut probes = hid
+        .device_list()
+        // "The Product String must contain 'CMSIS-DAP' somewhere in the string. This is used by
+        // the debuggers to identify a CMSIS-DAP compliant Debug Unit", CMSIS-DAP 2.0
+        .filter(|info| {
+            info.product_string()
+                .map(|s| s.contains("CMSIS-DAP"))
+                .unwrap_or(false)
+        })
+        .map(|info| Probe {
+            vendor: info.vendor_id(),
+            product: info.product_id(),
+            serial_number: info.serial_number().map(|s| s.to_owned()),
+            product_string: info.product_string().map(|s| s.to_owned()),
+        })
+        .collect::<Vec<_>>();
+
+    // composite devices show up once per HID interface
+    probes.sort_by(|a, b| {
+        (a.vendor, a.product, &a.serial_number).cmp(&(b.vendor, b.product, &b.serial_number))
+    });
+    probes.dedup();
+    probes
+}
+
 /* # Utility functions */
 // XXX are these reasonable defaults?
 const DEFAULT_SWD_FREQUENCY: u32 = 4_000_000;
@@ -61,9 +100,54 @@ impl crate::Dap {
         let device = if let Some(sn) = sn {
             hid.open_serial(vendor, product, sn)?
         } else {
+            // `HidApi::open` would pick one of the matching devices at random
+            let sns = probes(&hid)
+                .into_iter()
+                .filter(|probe| probe.vendor == vendor && probe.product == product)
+                .map(|probe| probe.serial_number.unwrap_or_else(|| "?".to_owned()))
+                .collect::<Vec<_>>();
+            if sns.len() > 1 {
+                bail!(
+                    "{} DAPs match {:04x}:{:04x}; select one using its serial number ({})",
+                    sns.len(),
+                    vendor,
+                    product,
+                    sns.join(", ")
+                );
+            }
+
             hid.open(vendor, product)?
         };
 
+        Self::new(device)
+    }
+
+    /// Opens all the DAP Debug Units that match the given vendor ID, product ID and serial
+    /// number triples
+    ///
+    /// NOTE use this instead of calling `open` several times; only one instance of the HID API
+    /// can be alive at any given time
+    pub fn open_all(units: &[(u16, u16, &str)]) -> Result<Vec<Self>, anyhow::Error> {
+        let hid = HidApi::new()?;
+        units
+            .iter()
+            .map(|(vendor, product, sn)| {
+                let device = hid
+                    .open_serial(*vendor, *product, sn)
+                    .map_err(|e| anyhow!("couldn't open the DAP with S/N {}: {}", sn, e))?;
+                Self::new(device)
+            })
+            .collect()
+    }
+
+    /// Lists the CMSIS-DAP Debug Units connected to the host
+    ///
+    /// The list is sorted by vendor ID, product ID and serial number
+    pub fn list() -> Result<Vec<Probe>, anyhow::Error> {
+        Ok(probes(&HidApi::new()?))
+    }
+
+    fn new(device: HidDevice) -> Result<Self, anyhow::Error> {
         let mut dap = Self {
             buffer: Box::new([crate::hid::REPORT_ID; 5]),
             device,
@@ -182,6 +266,34 @@ impl crate::Dap {
         Ok(())
     }
 
+    /// Starts capturing the target's SWO output in UART mode at the specified `baudrate`; the
+    /// captured data can then be read using `swo_data`
+    ///
+    /// Returns the actual baud rate
+    ///
+    /// NOTE this only configures the Debug Unit; the target's TPIU must be configured to output
+    /// the trace data at the same baud rate
+    pub fn swo_start(&mut self, baudrate: u32) -> Result<u32, anyhow::Error> {
+        info!("confirming SWO support");
+        if self.capabilities()? & dap::CAPABILITIES_SWO_UART == 0 {
+            bail!("DAP does not support SWO capture in UART mode")
+        }
+
+        self.swo_transport()?;
+        self.swo_mode(true)?;
+        let actual = self.swo_baudrate(baudrate)?;
+        info!("capturing SWO output at {} baud", actual);
+        self.swo_control(true)?;
+
+        Ok(actual)
+    }
+
+    /// Stops capturing the target's SWO output
+    pub fn swo_stop(&mut self) -> Result<(), anyhow::Error> {
+        self.swo_control(false)?;
+        self.swo_mode(false)
+    }
+
     /// Returns `true` if the probe supports atomic commands
     pub fn supports_atomic_commands(&mut self) -> Result<bool, anyhow::Error> {
         if let Some(atomic) = self.caps_atomic {
@@ -2,27 +2,70 @@
 
 extern crate proc_macro;
 
+use std::collections::BTreeSet;
+
 use proc_macro::TokenStream;
 
 use proc_macro2::Span as Span2;
 use proc_macro_hack::proc_macro_hack;
 use quote::{format_ident, quote};
 use syn::{
+    parenthesized,
     parse::{self, Parse, ParseBuffer},
     parse_macro_input,
     punctuated::Punctuated,
-    Ident, Token,
+    token, Ident, LitInt, Token,
 };
 
+// the nRF52840 software interrupts and their position in the vector table
+const SWIS: &[(&str, u8)] = &[
+    ("SWI0_EGU0", 20),
+    ("SWI1_EGU1", 21),
+    ("SWI2_EGU2", 22),
+    ("SWI3_EGU3", 23),
+    ("SWI4_EGU4", 24),
+    ("SWI5_EGU5", 25),
+];
+
+// number of priority bits implemented by the nRF52840
+const NVIC_PRIO_BITS: u8 = 3;
+
 #[proc_macro_hack]
 pub fn run(input: TokenStream) -> TokenStream {
-    let idents = parse_macro_input!(input as Input).idents;
-    let ntasks = idents.len();
+    let input = parse_macro_input!(input as Input);
 
-    if ntasks == 0 {
-        return parse::Error::new(Span2::call_site(), "expected at least one task")
-            .to_compile_error()
-            .into();
+    match expand(input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: Input) -> parse::Result<proc_macro2::TokenStream> {
+    let mut thread = None;
+    let mut groups = vec![];
+    for (i, section) in input.sections.into_iter().enumerate() {
+        match section {
+            Section::Thread(idents) => {
+                if i != 0 {
+                    return Err(parse::Error::new(
+                        idents[0].span(),
+                        "thread mode tasks must be listed first",
+                    ));
+                }
+
+                thread = Some(idents);
+            }
+
+            Section::Interrupt(group) => groups.push(group),
+        }
+    }
+    let thread = thread.unwrap_or_default();
+
+    if thread.is_empty() && groups.is_empty() {
+        return Err(parse::Error::new(
+            Span2::call_site(),
+            "expected at least one task",
+        ));
     }
 
     let mut stmts = vec![];
@@ -31,45 +74,191 @@ pub fn run(input: TokenStream) -> TokenStream {
     let krate = format_ident!("executor");
 
     // check that idents are futures and pin them
-    for ident in idents.iter() {
+    for ident in thread.iter() {
+        let ready = format_ident!("__executor_{}_ready", ident);
+        let waker = format_ident!("__executor_{}_waker", ident);
+        let cx = format_ident!("__executor_{}_cx", ident);
+
         stmts.push(quote!(
             let mut #ident = #krate::check(#ident);
             // the future will never be moved
             let mut #ident = unsafe { core::pin::Pin::new_unchecked(&mut #ident) };
+
+            let #ready = #krate::Ready::new();
+            // NOTE(unsafe) `#ready` is never deallocated because this block never returns
+            let #waker = unsafe { #krate::waker(&#ready) };
+            let mut #cx = core::task::Context::from_waker(&#waker);
         ));
 
         polls.push(quote!(
-            // XXX do we want to prevent futures being polled beyond completion?
-            let _ = #ident.as_mut().poll(&mut cx);
+            if #ready.take() {
+                // XXX do we want to prevent futures being polled beyond completion?
+                let _ = #ident.as_mut().poll(&mut #cx);
+            }
         ));
     }
 
-    stmts.push(quote!(
-        let waker = #krate::waker();
-        let mut cx = core::task::Context::from_waker(&waker);
+    let mut seen = BTreeSet::new();
+    let mut enables = vec![];
+    for group in groups {
+        let interrupt = &group.interrupt;
+        let name = interrupt.to_string();
+        let nr = if let Some((_, nr)) = SWIS.iter().find(|(swi, _)| *swi == name) {
+            *nr
+        } else {
+            return Err(parse::Error::new(
+                interrupt.span(),
+                "only the software interrupts (`SWI0_EGU0` to `SWI5_EGU5`) can run tasks",
+            ));
+        };
 
-        loop {
+        if !seen.insert(nr) {
+            return Err(parse::Error::new(
+                interrupt.span(),
+                "this interrupt is already running tasks",
+            ));
+        }
+
+        let priority = group.priority.base10_parse::<u8>()?;
+        if priority == 0 || priority >= 1 << NVIC_PRIO_BITS {
+            return Err(parse::Error::new(
+                group.priority.span(),
+                format!(
+                    "priority must be in the range 1..={}",
+                    (1 << NVIC_PRIO_BITS) - 1
+                ),
+            ));
+        }
+        // higher logical priority = lower hardware value = more urgent
+        let hw_priority = ((1u8 << NVIC_PRIO_BITS) - priority) << (8 - NVIC_PRIO_BITS);
+
+        let mut group_polls = vec![];
+        for ident in group.tasks.iter() {
+            let ready = format_ident!("__executor_{}_ready", ident);
+            let waker = format_ident!("__executor_{}_waker", ident);
+
+            stmts.push(quote!(
+                let mut #ident = #krate::Task::new(#ident);
+
+                let #ready = #krate::Ready::interrupt(#nr);
+                // NOTE(unsafe) `#ready` is never deallocated because this block never returns
+                let #waker = unsafe { #krate::waker(&#ready) };
+            ));
+
+            group_polls.push(quote!(
+                if #ready.take() {
+                    // NOTE(unsafe) the task is never moved
+                    unsafe {
+                        #ident.poll(&mut core::task::Context::from_waker(&#waker));
+                    }
+                }
+            ));
+        }
+
+        let poll = format_ident!("__executor_{}", interrupt);
+        let handler = format_ident!("__EXECUTOR_{}", interrupt);
+        stmts.push(quote!(
+            let mut #poll = || {
+                #(#group_polls)*
+            };
+
+            static #handler: #krate::Handler = #krate::Handler::new();
+
+            #[allow(non_snake_case)]
+            #[no_mangle]
+            unsafe extern "C" fn #interrupt() {
+                #handler.run()
+            }
+
+            // NOTE(unsafe) `#poll` is never deallocated and from now on only the interrupt handler
+            // accesses it and the tasks it polls
+            unsafe { #handler.install(&mut #poll) }
+        ));
+
+        enables.push(quote!(
+            // NOTE(unsafe) the interrupt handler has been installed
+            unsafe { #krate::enable(#nr, #hw_priority) }
+        ));
+    }
+
+    stmts.extend(enables);
+
+    let import = if polls.is_empty() {
+        None
+    } else {
+        Some(quote!(
             use core::future::Future as _;
+        ))
+    };
+
+    stmts.push(quote!(
+        loop {
+            #import
 
             #(#polls)*
+
+            // NOTE a task woken up after its ready bit was checked also sent an event (`SEV`) so
+            // this won't put the processor to sleep
             #krate::wfe();
         }
     ));
 
-    quote!({
+    Ok(quote!({
         #(#stmts)*
-    })
-    .into()
+    }))
 }
 
 struct Input {
-    idents: Punctuated<Ident, Token![,]>,
+    sections: Punctuated<Section, Token![;]>,
 }
 
 impl Parse for Input {
     fn parse(input: &ParseBuffer) -> parse::Result<Self> {
         Ok(Self {
-            idents: Punctuated::parse_separated_nonempty(input)?,
+            sections: Punctuated::parse_terminated(input)?,
         })
     }
 }
+
+enum Section {
+    // `a, b`
+    Thread(Punctuated<Ident, Token![,]>),
+
+    // `SWI0_EGU0 (priority = 1): c, d`
+    Interrupt(Group),
+}
+
+struct Group {
+    interrupt: Ident,
+    priority: LitInt,
+    tasks: Punctuated<Ident, Token![,]>,
+}
+
+impl Parse for Section {
+    fn parse(input: &ParseBuffer) -> parse::Result<Self> {
+        if input.peek(Ident) && input.peek2(token::Paren) {
+            let interrupt = input.parse()?;
+
+            let content;
+            parenthesized!(content in input);
+            let key = content.parse::<Ident>()?;
+            if key != "priority" {
+                return Err(parse::Error::new(key.span(), "expected `priority`"));
+            }
+            content.parse::<Token![=]>()?;
+            let priority = content.parse()?;
+
+            input.parse::<Token![:]>()?;
+
+            Ok(Section::Interrupt(Group {
+                interrupt,
+                priority,
+                tasks: Punctuated::parse_separated_nonempty(input)?,
+            }))
+        } else {
+            Ok(Section::Thread(Punctuated::parse_separated_nonempty(
+                input,
+            )?))
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use proc_macro::TokenStream;
+
+use proc_macro2::{Ident as Ident2, Span as Span2};
+use quote::quote;
+use syn::{
+    parse::{self, ParseStream},
+    parse_macro_input,
+    spanned::Spanned,
+    ItemFn, ItemMod, ItemUse, ReturnType, Visibility,
+};
+
+#[proc_macro_attribute]
+pub fn tests(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return parse::Error::new(Span2::call_site(), "this attribute takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let input = parse_macro_input!(input as Input);
+
+    let name = &input.name;
+    let uses = &input.uses;
+    let mut fns = vec![];
+    let mut citems = vec![];
+    for (i, test) in input.tests.iter().enumerate() {
+        let f = &test.f;
+        let fname = &f.sig.ident;
+        let tname = format!("{}::{}", name, fname);
+        let should_panic = test.should_panic;
+        // NOTE the linker sorts the tests by section name; this preserves the declaration order
+        let section = format!(".tests.{}.{:04}", name, i);
+
+        fns.push(f);
+        citems.push(quote!(
+            #[link_section = #section]
+            #[used]
+            static TEST: harness::Test = harness::Test {
+                name: #tname,
+                run: #fname,
+                should_panic: #should_panic,
+            };
+        ));
+    }
+
+    quote!(
+        mod #name {
+            #(#uses)*
+
+            #(#fns)*
+
+            #(
+                const _: () = {
+                    #citems
+                };
+            )*
+        }
+    )
+    .into()
+}
+
+struct Input {
+    name: Ident2,
+    tests: Vec<Test>,
+    uses: Vec<ItemUse>,
+}
+
+struct Test {
+    f: ItemFn,
+    should_panic: bool,
+}
+
+impl parse::Parse for Input {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let mod_: ItemMod = input.parse()?;
+
+        if mod_.vis != Visibility::Inherited {
+            return Err(parse::Error::new(mod_.span(), "module must be private"));
+        }
+
+        if !mod_.attrs.is_empty() {
+            return Err(parse::Error::new(
+                mod_.span(),
+                "module must have no attributes",
+            ));
+        }
+
+        if let Some((_, items)) = mod_.content {
+            let mut tests = vec![];
+            let mut uses = vec![];
+            for item in items {
+                match item {
+                    syn::Item::Fn(mut f) => {
+                        let mut should_panic = false;
+                        if let Some(pos) = f.attrs.iter().position(|attr| {
+                            attr.path
+                                .get_ident()
+                                .map(|id| id == "should_panic")
+                                .unwrap_or(false)
+                        }) {
+                            if !f.attrs[pos].tokens.is_empty() {
+                                return Err(parse::Error::new(
+                                    f.attrs[pos].span(),
+                                    "`#[should_panic]` takes no arguments",
+                                ));
+                            }
+
+                            f.attrs.remove(pos);
+                            should_panic = true;
+                        }
+
+                        if !f.attrs.is_empty() {
+                            return Err(parse::Error::new(
+                                f.span(),
+                                "function must have no attributes other than `#[should_panic]`",
+                            ));
+                        }
+
+                        if f.vis != Visibility::Inherited {
+                            return Err(parse::Error::new(f.span(), "function must be private"));
+                        }
+
+                        let sig = &f.sig;
+
+                        if sig.constness.is_some()
+                            || sig.asyncness.is_some()
+                            || sig.unsafety.is_some()
+                            || sig.abi.is_some()
+                            || !sig.generics.params.is_empty()
+                            || sig.generics.where_clause.is_some()
+                            || !sig.inputs.is_empty()
+                            || sig.output != ReturnType::Default
+                        {
+                            return Err(parse::Error::new(
+                                f.span(),
+                                "function must have signature `fn()`",
+                            ));
+                        }
+
+                        tests.push(Test { f, should_panic });
+                    }
+
+                    syn::Item::Use(u) => uses.push(u),
+
+                    _ => {
+                        return Err(parse::Error::new(
+                            item.span(),
+                            "module must only contain functions and `use` items",
+                        ))
+                    }
+                }
+            }
+
+            Ok(Input {
+                name: mod_.ident,
+                tests,
+                uses,
+            })
+        } else {
+            Err(parse::Error::new(mod_.span(), "module must be inline"))
+        }
+    }
+}
@@ -413,71 +413,87 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                 base_address: 0xE000_E100,
             },
             name: "NVIC".into(),
-            registers: vec![
-                // NOTE(unsafe_write) enabling interrupts can break critical section
-                Register {
-                    access: Access::ReadWrite { unsafe_write: true },
-                    description: Some("Interrupt Set-Enable Register 0".into()),
-                    name: "ISER0".into(),
-                    offset: 0x0,
-                    r_fields: vec![],
-                    w_fields: vec![],
-                    width: Width::U32,
-                },
-                Register {
-                    access: Access::ReadWrite { unsafe_write: true },
-                    description: Some("Interrupt Set-Enable Register 1".into()),
-                    name: "ISER1".into(),
-                    offset: 0x4,
-                    r_fields: vec![],
-                    w_fields: vec![],
-                    width: Width::U32,
-                },
-                Register {
-                    access: Access::ReadWrite {
-                        unsafe_write: false,
+            registers: {
+                let mut registers = vec![
+                    // NOTE(unsafe_write) enabling interrupts can break critical section
+                    Register {
+                        access: Access::ReadWrite { unsafe_write: true },
+                        description: Some("Interrupt Set-Enable Register 0".into()),
+                        name: "ISER0".into(),
+                        offset: 0x0,
+                        r_fields: vec![],
+                        w_fields: vec![],
+                        width: Width::U32,
                     },
-                    description: Some("Interrupt Clear-Enable Register 0".into()),
-                    name: "ICER0".into(),
-                    offset: 0x80,
-                    r_fields: vec![],
-                    w_fields: vec![],
-                    width: Width::U32,
-                },
-                Register {
-                    access: Access::ReadWrite {
-                        unsafe_write: false,
+                    Register {
+                        access: Access::ReadWrite { unsafe_write: true },
+                        description: Some("Interrupt Set-Enable Register 1".into()),
+                        name: "ISER1".into(),
+                        offset: 0x4,
+                        r_fields: vec![],
+                        w_fields: vec![],
+                        width: Width::U32,
                     },
-                    description: Some("Interrupt Clear-Enable Register 1".into()),
-                    name: "ICER1".into(),
-                    offset: 0x84,
-                    r_fields: vec![],
-                    w_fields: vec![],
-                    width: Width::U32,
-                },
-                Register {
-                    access: Access::ReadWrite {
-                        unsafe_write: false,
+                    Register {
+                        access: Access::ReadWrite {
+                            unsafe_write: false,
+                        },
+                        description: Some("Interrupt Clear-Enable Register 0".into()),
+                        name: "ICER0".into(),
+                        offset: 0x80,
+                        r_fields: vec![],
+                        w_fields: vec![],
+                        width: Width::U32,
                     },
-                    description: Some("Interrupt Set-Pending Register 0".into()),
-                    name: "ISPR0".into(),
-                    offset: 0x100,
-                    r_fields: vec![],
-                    w_fields: vec![],
-                    width: Width::U32,
-                },
-                Register {
-                    access: Access::ReadWrite {
-                        unsafe_write: false,
+                    Register {
+                        access: Access::ReadWrite {
+                            unsafe_write: false,
+                        },
+                        description: Some("Interrupt Clear-Enable Register 1".into()),
+                        name: "ICER1".into(),
+                        offset: 0x84,
+                        r_fields: vec![],
+                        w_fields: vec![],
+                        width: Width::U32,
                     },
-                    description: Some("Interrupt Set-Pending Register 1".into()),
-                    name: "ISPR1".into(),
-                    offset: 0x104,
+                    Register {
+                        access: Access::ReadWrite {
+                            unsafe_write: false,
+                        },
+                        description: Some("Interrupt Set-Pending Register 0".into()),
+                        name: "ISPR0".into(),
+                        offset: 0x100,
+                        r_fields: vec![],
+                        w_fields: vec![],
+                        width: Width::U32,
+                    },
+                    Register {
+                        access: Access::ReadWrite {
+                            unsafe_write: false,
+                        },
+                        description: Some("Interrupt Set-Pending Register 1".into()),
+                        name: "ISPR1".into(),
+                        offset: 0x104,
+                        r_fields: vec![],
+                        w_fields: vec![],
+                        width: Width::U32,
+                    },
+                ];
+
+                // section B3.4.9 of (ARM); one byte per interrupt
+                // NOTE(unsafe_write) changing priorities can break priority-based critical sections
+                registers.extend((0..12).map(|i| Register {
+                    access: Access::ReadWrite { unsafe_write: true },
+                    description: Some(format!("Interrupt Priority Register {}", i).into()),
+                    name: format!("IPR{}", i).into(),
+                    offset: 0x300 + 4 * i,
                     r_fields: vec![],
                     w_fields: vec![],
                     width: Width::U32,
-                },
-            ],
+                }));
+
+                registers
+            },
         },
         Peripheral {
             description: Some("System Control Block".into()),
@@ -694,6 +710,113 @@ fn peripherals() -> Vec<Peripheral<'static>> {
                         width: Width::U32,
                     }
                 },
+                {
+                    let fields = [
+                        // MemManage Fault Status Register
+                        ("IACCVIOL", 0),
+                        ("DACCVIOL", 1),
+                        ("MUNSTKERR", 3),
+                        ("MSTKERR", 4),
+                        ("MLSPERR", 5),
+                        ("MMARVALID", 7),
+                        // BusFault Status Register
+                        ("IBUSERR", 8),
+                        ("PRECISERR", 9),
+                        ("IMPRECISERR", 10),
+                        ("UNSTKERR", 11),
+                        ("STKERR", 12),
+                        ("LSPERR", 13),
+                        ("BFARVALID", 15),
+                        // UsageFault Status Register
+                        ("UNDEFINSTR", 16),
+                        ("INVSTATE", 17),
+                        ("INVPC", 18),
+                        ("NOCP", 19),
+                        ("UNALIGNED", 24),
+                        ("DIVBYZERO", 25),
+                    ]
+                    .iter()
+                    .map(|(name, offset)| Bitfield {
+                        description: None,
+                        name: (*name).into(),
+                        offset: *offset,
+                        width: 1,
+                    })
+                    .collect::<Vec<_>>();
+
+                    // section B3.2.15 of (ARM)
+                    // NOTE bits are cleared by writing `1` to them
+                    Register {
+                        access: Access::ReadWrite {
+                            unsafe_write: false,
+                        },
+                        description: Some("Configurable Fault Status Register".into()),
+                        name: "CFSR".into(),
+                        offset: 0x28,
+                        r_fields: fields.clone(),
+                        w_fields: fields,
+                        width: Width::U32,
+                    }
+                },
+                {
+                    let mut fields = vec![];
+                    fields.push(Bitfield {
+                        description: None,
+                        name: "VECTTBL".into(),
+                        offset: 1,
+                        width: 1,
+                    });
+                    fields.push(Bitfield {
+                        description: None,
+                        name: "FORCED".into(),
+                        offset: 30,
+                        width: 1,
+                    });
+                    fields.push(Bitfield {
+                        description: None,
+                        name: "DEBUGEVT".into(),
+                        offset: 31,
+                        width: 1,
+                    });
+
+                    // section B3.2.16 of (ARM)
+                    // NOTE bits are cleared by writing `1` to them
+                    Register {
+                        access: Access::ReadWrite {
+                            unsafe_write: false,
+                        },
+                        description: Some("HardFault Status Register".into()),
+                        name: "HFSR".into(),
+                        offset: 0x2c,
+                        r_fields: fields.clone(),
+                        w_fields: fields,
+                        width: Width::U32,
+                    }
+                },
+                // section B3.2.17 of (ARM)
+                Register {
+                    access: Access::ReadWrite {
+                        unsafe_write: false,
+                    },
+                    description: Some("MemManage Fault Address Register".into()),
+                    name: "MMFAR".into(),
+                    offset: 0x34,
+                    r_fields: vec![],
+                    w_fields: vec![],
+                    width: Width::U32,
+                },
+                // section B3.2.18 of (ARM)
+                Register {
+                    access: Access::ReadWrite {
+                        unsafe_write: false,
+                    },
+                    description: Some("BusFault Address Register".into()),
+                    name: "BFAR".into(),
+                    offset: 0x38,
+                    r_fields: vec![],
+                    w_fields: vec![],
+                    width: Width::U32,
+                },
             ],
         },
     ]
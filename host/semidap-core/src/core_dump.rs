@@ -0,0 +1,490 @@
+//! Core dumps: a snapshot of a halted program that can be inspected offline
+//!
+//! The snapshot is stored as an ELF core file: every memory region is a `PT_LOAD` segment and the
+//! registers are stored in notes. Besides the `NT_PRSTATUS` note (`r0`-`pc` and `xPSR`), which
+//! other tools understand, there's a `SEMIDAP` note with all the core registers (including the
+//! special and FP registers) and another one with the path to the program's ELF file.
+//!
+//! NOTE the stacked exception frames live in RAM so they are part of the snapshot
+
+use core::{convert::TryInto, ops::Range};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+use cmsis_dap::cortex_m::Register;
+
+use crate::{elf::Elf, target::Snapshot};
+
+// nRF52840 RAM
+const RAM: Range<u32> = 0x2000_0000..0x2004_0000;
+
+// System Control Block; includes `ICSR` and the fault status registers
+const SCB: Range<u32> = 0xE000_ED00..0xE000_ED90;
+
+// Coprocessor Access Control Register
+const SCB_CPACR: u32 = 0xE000_ED88;
+// CP10 and CP11 (the FPU) access bits
+const CPACR_FPU: u32 = 0b1111 << 20;
+
+const CORE_REGISTERS: &[Register] = &[
+    Register::R0,
+    Register::R1,
+    Register::R2,
+    Register::R3,
+    Register::R4,
+    Register::R5,
+    Register::R6,
+    Register::R7,
+    Register::R8,
+    Register::R9,
+    Register::R10,
+    Register::R11,
+    Register::R12,
+    Register::SP,
+    Register::LR,
+    Register::PC,
+    Register::XPSR,
+    Register::MSP,
+    Register::PSP,
+    Register::CFBP,
+];
+
+const FP_REGISTERS: &[Register] = &[
+    Register::FPSCR,
+    Register::S0,
+    Register::S1,
+    Register::S2,
+    Register::S3,
+    Register::S4,
+    Register::S5,
+    Register::S6,
+    Register::S7,
+    Register::S8,
+    Register::S9,
+    Register::S10,
+    Register::S11,
+    Register::S12,
+    Register::S13,
+    Register::S14,
+    Register::S15,
+    Register::S16,
+    Register::S17,
+    Register::S18,
+    Register::S19,
+    Register::S20,
+    Register::S21,
+    Register::S22,
+    Register::S23,
+    Register::S24,
+    Register::S25,
+    Register::S26,
+    Register::S27,
+    Register::S28,
+    Register::S29,
+    Register::S30,
+    Register::S31,
+];
+
+/* ELF constants */
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_CORE: u16 = 4;
+const EM_ARM: u16 = 40;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+
+const EHDR_SIZE: u16 = 52;
+const PHDR_SIZE: u16 = 32;
+
+// size of `struct elf_prstatus` on 32-bit ARM and offset of its `pr_reg` field
+const PRSTATUS_SIZE: usize = 148;
+const PRSTATUS_PR_REG: usize = 72;
+
+const NOTE_CORE: &str = "CORE";
+const NOTE_SEMIDAP: &str = "SEMIDAP";
+// `(REGSEL: u32, value: u32)` pairs
+const NT_SEMIDAP_REGISTERS: u32 = 1;
+// UTF-8 encoded path
+const NT_SEMIDAP_ELF: u32 = 2;
+
+/// A snapshot of a halted program
+#[derive(Debug, PartialEq)]
+pub struct CoreDump {
+    /// Path to the program's ELF file
+    pub elf: PathBuf,
+    /// `REGSEL` -> value
+    registers: BTreeMap<u8, u32>,
+    /// `(start address, contents)`
+    regions: Vec<(u32, Vec<u8>)>,
+}
+
+impl CoreDump {
+    /// Takes a snapshot of the halted target
+    pub fn capture(target: &mut impl Snapshot, elf: &Path) -> Result<Self, anyhow::Error> {
+        let fpu = target.memory_read_word(SCB_CPACR)? & CPACR_FPU != 0;
+
+        let mut registers = BTreeMap::new();
+        for reg in CORE_REGISTERS
+            .iter()
+            .chain(if fpu { FP_REGISTERS } else { &[] })
+        {
+            registers.insert(*reg as u8, target.read_core_register(*reg)?);
+        }
+
+        let mut regions = vec![];
+        for range in &[RAM, SCB] {
+            let words = target.memory_read_words(range.start, (range.end - range.start) / 4)?;
+            let bytes = words
+                .iter()
+                .flat_map(|word| word.to_le_bytes().to_vec())
+                .collect();
+            regions.push((range.start, bytes));
+        }
+
+        Ok(Self {
+            elf: elf.canonicalize().unwrap_or_else(|_| elf.to_owned()),
+            registers,
+            regions,
+        })
+    }
+
+    /// Checks that the read-only sections of `elf` match the contents of the core dump
+    pub fn check(&self, elf: &Elf) -> Result<(), anyhow::Error> {
+        for section in elf.sections.iter().filter(|section| !section.writable) {
+            let start = section.address;
+            let end = start + section.bytes.len() as u32;
+            if let Some(bytes) = self.bytes(start, end) {
+                if bytes != section.bytes {
+                    bail!(
+                        "section `{}` doesn't match the core dump; was the program rebuilt?",
+                        section.name
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a core dump from disk
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        Self::parse(&fs::read(path)?)
+            .map_err(|e| anyhow!("`{}` is not a valid core dump: {}", path.display(), e))
+    }
+
+    /// Writes the core dump to disk
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    fn bytes(&self, start: u32, end: u32) -> Option<&[u8]> {
+        self.regions.iter().find_map(|(addr, bytes)| {
+            let region_end = *addr as u64 + bytes.len() as u64;
+            if start >= *addr && u64::from(end) <= region_end {
+                Some(&bytes[(start - addr) as usize..(end - addr) as usize])
+            } else {
+                None
+            }
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut notes = vec![];
+
+        let mut prstatus = vec![0; PRSTATUS_SIZE];
+        let pr_reg = CORE_REGISTERS
+            .iter()
+            .take_while(|reg| **reg != Register::MSP)
+            .map(|reg| self.registers.get(&(*reg as u8)).cloned().unwrap_or(0));
+        for (i, val) in pr_reg.enumerate() {
+            let start = PRSTATUS_PR_REG + 4 * i;
+            prstatus[start..start + 4].copy_from_slice(&val.to_le_bytes());
+        }
+        push_note(&mut notes, NOTE_CORE, NT_PRSTATUS, &prstatus);
+
+        let mut registers = vec![];
+        for (regsel, val) in &self.registers {
+            registers.extend_from_slice(&u32::from(*regsel).to_le_bytes());
+            registers.extend_from_slice(&val.to_le_bytes());
+        }
+        push_note(&mut notes, NOTE_SEMIDAP, NT_SEMIDAP_REGISTERS, &registers);
+
+        push_note(
+            &mut notes,
+            NOTE_SEMIDAP,
+            NT_SEMIDAP_ELF,
+            self.elf.to_string_lossy().as_bytes(),
+        );
+
+        let phnum = 1 + self.regions.len() as u16;
+        let mut offset = u32::from(EHDR_SIZE) + u32::from(phnum) * u32::from(PHDR_SIZE);
+
+        let mut bytes = vec![0x7f, b'E', b'L', b'F', ELFCLASS32, ELFDATA2LSB, EV_CURRENT];
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&ET_CORE.to_le_bytes());
+        bytes.extend_from_slice(&EM_ARM.to_le_bytes());
+        bytes.extend_from_slice(&u32::from(EV_CURRENT).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // e_entry
+        bytes.extend_from_slice(&u32::from(EHDR_SIZE).to_le_bytes()); // e_phoff
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // e_shoff
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+        bytes.extend_from_slice(&EHDR_SIZE.to_le_bytes());
+        bytes.extend_from_slice(&PHDR_SIZE.to_le_bytes());
+        bytes.extend_from_slice(&phnum.to_le_bytes());
+        bytes.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+        bytes.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+        let mut push_phdr = |ty: u32, vaddr: u32, size: u32, flags: u32, align: u32| {
+            for field in &[ty, offset, vaddr, vaddr, size, size, flags, align] {
+                bytes.extend_from_slice(&field.to_le_bytes());
+            }
+            offset += size;
+        };
+        push_phdr(PT_NOTE, 0, notes.len() as u32, 0, 4);
+        for (addr, contents) in &self.regions {
+            push_phdr(PT_LOAD, *addr, contents.len() as u32, PF_R | PF_W, 4);
+        }
+
+        bytes.extend_from_slice(&notes);
+        for (_, contents) in &self.regions {
+            bytes.extend_from_slice(contents);
+        }
+
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let u16_at = |offset: usize| -> Result<u16, anyhow::Error> {
+            Ok(u16::from_le_bytes(
+                bytes
+                    .get(offset..offset + 2)
+                    .ok_or_else(|| anyhow!("unexpected end of file"))?
+                    .try_into()?,
+            ))
+        };
+        let u32_at = |offset: usize| -> Result<u32, anyhow::Error> {
+            Ok(u32::from_le_bytes(
+                bytes
+                    .get(offset..offset + 4)
+                    .ok_or_else(|| anyhow!("unexpected end of file"))?
+                    .try_into()?,
+            ))
+        };
+        let slice = |offset: u32, size: u32| {
+            bytes
+                .get(offset as usize..offset as usize + size as usize)
+                .ok_or_else(|| anyhow!("unexpected end of file"))
+        };
+
+        if bytes.get(..6) != Some(&[0x7f, b'E', b'L', b'F', ELFCLASS32, ELFDATA2LSB])
+            || u16_at(16)? != ET_CORE
+            || u16_at(18)? != EM_ARM
+        {
+            bail!("not a 32-bit ARM ELF core file");
+        }
+
+        let phoff = u32_at(28)? as usize;
+        let phentsize = usize::from(u16_at(42)?);
+        let phnum = usize::from(u16_at(44)?);
+
+        let mut elf = None;
+        let mut registers = BTreeMap::new();
+        let mut regions = vec![];
+        for i in 0..phnum {
+            let phdr = phoff + i * phentsize;
+            let (ty, offset, vaddr, filesz) = (
+                u32_at(phdr)?,
+                u32_at(phdr + 4)?,
+                u32_at(phdr + 8)?,
+                u32_at(phdr + 16)?,
+            );
+            let contents = slice(offset, filesz)?;
+
+            if ty == PT_LOAD {
+                regions.push((vaddr, contents.to_owned()));
+            } else if ty == PT_NOTE {
+                let mut notes = contents;
+                while !notes.is_empty() {
+                    let (note, rest) = parse_note(notes)?;
+                    notes = rest;
+
+                    if note.name != NOTE_SEMIDAP.as_bytes() {
+                        continue;
+                    }
+
+                    if note.ty == NT_SEMIDAP_REGISTERS {
+                        for pair in note.desc.chunks_exact(8) {
+                            registers.insert(
+                                pair[0],
+                                u32::from_le_bytes(pair[4..].try_into().expect("UNREACHABLE")),
+                            );
+                        }
+                    } else if note.ty == NT_SEMIDAP_ELF {
+                        elf = Some(PathBuf::from(String::from_utf8(note.desc.to_owned())?));
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            elf: elf.ok_or_else(|| anyhow!("missing the path to the ELF file"))?,
+            registers,
+            regions,
+        })
+    }
+}
+
+impl Snapshot for CoreDump {
+    fn read_core_register(&mut self, reg: Register) -> Result<u32, anyhow::Error> {
+        self.registers
+            .get(&(reg as u8))
+            .cloned()
+            .ok_or_else(|| anyhow!("register {:?} is not in the core dump", reg))
+    }
+
+    fn memory_read_words(&mut self, addr: u32, n: u32) -> Result<Vec<u32>, anyhow::Error> {
+        let end = addr
+            .checked_add(4 * n)
+            .ok_or_else(|| anyhow!("invalid memory range"))?;
+        let bytes = self.bytes(addr, end).ok_or_else(|| {
+            anyhow!(
+                "memory range {:#010x}..{:#010x} is not in the core dump",
+                addr,
+                end
+            )
+        })?;
+
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().expect("UNREACHABLE")))
+            .collect())
+    }
+
+    fn memory_read_bytes(&mut self, addr: u32, n: u32) -> Result<Vec<u8>, anyhow::Error> {
+        let end = addr
+            .checked_add(n)
+            .ok_or_else(|| anyhow!("invalid memory range"))?;
+        let bytes = self.bytes(addr, end).ok_or_else(|| {
+            anyhow!(
+                "memory range {:#010x}..{:#010x} is not in the core dump",
+                addr,
+                end
+            )
+        })?;
+
+        Ok(bytes.to_owned())
+    }
+}
+
+fn push_note(notes: &mut Vec<u8>, name: &str, ty: u32, desc: &[u8]) {
+    fn pad(bytes: &mut Vec<u8>) {
+        while bytes.len() % 4 != 0 {
+            bytes.push(0);
+        }
+    }
+
+    // NOTE the name includes the null terminator
+    notes.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&ty.to_le_bytes());
+    notes.extend_from_slice(name.as_bytes());
+    notes.push(0);
+    pad(notes);
+    notes.extend_from_slice(desc);
+    pad(notes);
+}
+
+struct Note<'a> {
+    name: &'a [u8],
+    ty: u32,
+    desc: &'a [u8],
+}
+
+// Returns the first note in `bytes` and the rest of the bytes
+fn parse_note(bytes: &[u8]) -> Result<(Note<'_>, &[u8]), anyhow::Error> {
+    let align = |n: usize| (n + 3) & !3;
+    let header = bytes.get(..12).ok_or_else(|| anyhow!("malformed note"))?;
+    let namesz = u32::from_le_bytes(header[..4].try_into()?) as usize;
+    let descsz = u32::from_le_bytes(header[4..8].try_into()?) as usize;
+    let ty = u32::from_le_bytes(header[8..].try_into()?);
+
+    let desc_start = 12 + align(namesz);
+    let end = desc_start + align(descsz);
+    if bytes.len() < end {
+        bail!("malformed note");
+    }
+
+    // strip the null terminator
+    let mut name = &bytes[12..12 + namesz];
+    if name.last() == Some(&0) {
+        name = &name[..namesz - 1];
+    }
+
+    Ok((
+        Note {
+            name,
+            ty,
+            desc: &bytes[desc_start..desc_start + descsz],
+        },
+        &bytes[end..],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use cmsis_dap::cortex_m::Register;
+
+    use super::CoreDump;
+    use crate::target::Snapshot;
+
+    #[test]
+    fn roundtrip() {
+        let mut registers = BTreeMap::new();
+        registers.insert(Register::PC as u8, 0x2000_0100);
+        registers.insert(Register::SP as u8, 0x2000_3ff0);
+        registers.insert(Register::S31 as u8, 0x3f80_0000);
+
+        let dump = CoreDump {
+            elf: PathBuf::from("/firmware/target/thumbv7em-none-eabi/release/hard-fault"),
+            registers,
+            regions: vec![
+                (0x2000_0000, (0..16).collect()),
+                (0xE000_ED00, vec![0xff; 8]),
+            ],
+        };
+
+        let mut parsed = CoreDump::parse(&dump.to_bytes()).unwrap();
+        assert_eq!(parsed, dump);
+
+        assert_eq!(
+            parsed.read_core_register(Register::PC).unwrap(),
+            0x2000_0100
+        );
+        assert_eq!(
+            parsed.read_core_register(Register::S31).unwrap(),
+            0x3f80_0000
+        );
+        assert!(parsed.read_core_register(Register::R0).is_err());
+
+        assert_eq!(
+            parsed.memory_read_words(0x2000_0004, 2).unwrap(),
+            vec![0x0706_0504, 0x0b0a_0908]
+        );
+        assert_eq!(parsed.memory_read_word(0xE000_ED04).unwrap(), 0xffff_ffff);
+        // straddles the end of a region
+        assert!(parsed.memory_read_words(0x2000_000c, 2).is_err());
+        assert!(parsed.memory_read_word(0x1000_0000).is_err());
+    }
+}
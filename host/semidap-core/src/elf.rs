@@ -0,0 +1,297 @@
+//! ELF parsing
+
+use core::{cmp, convert::TryFrom, mem, ops::Range};
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use arrayref::array_ref;
+use gimli::{read::DebugFrame, EndianSlice, LittleEndian};
+use log::{debug, error};
+use xmas_elf::{
+    sections::{SectionData, ShType, SHF_ALLOC, SHF_WRITE},
+    symbol_table::{Entry, Type},
+    ElfFile,
+};
+
+/// Information extracted from the ELF file
+pub struct Elf<'a> {
+    /// Address ranges of all the allocatable sections, including the ones that are not loaded
+    /// (e.g. `.bss`)
+    pub allocations: Vec<Range<u32>>,
+    pub debug_frame: DebugFrame<EndianSlice<'a, LittleEndian>>,
+    pub footprints: BTreeMap<u64, &'a str>,
+    /// Address of the test harness' `HARNESS_NEXT` variable
+    pub harness_next: Option<u32>,
+    /// Data objects (e.g. `static` variables): demangled name -> `(address, size)`
+    pub objects: BTreeMap<String, (u32, u32)>,
+    pub range_names: Vec<(Range<u64>, String)>,
+    /// Sections that will be loaded into the target's memory
+    pub sections: Vec<Section<'a>>,
+    /// `(address, number of cursors)`
+    pub semidap_cursor: Option<(u32, u64)>,
+    /// `(address, total length)`
+    pub semidap_buffer: Option<(u32, u32)>,
+    /// Number of ITM stimulus ports used for logging
+    pub semidap_itm: Option<u64>,
+    pub semidap_stdin_cursor: Option<u32>,
+    pub semidap_stdin_irq: Option<u32>,
+    /// `(address, length)`
+    pub semidap_stdin_buffer: Option<(u32, u32)>,
+    pub vectors: Vectors,
+}
+
+pub struct Section<'a> {
+    pub address: u32,
+    pub bytes: &'a [u8],
+    pub name: &'a str,
+    /// Whether the program can modify this section at runtime (e.g. `.data`)
+    pub writable: bool,
+}
+
+pub struct Vectors {
+    pub vtor: u32,
+    pub sp: u32,
+    pub pc: u32,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, anyhow::Error> {
+        debug!("parsing ELF file");
+        let elf = &ElfFile::new(bytes).map_err(anyhow::Error::msg)?;
+
+        debug!("extracting allocatable sections from the ELF file");
+        let mut vectors = None;
+        let mut allocations = vec![];
+        let mut footprints = BTreeMap::new();
+        let mut sections = vec![];
+        let mut harness_next = None;
+        let mut objects = BTreeMap::new();
+        let mut semidap_cursor = None;
+        let mut semidap_buffer = None;
+        let mut semidap_itm = None;
+        let mut semidap_stdin_cursor = None;
+        let mut semidap_stdin_irq = None;
+        let mut semidap_stdin_buffer = None;
+        let mut debug_frame = None;
+        let mut range_names = vec![];
+        let binfmt_shndx = shndx(elf, ".binfmt");
+        let text_shndx = shndx(elf, ".text");
+        for sect in elf.section_iter() {
+            let is_allocatable = sect.flags() & SHF_ALLOC != 0;
+
+            let size = sect.size();
+            if is_allocatable && size != 0 {
+                let name = sect.get_name(elf).map_err(anyhow::Error::msg)?;
+
+                let address = sect.address();
+                let max = u64::from(u32::max_value());
+                if address > max || address + size > max {
+                    return Err(anyhow!(
+                        " section `{}` is outside the 32-bit address space",
+                        name
+                    ));
+                }
+                allocations.push(address as u32..(address + size) as u32);
+
+                // NOLOAD section like `.uninit` or `.bss`
+                if sect.get_type() == Ok(ShType::NoBits) {
+                    // we never load these sections
+                    continue;
+                }
+
+                let align = mem::size_of::<u32>() as u64;
+                if address % align != 0 || size % align != 0 {
+                    return Err(anyhow!(
+                        " section `{}` is not 4-byte aligned (start = {:#010x}, size = {})",
+                        name,
+                        address,
+                        size
+                    ));
+                }
+
+                let bytes = sect.raw_data(elf);
+                if name == ".vectors" {
+                    let sp = u32::from_le_bytes(*array_ref!(bytes, 0, 4));
+                    let pc = u32::from_le_bytes(*array_ref!(bytes, 4, 4));
+
+                    vectors = Some(Vectors {
+                        vtor: address as u32,
+                        pc,
+                        sp,
+                    })
+                }
+
+                sections.push(Section {
+                    address: address as u32,
+                    bytes,
+                    name,
+                    writable: sect.flags() & SHF_WRITE != 0,
+                })
+            } else if sect.get_name(elf) == Ok(".symtab") {
+                if let Ok(symtab) = sect.get_data(elf) {
+                    if let SectionData::SymbolTable32(entries) = symtab {
+                        for entry in entries {
+                            if let Ok(name) = entry.get_name(elf) {
+                                if Some(entry.shndx() as u32) == binfmt_shndx {
+                                    footprints.insert(entry.value(), name);
+                                } else if Some(entry.shndx() as u32) == text_shndx
+                                    && entry.size() != 0
+                                {
+                                    // clear the thumb bit
+                                    let start = entry.value() & !1;
+
+                                    range_names.push((start..start + entry.size(), demangle(name)));
+                                } else if entry.get_type() == Ok(Type::Object) && entry.size() != 0
+                                {
+                                    if let (Ok(addr), Ok(size)) =
+                                        (u32::try_from(entry.value()), u32::try_from(entry.size()))
+                                    {
+                                        objects.insert(demangle(name), (addr, size));
+                                    }
+                                }
+
+                                if name == "HARNESS_NEXT" {
+                                    harness_next = u32::try_from(entry.value()).ok();
+                                } else if name == "SEMIDAP_CURSOR" {
+                                    if let Ok(addr) = u32::try_from(entry.value()) {
+                                        semidap_cursor = Some((addr, entry.size() / 2));
+                                    }
+                                } else if name == "SEMIDAP_ITM" {
+                                    semidap_itm = Some(entry.size());
+                                } else if name == "SEMIDAP_STDIN_CURSOR" {
+                                    semidap_stdin_cursor = u32::try_from(entry.value()).ok();
+                                } else if name == "SEMIDAP_STDIN_IRQ" {
+                                    semidap_stdin_irq = u32::try_from(entry.value()).ok();
+                                } else if name == "SEMIDAP_STDIN_BUFFER" {
+                                    if let (Ok(addr), Ok(len)) =
+                                        (u32::try_from(entry.value()), u32::try_from(entry.size()))
+                                    {
+                                        if len.is_power_of_two() {
+                                            semidap_stdin_buffer = Some((addr, len));
+                                        } else {
+                                            error!("malformed SEMIDAP_STDIN_BUFFER (len={})", len);
+                                        }
+                                    }
+                                } else if name == "SEMIDAP_BUFFER" {
+                                    let size = entry.size();
+                                    if size.is_power_of_two() {
+                                        if let (Ok(addr), Ok(len)) =
+                                            (u32::try_from(entry.value()), u32::try_from(size))
+                                        {
+                                            semidap_buffer = Some((addr, len));
+                                        }
+                                    } else {
+                                        error!("malformed SEMIDAP_BUFFER (len={})", size);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            } else if sect.get_name(elf) == Ok(".debug_frame") {
+                let mut df = DebugFrame::new(sect.raw_data(elf), LittleEndian);
+                // 32-bit ARM
+                df.set_address_size(4);
+                debug_frame = Some(df);
+            }
+        }
+
+        let vectors = vectors.ok_or_else(|| anyhow!("`.vectors` section not found"))?;
+        let debug_frame =
+            debug_frame.ok_or_else(|| anyhow!("`.debug_frame` section is missing"))?;
+
+        range_names.sort_unstable_by(|a, b| a.0.start.cmp(&b.0.start));
+
+        Ok(Elf {
+            allocations,
+            debug_frame,
+            footprints,
+            harness_next,
+            objects,
+            range_names,
+            sections,
+            semidap_cursor,
+            semidap_buffer,
+            semidap_itm,
+            semidap_stdin_cursor,
+            semidap_stdin_irq,
+            semidap_stdin_buffer,
+            vectors,
+        })
+    }
+}
+
+impl Elf<'_> {
+    /// Looks up a data object by its full path (e.g. `hal::radio::LOCK`) or, if that's not
+    /// ambiguous, by the last components of its path (e.g. `radio::LOCK` or `LOCK`)
+    pub fn object(&self, name: &str) -> Result<(u32, u32), anyhow::Error> {
+        if let Some(object) = self.objects.get(name) {
+            return Ok(*object);
+        }
+
+        let suffix = format!("::{}", name);
+        let candidates = self
+            .objects
+            .iter()
+            .filter(|(path, _)| path.ends_with(&suffix))
+            .collect::<Vec<_>>();
+        match candidates.len() {
+            0 => Err(anyhow!("symbol `{}` not found", name)),
+            1 => Ok(*candidates[0].1),
+            _ => Err(anyhow!(
+                "symbol `{}` is ambiguous; candidates: {}",
+                name,
+                candidates
+                    .iter()
+                    .map(|(path, _)| &path[..])
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// Returns the name of the function that contains the instruction at address `pc`
+    pub fn function(&self, pc: u32) -> Option<&str> {
+        let pc = u64::from(pc);
+        self.range_names
+            .binary_search_by(|(range, _)| {
+                if range.contains(&pc) {
+                    cmp::Ordering::Equal
+                } else if pc < range.start {
+                    cmp::Ordering::Greater
+                } else {
+                    cmp::Ordering::Less
+                }
+            })
+            .ok()
+            .map(|i| &*self.range_names[i].1)
+    }
+}
+
+// Demangles `name` and strips the hash (e.g. `::hd881d91ced85c2b0`) from it
+fn demangle(name: &str) -> String {
+    let mut name = rustc_demangle::demangle(name).to_string();
+
+    let hash_len = "::hd881d91ced85c2b0".len();
+    if let Some(pos) = name.len().checked_sub(hash_len) {
+        let maybe_hash = &name[pos..];
+        if maybe_hash.starts_with("::h") {
+            name.truncate(pos);
+        }
+    }
+
+    name
+}
+
+fn shndx(elf: &ElfFile, name: &str) -> Option<u32> {
+    elf.section_iter()
+        .zip(0..)
+        .filter_map(|(sect, shndx)| {
+            if sect.get_name(elf) == Ok(name) {
+                Some(shndx)
+            } else {
+                None
+            }
+        })
+        .next()
+}
@@ -0,0 +1,258 @@
+//! Decoding of the fault status registers
+//!
+//! # References
+//!
+//! - (ARM) ARMv7-M Architecture Reference Manual (ARM DDI 0403E.b)
+
+use cm::scb::{cfsr, hfsr, BFAR, CFSR, HFSR, MMFAR};
+
+use crate::target::Snapshot;
+
+/// Contents of the fault status and fault address registers; section B3.2.15 - B3.2.18 of (ARM)
+#[derive(Clone, Copy)]
+pub struct Faults {
+    cfsr: u32,
+    hfsr: u32,
+    mmfar: u32,
+    bfar: u32,
+}
+
+impl Faults {
+    pub fn read(target: &mut impl Snapshot) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            cfsr: target.memory_read_word(CFSR::address() as usize as u32)?,
+            hfsr: target.memory_read_word(HFSR::address() as usize as u32)?,
+            mmfar: target.memory_read_word(MMFAR::address() as usize as u32)?,
+            bfar: target.memory_read_word(BFAR::address() as usize as u32)?,
+        })
+    }
+
+    /// Returns one line per fault status bit that's set; each line names the fault, explains it and
+    /// includes the faulting address when the hardware recorded it
+    pub fn explain(&self) -> Vec<String> {
+        let cfsr = cfsr::R::from(self.cfsr);
+        let hfsr = hfsr::R::from(self.hfsr);
+        let mut lines = vec![];
+
+        let mut push = |set: u8, fault: &str, bit: &str, what: &str, addr: Option<u32>| {
+            if set != 0 {
+                let at = addr
+                    .map(|addr| format!(" at address {:#010x}", addr))
+                    .unwrap_or_default();
+                lines.push(format!("{}: {}{} ({})", fault, what, at, bit));
+            }
+        };
+
+        const HF: &str = "HardFault";
+        push(
+            hfsr.VECTTBL(),
+            HF,
+            "HFSR.VECTTBL",
+            "bus fault while reading the vector table",
+            None,
+        );
+        push(
+            hfsr.FORCED(),
+            HF,
+            "HFSR.FORCED",
+            "escalated from a configurable fault whose handler is disabled or can't preempt \
+             the current context",
+            None,
+        );
+        push(
+            hfsr.DEBUGEVT(),
+            HF,
+            "HFSR.DEBUGEVT",
+            "debug event while halting debug is disabled",
+            None,
+        );
+
+        // NOTE the address registers may be shared; only trust them if the `*VALID` bit is set
+        let mmfar = if cfsr.MMARVALID() != 0 {
+            Some(self.mmfar)
+        } else {
+            None
+        };
+        const MM: &str = "MemManage";
+        push(
+            cfsr.IACCVIOL(),
+            MM,
+            "MMFSR.IACCVIOL",
+            "MPU violation, instruction fetch",
+            None,
+        );
+        push(
+            cfsr.DACCVIOL(),
+            MM,
+            "MMFSR.DACCVIOL",
+            "MPU violation, data access",
+            mmfar,
+        );
+        push(
+            cfsr.MSTKERR(),
+            MM,
+            "MMFSR.MSTKERR",
+            "MPU violation while stacking on exception entry",
+            None,
+        );
+        push(
+            cfsr.MUNSTKERR(),
+            MM,
+            "MMFSR.MUNSTKERR",
+            "MPU violation while unstacking on exception return",
+            None,
+        );
+        push(
+            cfsr.MLSPERR(),
+            MM,
+            "MMFSR.MLSPERR",
+            "MPU violation during lazy floating-point state preservation",
+            mmfar,
+        );
+
+        let bfar = if cfsr.BFARVALID() != 0 {
+            Some(self.bfar)
+        } else {
+            None
+        };
+        const BF: &str = "BusFault";
+        push(
+            cfsr.IBUSERR(),
+            BF,
+            "BFSR.IBUSERR",
+            "bus fault, instruction fetch",
+            None,
+        );
+        push(
+            cfsr.PRECISERR(),
+            BF,
+            "BFSR.PRECISERR",
+            "precise bus fault, data access",
+            bfar,
+        );
+        push(
+            cfsr.IMPRECISERR(),
+            BF,
+            "BFSR.IMPRECISERR",
+            "imprecise bus fault, data access; the stacked PC may not point to the faulting \
+             instruction",
+            None,
+        );
+        push(
+            cfsr.STKERR(),
+            BF,
+            "BFSR.STKERR",
+            "bus fault while stacking on exception entry (stack overflow?)",
+            None,
+        );
+        push(
+            cfsr.UNSTKERR(),
+            BF,
+            "BFSR.UNSTKERR",
+            "bus fault while unstacking on exception return",
+            None,
+        );
+        push(
+            cfsr.LSPERR(),
+            BF,
+            "BFSR.LSPERR",
+            "bus fault during lazy floating-point state preservation",
+            bfar,
+        );
+
+        const UF: &str = "UsageFault";
+        push(
+            cfsr.UNDEFINSTR(),
+            UF,
+            "UFSR.UNDEFINSTR",
+            "undefined instruction",
+            None,
+        );
+        push(
+            cfsr.INVSTATE(),
+            UF,
+            "UFSR.INVSTATE",
+            "invalid state; e.g. branch to an address with bit 0 cleared (ARM state)",
+            None,
+        );
+        push(
+            cfsr.INVPC(),
+            UF,
+            "UFSR.INVPC",
+            "invalid EXC_RETURN value or integrity check failure on exception return",
+            None,
+        );
+        push(
+            cfsr.NOCP(),
+            UF,
+            "UFSR.NOCP",
+            "coprocessor access while it's disabled or not present; e.g. FPU not enabled",
+            None,
+        );
+        push(
+            cfsr.UNALIGNED(),
+            UF,
+            "UFSR.UNALIGNED",
+            "unaligned memory access",
+            None,
+        );
+        push(
+            cfsr.DIVBYZERO(),
+            UF,
+            "UFSR.DIVBYZERO",
+            "integer division by zero",
+            None,
+        );
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Faults;
+
+    #[test]
+    fn explain() {
+        // what `hard-fault.rs` produces: a precise bus fault escalated to a hard fault
+        let faults = Faults {
+            cfsr: (1 << 15) | (1 << 9),
+            hfsr: 1 << 30,
+            mmfar: 0xffff_fff0,
+            bfar: 0xffff_fff0,
+        };
+        assert_eq!(
+            faults.explain(),
+            vec![
+                "HardFault: escalated from a configurable fault whose handler is disabled or \
+                 can't preempt the current context (HFSR.FORCED)",
+                "BusFault: precise bus fault, data access at address 0xfffffff0 (BFSR.PRECISERR)",
+            ]
+        );
+
+        // the address registers are ignored if their `VALID` bit is not set
+        let faults = Faults {
+            cfsr: (1 << 1) | (1 << 10) | (1 << 25),
+            hfsr: 0,
+            mmfar: 0x2000_0000,
+            bfar: 0x2000_0000,
+        };
+        assert_eq!(
+            faults.explain(),
+            vec![
+                "MemManage: MPU violation, data access (MMFSR.DACCVIOL)",
+                "BusFault: imprecise bus fault, data access; the stacked PC may not point to the \
+                 faulting instruction (BFSR.IMPRECISERR)",
+                "UsageFault: integer division by zero (UFSR.DIVBYZERO)",
+            ]
+        );
+
+        let faults = Faults {
+            cfsr: 0,
+            hfsr: 0,
+            mmfar: 0,
+            bfar: 0,
+        };
+        assert!(faults.explain().is_empty());
+    }
+}
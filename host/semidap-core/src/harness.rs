@@ -0,0 +1,192 @@
+//! Host side of the on-target test harness (see the `harness` crate)
+
+use core::str;
+
+use anyhow::bail;
+use cmsis_dap::cortex_m::Register;
+use colored::*;
+
+use crate::target::Target;
+
+pub const SYS_TEST: u16 = 0xbeac; // BKPT 0xAC
+
+// events reported through `SYS_TEST` (passed in `r0`)
+const EVENT_START: u32 = 0;
+const EVENT_END: u32 = 1;
+
+/// Tracks the progress of the tests that run on the target
+#[derive(Default)]
+pub struct Harness {
+    current: Option<Test>,
+    failed: Vec<String>,
+    passed: usize,
+}
+
+struct Test {
+    name: String,
+    should_panic: bool,
+}
+
+impl Harness {
+    /// Whether a test is currently running on the target
+    pub fn in_test(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Whether the test that's currently running is expected to panic
+    pub fn expects_panic(&self) -> bool {
+        self.current.as_ref().map(|test| test.should_panic) == Some(true)
+    }
+
+    /// Services the `SYS_TEST` system call
+    ///
+    /// Returns a line to report to the user
+    pub fn syscall(&mut self, target: &mut impl Target) -> Result<Option<String>, anyhow::Error> {
+        let event = target.read_core_register(Register::R0)?;
+
+        match event {
+            EVENT_START => {
+                let namep = target.read_core_register(Register::R1)?;
+                let len = target.read_core_register(Register::R2)?;
+                let should_panic = target.read_core_register(Register::R3)? != 0;
+                let name = target.memory_read_bytes(namep, len)?;
+
+                self.current = Some(Test {
+                    name: str::from_utf8(&name)?.to_owned(),
+                    should_panic,
+                });
+
+                Ok(None)
+            }
+
+            EVENT_END => {
+                if let Some(test) = self.current.take() {
+                    if test.should_panic {
+                        Ok(Some(self.fail(test.name, " (did not panic)")))
+                    } else {
+                        Ok(Some(self.pass(test.name)))
+                    }
+                } else {
+                    bail!("test harness reported the end of a test that never started")
+                }
+            }
+
+            _ => bail!("unknown test harness event: {}", event),
+        }
+    }
+
+    /// The current test aborted or raised an unhandled exception
+    ///
+    /// Returns a line to report to the user
+    pub fn aborted(&mut self) -> Option<String> {
+        let test = self.current.take()?;
+
+        Some(if test.should_panic {
+            self.pass(test.name)
+        } else {
+            self.fail(test.name, "")
+        })
+    }
+
+    /// The program exited, or halted for an unknown reason, while a test was running
+    ///
+    /// The current test did not run to completion so it fails, even if it was expected to panic.
+    /// Returns a line to report to the user
+    pub fn exited(&mut self) -> Option<String> {
+        let test = self.current.take()?;
+
+        Some(self.fail(test.name, " (exited)"))
+    }
+
+    /// The program exited with the given exit `code`
+    ///
+    /// Returns a summary to report to the user (if any test ran) and the exit code `semidap`
+    /// should use
+    pub fn finish(&mut self, code: i32) -> (Option<String>, i32) {
+        if self.passed == 0 && self.failed.is_empty() {
+            return (None, code);
+        }
+
+        let mut summary = String::new();
+        if !self.failed.is_empty() {
+            summary.push_str("\nfailures:\n");
+            for name in &self.failed {
+                summary.push_str(&format!("    {}\n", name));
+            }
+        }
+
+        summary.push_str(&format!(
+            "\ntest result: {}. {} passed; {} failed\n",
+            if self.failed.is_empty() {
+                "ok".green()
+            } else {
+                "FAILED".red()
+            },
+            self.passed,
+            self.failed.len()
+        ));
+
+        let code = if code != 0 {
+            code
+        } else if self.failed.is_empty() {
+            0
+        } else {
+            101
+        };
+
+        (Some(summary), code)
+    }
+
+    fn pass(&mut self, name: String) -> String {
+        self.passed += 1;
+        format!("test {} ... {}", name, "ok".green())
+    }
+
+    fn fail(&mut self, name: String, reason: &str) -> String {
+        let line = format!("test {} ... {}{}", name, "FAILED".red(), reason);
+        self.failed.push(name);
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Harness, Test};
+
+    fn running(name: &str, should_panic: bool) -> Harness {
+        Harness {
+            current: Some(Test {
+                name: name.to_owned(),
+                should_panic,
+            }),
+            ..Harness::default()
+        }
+    }
+
+    #[test]
+    fn should_panic() {
+        let mut harness = running("panics", true);
+        assert!(harness.aborted().is_some());
+        assert_eq!(harness.finish(134).1, 134);
+        assert_eq!(harness.passed, 1);
+
+        // exiting is not panicking
+        let mut harness = running("exits", true);
+        assert!(harness.exited().is_some());
+        assert_eq!(harness.finish(0).1, 101);
+        assert_eq!(harness.failed, ["exits"]);
+    }
+
+    #[test]
+    fn exited() {
+        let mut harness = running("exits", false);
+        assert!(harness.exited().unwrap().ends_with("(exited)"));
+        assert!(!harness.in_test());
+        assert_eq!(harness.finish(0).1, 101);
+
+        // outside a test exiting is the normal way to end the run
+        let mut harness = Harness::default();
+        assert!(harness.exited().is_none());
+        assert_eq!(harness.finish(0), (None, 0));
+    }
+}
@@ -0,0 +1,282 @@
+//! Host I/O: file system access, wall-clock time, command-line arguments and standard input
+
+use core::{convert::TryFrom, str};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use cmsis_dap::cortex_m::Register;
+use log::debug;
+
+use crate::{elf::Elf, stdin::Stdin, target::Target};
+
+pub const SYS_HOSTIO: u16 = 0xbead; // BKPT 0xAD
+
+// operations (passed in `r0`)
+const OP_OPEN: u32 = 0;
+const OP_CLOSE: u32 = 1;
+const OP_READ: u32 = 2;
+const OP_WRITE: u32 = 3;
+const OP_TIME: u32 = 4;
+const OP_ARGC: u32 = 5;
+const OP_ARGV: u32 = 6;
+
+// `OP_OPEN` modes
+const MODE_READ: u32 = 0;
+const MODE_WRITE: u32 = 1;
+const MODE_APPEND: u32 = 2;
+
+/// Host side state of the host I/O system calls and of the standard input channel
+pub struct HostIo {
+    args: Vec<String>,
+    files: Vec<Option<File>>,
+    // file system access is disabled when this is `None`
+    root: Option<PathBuf>,
+    stdin: Option<Stdin>,
+}
+
+impl HostIo {
+    /// `stdin` indicates whether the host's standard input should be forwarded to the target
+    pub fn new(root: Option<PathBuf>, args: Vec<String>, stdin: bool) -> Self {
+        Self {
+            args,
+            files: vec![],
+            root,
+            stdin: if stdin { Some(Stdin::spawn()) } else { None },
+        }
+    }
+
+    /// Must be called every time the program (re)boots
+    pub fn boot(&mut self, elf: &Elf) {
+        if let Some(stdin) = self.stdin.as_mut() {
+            stdin.boot(elf);
+        }
+    }
+
+    /// Must be called when attaching to a program that's already running
+    pub fn attach(&mut self, target: &mut impl Target, elf: &Elf) -> Result<(), anyhow::Error> {
+        if let Some(stdin) = self.stdin.as_mut() {
+            stdin.attach(target, elf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Forwards the host's standard input to the target
+    pub fn feed(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        if let Some(stdin) = self.stdin.as_mut() {
+            stdin.feed(target)?;
+        }
+
+        Ok(())
+    }
+
+    /// Services the `SYS_HOSTIO` system call
+    ///
+    /// The operation is passed in `r0` and its arguments in `r1`-`r3`. The result is written back
+    /// into `r0`; a negative value indicates an error
+    pub fn syscall(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        let op = target.read_core_register(Register::R0)?;
+        let r1 = target.read_core_register(Register::R1)?;
+        let r2 = target.read_core_register(Register::R2)?;
+        let r3 = target.read_core_register(Register::R3)?;
+
+        let r0 = match self.service(target, op, r1, r2, r3)? {
+            Ok(n) => i32::try_from(n).unwrap_or(i32::max_value()),
+            Err(e) => {
+                debug!("host I/O operation {} failed: {}", op, e);
+                -1
+            }
+        };
+
+        target.write_core_register(Register::R0, r0 as u32)
+    }
+
+    // NOTE the outer `Result` reports errors in the communication with the target; the inner one
+    // reports errors that must be forwarded to the target
+    fn service(
+        &mut self,
+        target: &mut impl Target,
+        op: u32,
+        r1: u32,
+        r2: u32,
+        r3: u32,
+    ) -> Result<Result<u32, io::Error>, anyhow::Error> {
+        Ok(match op {
+            OP_OPEN => {
+                let path = target.memory_read_bytes(r1, r2)?;
+                self.open(&path, r3)
+            }
+
+            OP_CLOSE => match self.file(r1) {
+                Ok(_) => {
+                    self.files[r1 as usize] = None;
+                    Ok(0)
+                }
+                Err(e) => Err(e),
+            },
+
+            OP_READ => match self.file(r1) {
+                Ok(file) => {
+                    let mut buf = vec![0; r3 as usize];
+                    match file.read(&mut buf) {
+                        Ok(n) => {
+                            write_bytes(target, r2, &buf[..n])?;
+                            Ok(n as u32)
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+                Err(e) => Err(e),
+            },
+
+            OP_WRITE => {
+                let bytes = target.memory_read_bytes(r2, r3)?;
+                self.file(r1)
+                    .and_then(|file| file.write(&bytes))
+                    .map(|n| n as u32)
+            }
+
+            OP_TIME => {
+                // NOTE a clock set before the UNIX epoch is reported as the epoch
+                let now = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default();
+                let mut bytes = vec![];
+                bytes.extend_from_slice(&(now.as_secs() as u32).to_le_bytes());
+                bytes.extend_from_slice(&now.subsec_nanos().to_le_bytes());
+                write_bytes(target, r1, &bytes)?;
+                Ok(0)
+            }
+
+            OP_ARGC => Ok(self.args.len() as u32),
+
+            OP_ARGV => {
+                if let Some(arg) = self.args.get(r1 as usize) {
+                    let n = arg.len().min(r3 as usize);
+                    write_bytes(target, r2, &arg.as_bytes()[..n])?;
+                    // NOTE the target uses this to check whether its buffer was large enough
+                    Ok(arg.len() as u32)
+                } else {
+                    Err(io::Error::new(io::ErrorKind::NotFound, "no such argument"))
+                }
+            }
+
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unknown operation",
+            )),
+        })
+    }
+
+    fn open(&mut self, path: &[u8], mode: u32) -> Result<u32, io::Error> {
+        let root = self.root.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file system access is disabled; use `--fs-root` to enable it",
+            )
+        })?;
+
+        let path = str::from_utf8(path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+            .and_then(|path| sandbox(root, Path::new(path)))?;
+
+        let mut options = OpenOptions::new();
+        match mode {
+            MODE_READ => options.read(true),
+            MODE_WRITE => options.write(true).create(true).truncate(true),
+            MODE_APPEND => options.append(true).create(true),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown mode")),
+        };
+        let file = options.open(&path)?;
+        debug!("opened `{}` (mode = {})", path.display(), mode);
+
+        // reuse the first free file descriptor
+        if let Some(pos) = self.files.iter().position(|file| file.is_none()) {
+            self.files[pos] = Some(file);
+            Ok(pos as u32)
+        } else {
+            self.files.push(Some(file));
+            Ok(self.files.len() as u32 - 1)
+        }
+    }
+
+    fn file(&mut self, fd: u32) -> Result<&mut File, io::Error> {
+        self.files
+            .get_mut(fd as usize)
+            .and_then(|file| file.as_mut())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad file descriptor"))
+    }
+}
+
+// Resolves the target provided `path` against the `root` directory; rejects paths that would
+// escape that directory
+fn sandbox(root: &Path, path: &Path) -> Result<PathBuf, io::Error> {
+    let escapes = || {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("`{}` is outside the sandbox", path.display()),
+        )
+    };
+
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(escapes());
+    }
+
+    let root = root.canonicalize()?;
+    let path = root.join(path);
+
+    // check that symlinks don't take us out of the sandbox
+    let parent = path.parent().unwrap_or(&root).canonicalize()?;
+    let target = path.canonicalize().ok();
+    if !parent.starts_with(&root) || target.map(|p| !p.starts_with(&root)).unwrap_or(false) {
+        return Err(escapes());
+    }
+
+    Ok(path)
+}
+
+// Writes `bytes` into the target's memory; unlike `Target::memory_write_bytes` this supports unaligned
+// addresses and lengths
+pub fn write_bytes(target: &mut impl Target, addr: u32, bytes: &[u8]) -> Result<(), anyhow::Error> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+
+    let start = addr & !3;
+    let end = (addr + bytes.len() as u32 + 3) & !3;
+
+    // read-modify-write; this is fine because the target is halted
+    let mut buf = target.memory_read_bytes(start, end - start)?;
+    let offset = (addr - start) as usize;
+    buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    target.memory_write_bytes(start, &buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    #[test]
+    fn sandbox() {
+        let root = env::temp_dir().join(format!("semidap-sandbox-{}", std::process::id()));
+        fs::create_dir_all(root.join("logs")).unwrap();
+
+        assert!(super::sandbox(&root, Path::new("data.bin")).is_ok());
+        assert!(super::sandbox(&root, Path::new("./logs/data.bin")).is_ok());
+
+        assert!(super::sandbox(&root, Path::new("../data.bin")).is_err());
+        assert!(super::sandbox(&root, Path::new("logs/../../data.bin")).is_err());
+        assert!(super::sandbox(&root, Path::new("/etc/passwd")).is_err());
+        // parent directory doesn't exist
+        assert!(super::sandbox(&root, Path::new("nope/data.bin")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
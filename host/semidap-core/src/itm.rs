@@ -0,0 +1,202 @@
+//! Decoder of the ITM / DWT packet protocol; see appendix D4 of the ARMv7-M ARM
+
+/// A decoded packet
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    /// Data written to an ITM stimulus port
+    Instrumentation { port: u8, payload: Vec<u8> },
+
+    /// DWT exception trace
+    Exception { number: u16, function: Function },
+
+    /// DWT periodic PC sample; `None` means that the processor was sleeping
+    PcSample(Option<u32>),
+
+    /// The ITM's FIFO overflowed and some packets were lost
+    Overflow,
+}
+
+/// What happened to the exception
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Entered,
+    Exited,
+    Returned,
+}
+
+/// Turns a stream of bytes into packets
+#[derive(Default)]
+pub struct Decoder {
+    // bytes that don't form a complete packet yet
+    buffer: Vec<u8>,
+    // number of consecutive zero bytes seen; part of a synchronization packet
+    zeros: usize,
+}
+
+// DWT hardware source packet discriminators
+const HW_EXCEPTION: u8 = 1;
+const HW_PC_SAMPLE: u8 = 2;
+
+impl Decoder {
+    /// Decodes `bytes` and passes each complete packet to `f`
+    ///
+    /// Bytes that are part of an incomplete packet are kept until the next call
+    pub fn feed(&mut self, bytes: &[u8], mut f: impl FnMut(Packet)) {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut consumed = 0;
+        while let Some((packet, n)) = parse(&self.buffer[consumed..], &mut self.zeros) {
+            consumed += n;
+            if let Some(packet) = packet {
+                f(packet);
+            }
+        }
+
+        self.buffer.drain(..consumed);
+    }
+}
+
+// Returns `None` if `bytes` doesn't contain a complete packet; otherwise returns the packet,
+// if it's one we care about, and its size in bytes
+fn parse(bytes: &[u8], zeros: &mut usize) -> Option<(Option<Packet>, usize)> {
+    let header = *bytes.first()?;
+
+    if header == 0 {
+        // synchronization packet: at least 47 zero bits followed by a one bit
+        *zeros += 1;
+        return Some((None, 1));
+    }
+
+    let synchronizing = *zeros != 0;
+    *zeros = 0;
+    if header == 0x80 && synchronizing {
+        // end of a synchronization packet
+        return Some((None, 1));
+    }
+
+    let size = header & 0b11;
+    if size == 0 {
+        // protocol packet
+        if header == 0x70 {
+            return Some((Some(Packet::Overflow), 1));
+        }
+
+        // timestamps and extension packets; all of them are ignored. The continuation bit
+        // (bit 7) indicates whether more bytes follow
+        let mut n = 1;
+        if header & 0x80 != 0 {
+            loop {
+                let byte = *bytes.get(n)?;
+                n += 1;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+        }
+        return Some((None, n));
+    }
+
+    // source packet
+    let len = if size == 3 { 4 } else { usize::from(size) };
+    let payload = bytes.get(1..1 + len)?;
+    let address = header >> 3;
+    let hardware = header & 0b100 != 0;
+
+    let packet = if !hardware {
+        Some(Packet::Instrumentation {
+            port: address,
+            payload: payload.to_owned(),
+        })
+    } else if address == HW_EXCEPTION && len == 2 {
+        let number = u16::from(payload[0]) | (u16::from(payload[1] & 1) << 8);
+        match (payload[1] >> 4) & 0b11 {
+            1 => Some(Function::Entered),
+            2 => Some(Function::Exited),
+            3 => Some(Function::Returned),
+            _ => None,
+        }
+        .map(|function| Packet::Exception { number, function })
+    } else if address == HW_PC_SAMPLE && len == 4 {
+        let mut pc = [0; 4];
+        pc.copy_from_slice(payload);
+        Some(Packet::PcSample(Some(u32::from_le_bytes(pc))))
+    } else if address == HW_PC_SAMPLE && len == 1 {
+        Some(Packet::PcSample(None))
+    } else {
+        // event counter and data trace packets
+        None
+    };
+
+    Some((packet, 1 + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Function, Packet};
+
+    #[test]
+    fn decode() {
+        let mut decoder = Decoder::default();
+        let mut packets = vec![];
+
+        let stream: &[u8] = &[
+            // synchronization
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x80, //
+            // 1-byte write to stimulus port 0
+            0x01, 0x2a, //
+            // 4-byte write to stimulus port 1
+            0x0b, 0x01, 0x02, 0x03, 0x04, //
+            // local timestamp with two continuation bytes
+            0xc0, 0x81, 0x01, //
+            // entered exception 15 (SysTick)
+            0x0e, 0x0f, 0x10, //
+            // returned to thread mode
+            0x0e, 0x00, 0x30, //
+            // PC sample
+            0x17, 0x01, 0x02, 0x00, 0x00, //
+            // PC sample while sleeping
+            0x15, 0x00, //
+            // overflow
+            0x70, //
+            // the first byte of a 2-byte write to stimulus port 0
+            0x02, 0xaa,
+        ];
+        decoder.feed(stream, |packet| packets.push(packet));
+
+        assert_eq!(
+            packets,
+            vec![
+                Packet::Instrumentation {
+                    port: 0,
+                    payload: vec![0x2a],
+                },
+                Packet::Instrumentation {
+                    port: 1,
+                    payload: vec![1, 2, 3, 4],
+                },
+                Packet::Exception {
+                    number: 15,
+                    function: Function::Entered,
+                },
+                Packet::Exception {
+                    number: 0,
+                    function: Function::Returned,
+                },
+                Packet::PcSample(Some(0x0201)),
+                Packet::PcSample(None),
+                Packet::Overflow,
+            ]
+        );
+
+        // the rest of the incomplete packet
+        packets.clear();
+        decoder.feed(&[0xbb], |packet| packets.push(packet));
+        assert_eq!(
+            packets,
+            vec![Packet::Instrumentation {
+                port: 0,
+                payload: vec![0xaa, 0xbb],
+            }]
+        );
+    }
+}
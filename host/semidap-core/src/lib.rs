@@ -0,0 +1,1224 @@
+//! Host side of the `semidap` protocol: program loading, log draining, system calls and
+//! post-mortem debugging
+//!
+//! The front-ends, `semidap` (CMSIS-DAP probes) and `semiprobe` (probe-rs), only need to connect
+//! to the target; everything else is implemented on top of the `Target` trait
+
+#![deny(warnings)]
+
+use core::{
+    cmp,
+    convert::TryInto,
+    fmt, mem,
+    ops::Range,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use std::{
+    borrow::Cow,
+    collections::btree_map::{self, BTreeMap},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail};
+use binfmt_parser::Message;
+use cm::scb::{cpuid, CPUID};
+use cmsis_dap::cortex_m;
+use gimli::{
+    read::{CfaRule, DebugFrame, UnwindSection},
+    BaseAddresses, EndianSlice, LittleEndian, RegisterRule, UninitializedUnwindContext,
+};
+use log::{debug, error, info};
+use rustyline::Editor;
+use structopt::StructOpt;
+
+pub use crate::target::{Snapshot, Target};
+use crate::{
+    core_dump::CoreDump,
+    elf::{Elf, Vectors},
+    fault::Faults,
+    harness::Harness,
+    hostio::HostIo,
+    logs::Logs,
+    monitor::Monitor,
+    target::memory_read_halfwords,
+};
+
+pub mod core_dump;
+pub mod elf;
+mod fault;
+mod harness;
+pub mod hostio;
+mod itm;
+mod logs;
+pub mod monitor;
+mod profile;
+mod stack;
+mod stdin;
+mod swo;
+mod target;
+pub mod test;
+mod vars;
+mod watch;
+
+// Command line options shared by the front-ends
+// NOTE not a doc comment; `structopt` would use it as the description of the front-end
+#[derive(StructOpt)]
+pub struct Opts {
+    #[structopt(long)]
+    pub verify: bool,
+
+    /// Attach to the program that's already running on the target instead of resetting the
+    /// target and loading the program
+    #[structopt(long)]
+    pub attach: bool,
+
+    #[structopt(flatten)]
+    pub monitor: monitor::Opts,
+
+    /// Write a core dump to this file when the program hits an unhandled exception; the core
+    /// dump can then be inspected with the `inspect` subcommand
+    #[structopt(
+        long,
+        value_name = "FILE",
+        parse(from_os_str),
+        conflicts_with = "watch"
+    )]
+    pub core_dump: Option<PathBuf>,
+
+    /// Rebuild the program, using `cargo build`, when a file in the current directory changes
+    /// and reload it into the target
+    #[structopt(long, conflicts_with = "attach")]
+    pub watch: bool,
+
+    /// Directory the program can access through the host I/O system calls; access is disabled
+    /// if omitted
+    #[structopt(long, parse(from_os_str))]
+    pub fs_root: Option<PathBuf>,
+
+    #[structopt(name = "ELF", parse(from_os_str))]
+    pub elf: Option<PathBuf>,
+
+    /// Arguments passed to the program
+    #[structopt(name = "ARGS")]
+    pub args: Vec<String>,
+}
+
+static CONTINUE: AtomicBool = AtomicBool::new(true);
+
+/// Makes Ctrl-C stop the program and leave the target in a clean state instead of killing the
+/// host process
+pub fn handle_ctrlc() -> Result<(), anyhow::Error> {
+    ctrlc::set_handler(|| CONTINUE.store(false, Ordering::Relaxed))?;
+    Ok(())
+}
+
+/// Runs the program specified in `opts` on `target` until it exits or the user presses Ctrl-C
+///
+/// `beginning` is the time at which the front-end started
+pub fn run_program(
+    target: &mut impl Target,
+    opts: Opts,
+    beginning: Instant,
+) -> Result<i32, anyhow::Error> {
+    let elf = opts
+        .elf
+        .ok_or_else(|| anyhow!("no ELF file was specified"))?;
+
+    if opts.watch {
+        return watch::run(
+            target,
+            &elf,
+            opts.verify,
+            opts.fs_root,
+            opts.args,
+            &opts.monitor,
+        );
+    }
+
+    let bytes = fs::read(&elf)?;
+    let run_opts = RunOpts {
+        attached: opts.attach,
+        deadline: None,
+        post_mortem: PostMortem {
+            prompt: true,
+            core_dump: opts.core_dump.map(|path| (path, elf)),
+        },
+    };
+    let elf = Elf::parse(&bytes)?;
+    let mut monitor = opts.monitor.build(&elf)?;
+
+    if opts.attach {
+        check_image(target, &elf)?;
+        monitor.attach(target)?;
+
+        info!(
+            "attached to the target (start to end: {:?})",
+            Instant::now() - beginning
+        );
+    } else {
+        load(target, &elf, opts.verify)?;
+
+        monitor.boot(target)?;
+
+        info!(
+            "booting program (start to end: {:?})",
+            Instant::now() - beginning
+        );
+
+        boot(target, &elf.vectors)?;
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let stdin = elf.semidap_stdin_cursor.is_some();
+    let mut io = HostIo::new(opts.fs_root, opts.args, stdin);
+    let stop = run(target, &elf, &run_opts, &mut io, &mut monitor, |output| {
+        match output {
+            Output::Log(src, message) => writeln!(stdout, "{}>{}", src, message)?,
+            Output::Harness(line) | Output::Notice(line) | Output::Report(line) => {
+                writeln!(stdout, "{}", line)?
+            }
+        }
+        Ok(())
+    })?;
+
+    for line in monitor.finish(target, &elf)? {
+        writeln!(stdout, "{}", line)?;
+    }
+
+    match stop {
+        Stop::Exited(code) => Ok(code),
+
+        Stop::Rebuilt => unreachable!(),
+
+        Stop::Interrupted | Stop::TimedOut => {
+            // leave the target undisturbed
+            if !opts.attach {
+                target.reset_halt()?;
+            }
+
+            Ok(0)
+        }
+    }
+}
+
+/// Reports which processor the target has
+pub fn identify(target: &mut impl Snapshot) -> Result<(), anyhow::Error> {
+    let cpuid = target.memory_read_word(CPUID::address() as usize as u32)?;
+    info!("target: {} (CPUID = {:#010x})", Part::from(cpuid), cpuid);
+
+    Ok(())
+}
+
+/// Reports the unhandled exception recorded in the core dump at `path` and drops the user into
+/// a prompt
+pub fn inspect(path: &Path, elf: Option<PathBuf>) -> Result<i32, anyhow::Error> {
+    let mut core = CoreDump::load(path)?;
+    let bytes = fs::read(elf.as_ref().unwrap_or(&core.elf))?;
+    let elf = Elf::parse(&bytes)?;
+    core.check(&elf)?;
+
+    let mut on_line = |line| {
+        println!("{}", line);
+        Ok(())
+    };
+    if !report_exception(&mut core, &elf, &mut on_line)? {
+        return Ok(1);
+    }
+    prompt(&mut core)?;
+
+    Ok(0)
+}
+
+/// Checks that `elf` is the program that's currently running on the target
+fn check_image(target: &mut impl Target, elf: &Elf) -> Result<(), anyhow::Error> {
+    // enable halting debug so that BKPT instructions halt the target
+    target.enable_debug()?;
+
+    let vtor = target.memory_read_word(cm::scb::VTOR::address() as u32)?;
+    if vtor != elf.vectors.vtor {
+        bail!(
+            "the ELF file doesn't match the program running on the target \
+             (VTOR = {:#010x}; expected {:#010x})",
+            vtor,
+            elf.vectors.vtor
+        );
+    }
+
+    // the program never modifies these sections so their contents must match
+    let start = Instant::now();
+    for section in elf.sections.iter().filter(|section| !section.writable) {
+        let bytes = target.memory_read_bytes(
+            section.address,
+            section.bytes.len().try_into().expect("UNIMPLEMENTED"),
+        )?;
+
+        if bytes != section.bytes {
+            bail!(
+                "the ELF file doesn't match the program running on the target \
+                 (section `{}` differs)",
+                section.name
+            );
+        }
+    }
+    info!("checked the program image in {:?}", Instant::now() - start);
+
+    Ok(())
+}
+
+/// Resets and halts the target and then loads the program into the target's memory
+pub fn load(target: &mut impl Target, elf: &Elf, verify: bool) -> Result<(), anyhow::Error> {
+    debug!("resetting and halting the target");
+    target.reset_halt()?;
+
+    debug!("loading ELF into the target's memory");
+    let mut total_bytes = 0;
+    let start = Instant::now();
+    for section in &elf.sections {
+        let start = Instant::now();
+        target.memory_write_bytes(section.address, section.bytes)?;
+        let end = Instant::now();
+        let bytes = section.bytes.len();
+        total_bytes += bytes as u64;
+
+        let dur = end - start;
+        info!("loaded `{}` ({} B) in {:?}", section.name, bytes, dur);
+
+        if verify {
+            // verify write
+            let start = Instant::now();
+            let bytes = target.memory_read_bytes(
+                section.address,
+                section.bytes.len().try_into().expect("UNIMPLEMENTED"),
+            )?;
+
+            if bytes != section.bytes {
+                return Err(anyhow!("verification of section `{}` failed", section.name));
+            }
+            let end = Instant::now();
+
+            info!("verified section `{}` in {:?}", section.name, end - start);
+        }
+    }
+
+    let end = Instant::now();
+
+    let dur = end - start;
+    const NANOS: u64 = 1_000_000_000;
+    let speed = total_bytes * NANOS / (dur.as_secs() * NANOS + u64::from(dur.subsec_nanos()));
+    info!("loaded {} bytes in {:?} ({} B/s)", total_bytes, dur, speed);
+
+    if let Some(next) = elf.harness_next {
+        // start from the first test
+        target.memory_write_word(next, 0)?;
+    }
+
+    Ok(())
+}
+
+/// Boots the program that has been loaded into the target's memory
+pub fn boot(target: &mut impl Target, vectors: &Vectors) -> Result<(), anyhow::Error> {
+    target.write_core_register(cortex_m::Register::LR, LR_END)?;
+    target.write_core_register(cortex_m::Register::SP, vectors.sp)?;
+    target.write_core_register(cortex_m::Register::PC, vectors.pc)?;
+    target.memory_write_word(cm::scb::VTOR::address() as u32, vectors.vtor)?;
+
+    target.resume()
+}
+
+/// Resets the target and boots the program again without reloading its read-only sections
+fn restart(
+    target: &mut impl Target,
+    elf: &Elf,
+    monitor: &mut Monitor,
+) -> Result<(), anyhow::Error> {
+    debug!("resetting and halting the target");
+    target.reset_halt()?;
+
+    // RAM is preserved across resets but the program may have modified its `static` variables
+    for section in elf.sections.iter().filter(|section| section.writable) {
+        target.memory_write_bytes(section.address, section.bytes)?;
+    }
+
+    monitor.boot(target)?;
+    boot(target, &elf.vectors)
+}
+
+/// Output produced by the program
+pub enum Output<'f> {
+    /// A log message received on the specified channel
+    Log(usize, Message<'f>),
+
+    /// A report from the test harness
+    Harness(String),
+
+    /// A report from `semidap` itself (e.g. a stack usage warning)
+    Notice(String),
+
+    /// A line of the report produced when the program aborts or hits an unhandled exception (e.g.
+    /// a backtrace)
+    Report(String),
+}
+
+/// The reason why `run` returned
+pub enum Stop {
+    /// The program exited with the specified exit code
+    Exited(i32),
+
+    /// The user pressed Ctrl-C
+    Interrupted,
+
+    /// The deadline passed to `run` was reached
+    TimedOut,
+
+    /// The program was rebuilt (watch mode only)
+    Rebuilt,
+}
+
+/// How `run` handles the program
+#[derive(Default)]
+pub struct RunOpts {
+    /// The program was already running when `semidap` started
+    pub attached: bool,
+    /// Stop the program at this instant
+    pub deadline: Option<Instant>,
+    /// What to do after an unhandled exception
+    pub post_mortem: PostMortem,
+}
+
+/// What to do after an unhandled exception, besides reporting it
+#[derive(Default)]
+pub struct PostMortem {
+    /// Drop the user into a prompt
+    prompt: bool,
+    /// Write a core dump; `(path to the core dump, path to the program's ELF file)`
+    core_dump: Option<(PathBuf, PathBuf)>,
+}
+
+/// Runs the booted program until it exits, servicing its system calls and passing its output
+/// to `on_output`
+pub fn run<'f>(
+    target: &mut impl Target,
+    elf: &Elf<'f>,
+    opts: &RunOpts,
+    io: &mut HostIo,
+    monitor: &mut Monitor,
+    mut on_output: impl FnMut(Output<'f>) -> Result<(), anyhow::Error>,
+) -> Result<Stop, anyhow::Error> {
+    let mut harness = Harness::default();
+    let mut logs = logs(elf);
+    if opts.attached {
+        // pick up where the program currently is
+        if let Some(logs) = logs.as_mut() {
+            logs.attach(target)?;
+        }
+        io.attach(target, elf)?;
+    } else {
+        io.boot(elf);
+    }
+    loop {
+        let halted = wait_for_halt(
+            target,
+            elf,
+            logs.as_mut(),
+            io,
+            monitor,
+            opts.deadline,
+            &mut on_output,
+        )?;
+        if let Some(stop) = halted {
+            return Ok(stop);
+        }
+
+        let pc = target.read_core_register(cortex_m::Register::PC)?;
+        let insn = memory_read_halfwords(target, pc, 1)?[0];
+        match insn {
+            harness::SYS_TEST => {
+                if let Some(line) = harness.syscall(target)? {
+                    on_output(Output::Harness(line))?;
+                }
+            }
+
+            hostio::SYS_HOSTIO => io.syscall(target)?,
+
+            _ => {
+                let aborted = insn == SYS_ABORT || insn == SYS_EXCEPTION;
+                let mut on_line = |line| on_output(Output::Report(line));
+                let code = if aborted && harness.expects_panic() {
+                    // skip the backtrace; this is the expected outcome
+                    134
+                } else if harness.in_test() {
+                    handle_syscall(target, elf, &PostMortem::default(), &mut on_line)?
+                } else {
+                    handle_syscall(target, elf, &opts.post_mortem, &mut on_line)?
+                };
+
+                if aborted {
+                    // a test panicked; resume execution from the next test
+                    if let Some(line) = harness.aborted() {
+                        on_output(Output::Harness(line))?;
+
+                        restart(target, elf, monitor)?;
+                        logs = self::logs(elf);
+                        io.boot(elf);
+                        continue;
+                    }
+                } else if let Some(line) = harness.exited() {
+                    // the program exited in the middle of a test; this ends the run
+                    on_output(Output::Harness(line))?;
+                }
+
+                let (summary, code) = harness.finish(code);
+                if let Some(summary) = summary {
+                    on_output(Output::Harness(summary))?;
+                }
+
+                return Ok(Stop::Exited(code));
+            }
+        }
+
+        // these system calls are not diverging: step over the BKPT instruction
+        target.write_core_register(cortex_m::Register::PC, pc + 2)?;
+        target.resume()?;
+    }
+}
+
+fn logs(elf: &Elf) -> Option<Logs> {
+    if let (Some((cursorp, ncursors)), Some((bufferp, total_len))) =
+        (elf.semidap_cursor, elf.semidap_buffer)
+    {
+        Some(Logs::new(cursorp, ncursors, bufferp, total_len))
+    } else {
+        elf.semidap_itm.map(Logs::itm)
+    }
+}
+
+/// Drains the target's logs, passing each message to `on_output`, forwards the standard input
+/// to the target and polls the `monitor` until the target halts
+///
+/// Returns `None` if the target halted
+fn wait_for_halt<'f>(
+    target: &mut impl Target,
+    elf: &Elf<'f>,
+    mut logs: Option<&mut Logs>,
+    io: &mut HostIo,
+    monitor: &mut Monitor,
+    deadline: Option<Instant>,
+    on_output: &mut impl FnMut(Output<'f>) -> Result<(), anyhow::Error>,
+) -> Result<Option<Stop>, anyhow::Error> {
+    let mut twice = false;
+    let mut observed_empty;
+    while CONTINUE.load(Ordering::Relaxed) {
+        io.feed(target)?;
+
+        if let Some(logs) = logs.as_mut() {
+            observed_empty = logs.drain(target)?;
+        } else {
+            observed_empty = true;
+        }
+
+        // NOTE when the program logs through the ITM its logs arrive over SWO
+        observed_empty &= monitor.trace(target, logs.as_deref_mut(), |line| {
+            on_output(Output::Notice(line))
+        })?;
+
+        if let Some(logs) = logs.as_mut() {
+            logs.decode(&elf.footprints, |src, message| {
+                on_output(Output::Log(src, message))
+            })?;
+        }
+
+        monitor.poll(target, |line| on_output(Output::Notice(line)))?;
+
+        // only handle a syscall when the device is halted, but first try to
+        // drain the buffer
+        if observed_empty {
+            if target.is_halted()? {
+                if twice {
+                    return Ok(None);
+                } else {
+                    twice = true;
+                }
+            }
+        }
+
+        if deadline.map(|deadline| Instant::now() > deadline) == Some(true) {
+            return Ok(Some(Stop::TimedOut));
+        }
+
+        if watch::REBUILT.swap(false, Ordering::Relaxed) {
+            return Ok(Some(Stop::Rebuilt));
+        }
+    }
+
+    Ok(Some(Stop::Interrupted))
+}
+
+const SYS_ABORT: u16 = 0xbeaa; // BKPT 0xAA
+const SYS_EXCEPTION: u16 = 0xbeff; // BKPT 0xFF
+const SYS_EXIT: u16 = 0xbeab; // BKPT 0xAB
+
+// if the target device is halted it is because it performed a system call using
+// the BKPT instruction. The immediate value passed to the BKPT instruction will
+// tell us which system call to service. All system calls serviced here are
+// 'diverging' from the point of view of the device; see `run` for the test
+// harness' (non-diverging) system call
+//
+// `post_mortem` indicates what to do after an unhandled exception; the report (e.g. the backtrace)
+// is passed to `on_line`, one line at a time
+fn handle_syscall(
+    target: &mut impl Target,
+    elf: &Elf,
+    post_mortem: &PostMortem,
+    on_line: &mut impl FnMut(String) -> Result<(), anyhow::Error>,
+) -> Result<i32, anyhow::Error> {
+    let pc = target.read_core_register(cortex_m::Register::PC)?;
+    let insn = memory_read_halfwords(target, pc, 1)?[0];
+
+    match insn {
+        SYS_EXIT => {
+            let r0 = target.read_core_register(cortex_m::Register::R0)?;
+            Ok(r0 as i32)
+        }
+
+        SYS_EXCEPTION => handle_exception(target, elf, post_mortem, on_line),
+
+        SYS_ABORT => {
+            let sp = target.read_core_register(cortex_m::Register::SP)?;
+            let lr = target.read_core_register(cortex_m::Register::LR)?;
+            backtrace(
+                target,
+                &elf.debug_frame,
+                &elf.range_names,
+                lr,
+                pc,
+                sp,
+                on_line,
+            )?;
+            Ok(134)
+        }
+
+        _ => {
+            error!("unknown instruction: {:#06x}", insn);
+            Ok(1)
+        }
+    }
+}
+
+// the reset value of the Link Register; this indicates the end of the stack
+const LR_END: u32 = 0xFFFF_FFFF;
+
+fn backtrace(
+    target: &mut impl Snapshot,
+    debug_frame: &DebugFrame<EndianSlice<LittleEndian>>,
+    range_names: &[(Range<u64>, String)],
+    lr: u32,
+    mut pc: u32,
+    sp: u32,
+    on_line: &mut impl FnMut(String) -> Result<(), anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    fn gimli2cortex(reg: &gimli::Register) -> cortex_m::Register {
+        if reg.0 == 13 {
+            Register::SP
+        } else if reg.0 == 14 {
+            Register::LR
+        } else if reg.0 == 11 {
+            Register::R11
+        } else if reg.0 == 10 {
+            Register::R10
+        } else if reg.0 == 9 {
+            Register::R9
+        } else if reg.0 == 8 {
+            Register::R8
+        } else if reg.0 == 7 {
+            Register::R7
+        } else if reg.0 == 6 {
+            Register::R6
+        } else if reg.0 == 5 {
+            Register::R5
+        } else if reg.0 == 4 {
+            Register::R4
+        } else {
+            panic!("unknown: {:?}", reg);
+        }
+    }
+
+    // Lazily evaluated registers
+    #[derive(Debug, Default)]
+    struct Registers {
+        cache: BTreeMap<Register, u32>,
+    }
+
+    impl Registers {
+        fn new(lr: u32, sp: u32) -> Self {
+            let mut cache = BTreeMap::new();
+            cache.insert(Register::LR, lr);
+            cache.insert(Register::SP, sp);
+            Self { cache }
+        }
+
+        fn get(
+            &mut self,
+            reg: cortex_m::Register,
+            target: &mut impl Snapshot,
+        ) -> Result<u32, anyhow::Error> {
+            Ok(match self.cache.entry(reg) {
+                btree_map::Entry::Occupied(entry) => *entry.get(),
+                btree_map::Entry::Vacant(entry) => *entry.insert(target.read_core_register(reg)?),
+            })
+        }
+
+        fn insert(&mut self, reg: cortex_m::Register, val: u32) {
+            self.cache.insert(reg, val);
+        }
+
+        fn update_cfa(
+            &mut self,
+            rule: &CfaRule<EndianSlice<LittleEndian>>,
+            target: &mut impl Snapshot,
+        ) -> Result</* cfa_changed: */ bool, anyhow::Error> {
+            debug!("Registers::update_cfg(self={:?}, rule={:?})", self, rule);
+
+            match rule {
+                CfaRule::RegisterAndOffset { register, offset } => {
+                    let cfa =
+                        (i64::from(self.get(gimli2cortex(register), target)?) + offset) as u32;
+                    let ok = self.cache.get(&Register::SP) != Some(&cfa);
+                    self.cache.insert(Register::SP, cfa);
+                    Ok(ok)
+                }
+
+                CfaRule::Expression(_) => unimplemented!("CfaRule::Expression"),
+            }
+        }
+
+        fn update(
+            &mut self,
+            reg: &gimli::Register,
+            rule: &RegisterRule<EndianSlice<LittleEndian>>,
+            target: &mut impl Snapshot,
+        ) -> Result<(), anyhow::Error> {
+            let reg = gimli2cortex(reg);
+            debug!(
+                "Registers::update(self={:?}, reg={:?}, rule={:?})",
+                self, reg, rule
+            );
+
+            match rule {
+                RegisterRule::Undefined => unreachable!(),
+
+                RegisterRule::Offset(offset) => {
+                    let cfa = self.get(Register::SP, target)?;
+                    let addr = (i64::from(cfa) + offset) as u32;
+                    self.cache.insert(reg, target.memory_read_word(addr)?);
+                }
+
+                _ => unimplemented!(),
+            }
+
+            Ok(())
+        }
+    }
+
+    use cortex_m::Register;
+
+    // statically linked binary -- there are no relative addresses
+    let bases = &BaseAddresses::default();
+    let ctx = &mut UninitializedUnwindContext::new();
+
+    on_line("stack backtrace:".to_owned())?;
+    let mut frame = 0;
+    let mut registers = Registers::new(lr, sp);
+    loop {
+        on_line(format!(
+            "{:>4}: {:#010x} - {}",
+            frame,
+            pc,
+            rustc_demangle::demangle(
+                range_names
+                    .binary_search_by(|rn| if rn.0.contains(&u64::from(pc)) {
+                        cmp::Ordering::Equal
+                    } else if u64::from(pc) < rn.0.start {
+                        cmp::Ordering::Greater
+                    } else {
+                        cmp::Ordering::Less
+                    })
+                    .map(|idx| &*range_names[idx].1)
+                    .unwrap_or("<unknown>")
+            )
+        ))?;
+
+        let fde = debug_frame.fde_for_address(bases, pc.into(), DebugFrame::cie_from_offset)?;
+        let uwt_row = fde.unwind_info_for_address(debug_frame, bases, ctx, pc.into())?;
+
+        let cfa_changed = registers.update_cfa(uwt_row.cfa(), target)?;
+
+        for (reg, rule) in uwt_row.registers() {
+            registers.update(reg, rule, target)?;
+        }
+
+        let lr = registers.get(Register::LR, target)?;
+        if lr == LR_END {
+            break;
+        }
+
+        if !cfa_changed && lr == pc {
+            on_line("error: the stack appears to be corrupted beyond this point".to_owned())?;
+            return Ok(());
+        }
+
+        if lr > 0xffff_fff0 {
+            on_line("      <exception entry>".to_owned())?;
+
+            let sp = registers.get(Register::SP, target)?;
+            let stacked = Stacked::read(target, sp)?;
+
+            // XXX insert other registers?
+            registers.insert(Register::LR, stacked.lr);
+            // adjust the stack pointer for stacked registers
+            registers.insert(Register::SP, sp + stacked.size(lr));
+            pc = stacked.pc;
+        } else {
+            if lr & 1 == 0 {
+                bail!("bug? LR ({:#010x}) didn't have the Thumb bit set", lr)
+            }
+            pc = lr & !1;
+        }
+
+        frame += 1;
+    }
+
+    Ok(())
+}
+
+fn handle_exception(
+    target: &mut impl Target,
+    elf: &Elf,
+    post_mortem: &PostMortem,
+    on_line: &mut impl FnMut(String) -> Result<(), anyhow::Error>,
+) -> Result<i32, anyhow::Error> {
+    if !report_exception(target, elf, on_line)? {
+        return Ok(1);
+    }
+
+    if let Some((path, elf)) = &post_mortem.core_dump {
+        CoreDump::capture(target, elf)?.save(path)?;
+        on_line("------------------------------------------".to_owned())?;
+        on_line(format!("wrote core dump to `{}`", path.display()))?;
+    }
+
+    if post_mortem.prompt {
+        prompt(target)?;
+    }
+
+    Ok(0)
+}
+
+/// Reports an unhandled exception: passes the exception, the registers and a backtrace to
+/// `on_line`, one line at a time
+///
+/// Returns `false` if the program was not servicing an exception
+fn report_exception(
+    target: &mut impl Snapshot,
+    elf: &Elf,
+    on_line: &mut impl FnMut(String) -> Result<(), anyhow::Error>,
+) -> Result<bool, anyhow::Error> {
+    use cortex_m::Register;
+
+    fn read_register(
+        target: &mut impl Snapshot,
+        reg: Register,
+    ) -> Result<(Register, u32), anyhow::Error> {
+        let val = target.read_core_register(reg)?;
+        Ok((reg, val))
+    }
+
+    const SCB_ICSR: u32 = 0xE000_ED04;
+
+    let icsr = target.memory_read_word(SCB_ICSR)?;
+    let vectactive = icsr as u8;
+
+    if vectactive == 0 {
+        on_line("error: SYS_EXCEPTION called from thread mode".to_owned())?;
+        return Ok(false);
+    }
+
+    // XXX we are assuming SP has not been modified since exception
+    // entry
+    let sp = target.read_core_register(Register::SP)?;
+
+    // these 8 registers are pushed onto the stack on exception entry
+    let stacked = Stacked::read(target, sp)?;
+    let r0 = target.read_core_register(Register::R0)?;
+    let r1 = target.read_core_register(Register::R1)?;
+    let r2 = target.read_core_register(Register::R2)?;
+    let r3 = target.read_core_register(Register::R3)?;
+    let r12 = target.read_core_register(Register::R12)?;
+    // XXX unclear whether the XPSR values are supposed to match; the IPSR
+    // part of xPSR will certainly be different
+    // let xpsr = target.read_core_register(Register::XPSR)?;
+
+    let stack_overflow = stacked.r0 != r0
+        || stacked.r1 != r1
+        || stacked.r2 != r2
+        || stacked.r3 != r3
+        || stacked.r12 != r12;
+
+    let mut registers = vec![
+        (Register::R0, r0),
+        (Register::R1, r1),
+        (Register::R2, r2),
+        (Register::R3, r3),
+    ];
+
+    registers.push(read_register(target, Register::R4)?);
+    registers.push(read_register(target, Register::R5)?);
+    registers.push(read_register(target, Register::R6)?);
+    registers.push(read_register(target, Register::R7)?);
+    registers.push(read_register(target, Register::R8)?);
+    registers.push(read_register(target, Register::R9)?);
+    registers.push(read_register(target, Register::R10)?);
+    registers.push(read_register(target, Register::R11)?);
+    registers.push((Register::R12, r12));
+
+    // correct for stacked registers
+    registers.push((Register::SP, sp + mem::size_of::<Stacked>() as u32));
+
+    // on stack overflow we can NOT rely on `pushed_registers` because they
+    // could have been pushed to invalid memory and the DAP would read them
+    // as `0`
+    if !stack_overflow {
+        registers.push((Register::PC, stacked.pc));
+        registers.push((Register::LR, stacked.lr));
+        registers.push((Register::XPSR, stacked.xpsr));
+    }
+
+    let cfbp = target.read_core_register(Register::CFBP)?;
+
+    on_line(String::new())?;
+    on_line("------------------------------------------".to_owned())?;
+    if stack_overflow {
+        on_line(format!("{:^42}", "stack overflow detected"))?;
+    } else {
+        let exception = exception_name(vectactive.into())
+            .unwrap_or_else(|| format!("??? (ICSR.VECTACTIVE = {})", vectactive).into());
+
+        on_line(format!("{:^42}", "unhandled exception"))?;
+        on_line(format!("{:^42}", exception))?;
+    }
+
+    on_line(String::new())?;
+
+    // HardFault, MemManage, BusFault or UsageFault
+    if (3..=6).contains(&vectactive) {
+        let faults = Faults::read(target)?;
+        for line in faults.explain() {
+            on_line(line)?;
+        }
+        on_line(String::new())?;
+    }
+
+    for pairs in registers.chunks(2) {
+        let mut line = format!("{:>7}: {:#010x}", format!("{:?}", pairs[0].0), pairs[0].1);
+
+        if let Some(second) = pairs.get(1) {
+            line.push_str(&format!(
+                "  {:>9}: {:#010x}",
+                format!("{:?}", second.0),
+                second.1
+            ));
+        }
+
+        on_line(line)?;
+    }
+
+    let control = cfbp >> 24;
+    let faultmask = (cfbp >> 16) & 0xff;
+    let basepri = (cfbp >> 8) & 0xff;
+    let primask = cfbp & 0xff;
+
+    on_line(format!(
+        "CONTROL: {:#04x}        FAULTMASK: {:#04x}",
+        control, faultmask
+    ))?;
+    on_line(format!(
+        "BASEPRI: {:#04x}          PRIMASK: {:#04x}",
+        basepri, primask
+    ))?;
+
+    if !stack_overflow {
+        on_line("------------------------------------------".to_owned())?;
+
+        backtrace(
+            target,
+            &elf.debug_frame,
+            &elf.range_names,
+            stacked.lr,
+            stacked.pc,
+            sp,
+            on_line,
+        )?;
+    }
+
+    Ok(true)
+}
+
+/// Returns the name of the exception with the given exception `number` (e.g. `ICSR.VECTACTIVE`)
+fn exception_name(number: u16) -> Option<Cow<'static, str>> {
+    Some(match number {
+        0 => "Thread".into(),
+        1 => "Reset".into(),
+        2 => "NMI".into(),
+        3 => "HardFault".into(),
+        4 => "MemManage".into(),
+        5 => "BusFault".into(),
+        6 => "UsageFault".into(),
+        11 => "SVCall".into(),
+        12 => "DebugMonitor".into(),
+        14 => "PendSV".into(),
+        15 => "SysTick".into(),
+        irqn if irqn >= 16 => format!("IRQ{}", irqn - 16).into(),
+        _ => return None,
+    })
+}
+
+fn prompt(target: &mut impl Snapshot) -> Result<(), anyhow::Error> {
+    println!("------------------------------------------");
+
+    let mut rl = Editor::<()>::new();
+    while let Ok(line) = rl.readline("\n> ") {
+        let mut line = line.trim();
+        // remove comments
+        line = line.splitn(2, '#').next().unwrap_or("");
+
+        if line.is_empty() {
+            // just a comment; nothing to do
+            continue;
+        } else if line == "help" {
+            println!(
+                "\
+commands:
+  help                        Displays this text
+  show <address> <i16>        Displays memory
+  show <address> -<u16> <u16> Displays memory
+  exit                        Exits the debugger
+  quit                        Alias for `exit`"
+            );
+        } else if line == "quit" {
+            break;
+        } else if line.starts_with("show ") {
+            let mut parts = line["show ".len()..].trim().splitn(3, ' ');
+            let addr = parts.next().and_then(|s| {
+                if s.starts_with("0x") {
+                    u32::from_str_radix(&s["0x".len()..].replace('_', ""), 16).ok()
+                } else {
+                    s.parse::<u32>().ok()
+                }
+            });
+
+            let range = match (parts.next(), parts.next()) {
+                (Some(n), None) => n
+                    .parse::<i32>()
+                    .ok()
+                    .map(|n| if n < 0 { n..1 } else { 0..n }),
+
+                (Some(m), Some(n)) => {
+                    if m.starts_with('-') && !n.starts_with('-') {
+                        m.parse::<i32>()
+                            .ok()
+                            .and_then(|m| n.parse::<i32>().ok().map(|n| m..n + 1))
+                    } else {
+                        None
+                    }
+                }
+
+                _ => None,
+            };
+
+            if let (Some(addr), Some(Range { start, end })) = (addr, range) {
+                if addr % 4 == 0 {
+                    let n = (end - start) as u32;
+                    if n == 0 {
+                        continue;
+                    }
+
+                    let start_addr = (addr as i32 + 4 * start) as u32;
+                    let end_addr = (addr as i32 + 4 * end) as u32;
+                    let words = target.memory_read_words(start_addr, n)?;
+
+                    let mut i = 0;
+                    let mut cursor = start_addr & !0xf;
+                    while cursor < end_addr {
+                        print!("{:#010x}:", cursor);
+
+                        for _ in 0..4 {
+                            if cursor >= start_addr && cursor < end_addr {
+                                if cursor == addr {
+                                    use colored::*;
+
+                                    print!(" {}", format!("{:#010x}", words[i]).bold());
+                                } else {
+                                    print!(" {:#010x}", words[i]);
+                                }
+
+                                i += 1;
+                            } else {
+                                print!("           ");
+                            }
+
+                            cursor += 4;
+                        }
+                        println!();
+                    }
+                } else {
+                    println!("error: address must be 4-byte aligned");
+                }
+            } else {
+                println!(
+                    "\
+error: invalid syntax. try `show 0 16` or `show 0x2000_0000 -2 2`"
+                )
+            }
+        } else {
+            println!("unknown command; try `help`");
+        }
+    }
+
+    Ok(())
+}
+
+/// Part number
+pub enum Part {
+    /// ARM Cortex-M0
+    CortexM0,
+
+    /// ARM Cortex-M0+
+    CortexM0Plus,
+
+    /// ARM Cortex-M3
+    CortexM3,
+
+    /// ARM Cortex-M4
+    CortexM4,
+
+    /// ARM Cortex-M7
+    CortexM7,
+
+    /// ARM Cortex-M23
+    CortexM23,
+
+    /// ARM Cortex-M33
+    CortexM33,
+
+    /// Unknown part
+    Unknown,
+}
+
+impl From<u32> for Part {
+    fn from(bits: u32) -> Self {
+        let r = cpuid::R::from(bits);
+
+        const ARM: u8 = 0x41;
+
+        if r.IMPLEMENTER() != ARM {
+            return Part::Unknown;
+        }
+
+        const ARMV6M: u8 = 0xc;
+        const ARMV7M: u8 = 0xf;
+
+        let arch = r.ARCHITECTURE();
+        let partno = r.PARTNO();
+        if arch == ARMV6M {
+            if partno == 0xc20 {
+                Part::CortexM0
+            } else if partno == 0xc60 {
+                Part::CortexM0Plus
+            } else if partno == 0xd20 {
+                Part::CortexM23
+            } else {
+                Part::Unknown
+            }
+        } else if arch == ARMV7M {
+            if partno == 0xc23 {
+                Part::CortexM3
+            } else if partno == 0xC24 {
+                Part::CortexM4
+            } else if partno == 0xc27 {
+                Part::CortexM7
+            } else if partno == 0xd21 {
+                Part::CortexM33
+            } else {
+                Part::Unknown
+            }
+        } else {
+            Part::Unknown
+        }
+    }
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Part::CortexM0 => "ARM Cortex-M0",
+            Part::CortexM0Plus => "ARM Cortex-M0+",
+            Part::CortexM3 => "ARM Cortex-M3",
+            Part::CortexM4 => "ARM Cortex-M4",
+            Part::CortexM7 => "ARM Cortex-M7",
+            Part::CortexM23 => "ARM Cortex-M23",
+            Part::CortexM33 => "ARM Cortex-M33",
+            Part::Unknown => "unknown part",
+        };
+
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug)]
+struct Stacked {
+    r0: u32,
+    r1: u32,
+    r2: u32,
+    r3: u32,
+    r12: u32,
+    lr: u32,
+    pc: u32,
+    xpsr: u32,
+}
+
+impl Stacked {
+    fn read(target: &mut impl Snapshot, sp: u32) -> Result<Self, anyhow::Error> {
+        let registers = target.memory_read_words(sp, 8)?;
+
+        Ok(Stacked {
+            r0: registers[0],
+            r1: registers[1],
+            r2: registers[2],
+            r3: registers[3],
+            r12: registers[4],
+            lr: registers[5],
+            pc: registers[6],
+            xpsr: registers[7],
+        })
+    }
+
+    /// Returns the size of the exception frame; `exc_return` is the `EXC_RETURN` value
+    fn size(&self, exc_return: u32) -> u32 {
+        // the FP context (S0-S15, FPSCR and a reserved word) was also stacked
+        let mut size = if exc_return & (1 << 4) == 0 {
+            26 * 4
+        } else {
+            mem::size_of::<Stacked>() as u32
+        };
+
+        // the stack pointer was realigned to 8 bytes on exception entry
+        if self.xpsr & (1 << 9) != 0 {
+            size += 4;
+        }
+
+        size
+    }
+}
@@ -0,0 +1,163 @@
+//! Draining and decoding of the target's log buffers
+
+use core::{cmp, mem};
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use binfmt_parser::Message;
+use log::debug;
+
+use crate::target::{memory_read_halfwords, Target};
+
+/// Host side state of the target's log channels
+pub struct Logs {
+    source: Source,
+    buffers: Vec<Vec<u8>>,
+    last_ts: Option<u32>,
+}
+
+enum Source {
+    /// The target's `SEMIDAP_BUFFER`s
+    Ram {
+        cursorp: u32,
+        bufferp: u32,
+        total_len: u32,
+        // read cursors
+        reads: Vec<u16>,
+    },
+
+    /// The ITM's stimulus ports; the data is captured over SWO (see `Monitor::trace`)
+    Itm,
+}
+
+impl Logs {
+    pub fn new(cursorp: u32, ncursors: u64, bufferp: u32, total_len: u32) -> Self {
+        Self {
+            source: Source::Ram {
+                cursorp,
+                bufferp,
+                total_len,
+                reads: (0..ncursors).map(|_| 0).collect(),
+            },
+            buffers: (0..ncursors).map(|_| vec![]).collect(),
+            last_ts: None,
+        }
+    }
+
+    /// Logs written to `nports` ITM stimulus ports
+    pub fn itm(nports: u64) -> Self {
+        Self {
+            source: Source::Itm,
+            buffers: (0..nports).map(|_| vec![]).collect(),
+            last_ts: None,
+        }
+    }
+
+    /// Skips the data that's currently in the target's buffers
+    ///
+    /// Used when attaching to a running program
+    // NOTE if the target is in the middle of writing a message we'll fail to decode it
+    pub fn attach(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        if let Source::Ram { cursorp, reads, .. } = &mut self.source {
+            let writes = memory_read_halfwords(target, *cursorp, reads.len() as u32)?;
+            reads.copy_from_slice(&writes);
+        }
+
+        Ok(())
+    }
+
+    /// Moves new data from the target's buffers into host memory
+    pub fn drain(
+        &mut self,
+        target: &mut impl Target,
+    ) -> Result</* observed_empty */ bool, anyhow::Error> {
+        let (cursorp, bufferp, total_len, reads) = match &mut self.source {
+            Source::Ram {
+                cursorp,
+                bufferp,
+                total_len,
+                reads,
+            } => (*cursorp, *bufferp, *total_len, reads),
+            // see `extend`
+            Source::Itm => return Ok(true),
+        };
+
+        let mut observed_empty = true;
+        let len = (total_len / reads.len() as u32) as u16;
+        for i in 0..reads.len() {
+            let writep = cursorp + (mem::size_of::<u16>() * i) as u32;
+            let bufp = bufferp + (len as usize * i) as u32;
+            let readp = &mut reads[i];
+
+            let (write, bytes) = target.read_circbuf(writep, bufp, *readp % len, len)?;
+            if write == *readp {
+                // no new data
+                continue;
+            } else if write.wrapping_sub(*readp) >= len {
+                target.reset_halt()?;
+                bail!("semidap buffer has been overrun -- reset-halting device");
+            }
+
+            observed_empty = false;
+            let n = cmp::min(write.wrapping_sub(*readp), bytes.len() as u16);
+            self.buffers[i].extend_from_slice(&bytes[..n as usize]);
+            *readp += n;
+        }
+
+        Ok(observed_empty)
+    }
+
+    /// Appends data written to an ITM stimulus `port`
+    pub fn extend(&mut self, port: u8, bytes: &[u8]) {
+        if let Some(buffer) = self.buffers.get_mut(usize::from(port)) {
+            buffer.extend_from_slice(bytes);
+        } else {
+            debug!("ignoring data written to stimulus port {}", port);
+        }
+    }
+
+    /// Decodes the drained data and passes each complete message to `f`
+    pub fn decode<'f>(
+        &mut self,
+        footprints: &BTreeMap<u64, &'f str>,
+        mut f: impl FnMut(usize, Message<'f>) -> Result<(), anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        let mut messages = vec![];
+        for (src, buffer) in self.buffers.iter_mut().enumerate() {
+            if buffer.is_empty() {
+                continue;
+            }
+
+            let mut consumed = 0;
+            let mut bytes = &buffer[..];
+            let total = bytes.len();
+
+            debug!("{}> {:?}", src, bytes);
+            while let Ok((message, i)) = binfmt_parser::parse_message(&bytes, footprints) {
+                consumed += i;
+                bytes = &bytes[i..];
+                messages.push((src, message));
+            }
+
+            if consumed == total {
+                buffer.clear();
+            } else {
+                *buffer = buffer[consumed..].to_owned();
+            }
+        }
+
+        // FIXME this will still result in unordered messages
+        messages.sort_by_key(|(_, m)| m.timestamp);
+
+        for (src, mut message) in messages {
+            let curr = message.absolute();
+            if let Some(last) = self.last_ts {
+                message.delta(last);
+            }
+            f(src, message)?;
+            self.last_ts = curr;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+//! What `semidap` observes, besides the logs, while the program runs
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use structopt::StructOpt;
+
+use crate::{
+    elf::Elf,
+    itm::{Function, Packet},
+    logs::Logs,
+    profile::Profiler,
+    stack::Stack,
+    swo::Swo,
+    target::Target,
+    vars::Var,
+};
+
+// how often the stack is checked against the warning threshold
+const STACK_PERIOD: Duration = Duration::from_millis(100);
+
+// how often the watched variables are read
+const VARS_PERIOD: Duration = Duration::from_millis(50);
+
+// NOTE this must match the default value of `--swo-baud`
+const SWO_BAUD: u32 = 1_000_000;
+
+#[derive(StructOpt)]
+pub struct Opts {
+    /// Paint the stack before booting the program and report the peak stack usage when the
+    /// program stops
+    #[structopt(long, conflicts_with = "attach")]
+    stack_usage: bool,
+
+    /// Warn when the stack usage goes above this percentage of the stack; implies
+    /// `--stack-usage`
+    #[structopt(long, value_name = "PERCENT", conflicts_with = "attach")]
+    stack_warn: Option<u8>,
+
+    /// Sample the program counter while the program runs and report a flat profile when the
+    /// program stops
+    #[structopt(long)]
+    profile: bool,
+
+    /// Sampling frequency of the profiler
+    #[structopt(long, value_name = "HZ", default_value = "1000")]
+    profile_rate: u32,
+
+    /// Write the profile to this file in the folded stacks format used by flamegraph tools
+    #[structopt(long, value_name = "FILE", parse(from_os_str), requires = "profile")]
+    folded: Option<PathBuf>,
+
+    /// Report the value of this `static` variable every time it changes; the type can be one
+    /// of: bool, f32, i8, i16, i32, i64, u8, u16, u32, u64, bytes. Can be repeated
+    #[structopt(long = "watch-var", value_name = "SYMBOL[:TYPE]", number_of_values = 1)]
+    pub vars: Vec<String>,
+
+    /// Capture the ITM / DWT trace output over SWO; implied when the program logs through the
+    /// ITM. When profiling, the program counter is then sampled by the DWT
+    #[structopt(long)]
+    swo: bool,
+
+    /// Baud rate of the SWO output
+    #[structopt(long, value_name = "BAUD", default_value = "1000000")]
+    swo_baud: u32,
+
+    /// Report every exception entry and exit; implies `--swo`
+    #[structopt(long)]
+    trace_exceptions: bool,
+}
+
+impl Opts {
+    /// Builds a `Monitor` for the program `elf`
+    pub fn build(&self, elf: &Elf) -> Result<Monitor, anyhow::Error> {
+        let stack = match self
+            .stack_warn
+            .or(if self.stack_usage { Some(90) } else { None })
+        {
+            Some(warn) => Some((Stack::new(elf, warn)?, Instant::now())),
+            None => None,
+        };
+
+        let profiler = if self.profile {
+            Some(Profiler::new(self.profile_rate, self.folded.clone()))
+        } else {
+            None
+        };
+
+        let vars = self
+            .vars
+            .iter()
+            .map(|spec| Var::new(spec, elf))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let swo = if self.swo || self.trace_exceptions || elf.semidap_itm.is_some() {
+            Some(Swo::new(
+                self.swo_baud,
+                elf.semidap_itm.unwrap_or(0),
+                self.trace_exceptions,
+                if self.profile {
+                    Some(self.profile_rate)
+                } else {
+                    None
+                },
+            ))
+        } else {
+            None
+        };
+
+        Ok(Monitor {
+            profiler,
+            stack,
+            swo,
+            vars: (vars, Instant::now()),
+        })
+    }
+}
+
+/// Observations enabled from the command line; all of them are disabled by default
+pub struct Monitor {
+    profiler: Option<Profiler>,
+    stack: Option<(Stack, Instant)>,
+    swo: Option<Swo>,
+    vars: (Vec<Var>, Instant),
+}
+
+impl Monitor {
+    /// A `Monitor` that only does what's required to run the program `elf`, i.e. capture its logs
+    /// if it logs through the ITM
+    pub fn new(elf: &Elf) -> Self {
+        Self {
+            profiler: None,
+            stack: None,
+            swo: elf
+                .semidap_itm
+                .map(|nports| Swo::new(SWO_BAUD, nports, false, None)),
+            vars: (vec![], Instant::now()),
+        }
+    }
+
+    /// Must be called after the program has been loaded into memory but before it boots
+    pub fn boot(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        if let Some(swo) = self.swo.as_mut() {
+            swo.boot(target)?;
+        }
+
+        if let Some((stack, _)) = self.stack.as_mut() {
+            stack.paint(target)?;
+        }
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.boot(target)?;
+        }
+
+        for var in &mut self.vars.0 {
+            var.boot();
+        }
+
+        Ok(())
+    }
+
+    /// Must be called when attaching to a program that's already running
+    pub fn attach(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        // NOTE the trace components may not have been configured (e.g. the program was loaded by
+        // some other tool) so configure them again
+        if let Some(swo) = self.swo.as_mut() {
+            swo.boot(target)?;
+        }
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.boot(target)?;
+        }
+
+        Ok(())
+    }
+
+    /// Called between log drains; passes lines to report to `on_line`
+    pub fn poll(
+        &mut self,
+        target: &mut impl Target,
+        mut on_line: impl FnMut(String) -> Result<(), anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        if let Some((stack, last)) = self.stack.as_mut() {
+            if last.elapsed() >= STACK_PERIOD {
+                *last = Instant::now();
+                if let Some(line) = stack.poll(target)? {
+                    on_line(line)?;
+                }
+            }
+        }
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            // the DWT sends the samples over SWO; see `trace`
+            if !self.swo.as_ref().map(Swo::samples_pc).unwrap_or(false) {
+                profiler.poll(target)?;
+            }
+        }
+
+        let (vars, last) = &mut self.vars;
+        if !vars.is_empty() && last.elapsed() >= VARS_PERIOD {
+            *last = Instant::now();
+            for var in vars {
+                if let Some(line) = var.poll(target)? {
+                    on_line(line)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the trace output captured over SWO, if any; data written to the ITM stimulus ports is
+    /// passed to `logs` and lines to report are passed to `on_line`
+    pub fn trace(
+        &mut self,
+        target: &mut impl Target,
+        mut logs: Option<&mut Logs>,
+        mut on_line: impl FnMut(String) -> Result<(), anyhow::Error>,
+    ) -> Result</* observed_empty */ bool, anyhow::Error> {
+        let swo = if let Some(swo) = self.swo.as_mut() {
+            swo
+        } else {
+            return Ok(true);
+        };
+
+        let profiler = &mut self.profiler;
+        swo.drain(target, |packet| {
+            match packet {
+                Packet::Instrumentation { port, payload } => {
+                    if let Some(logs) = logs.as_mut() {
+                        logs.extend(port, &payload);
+                    }
+                }
+
+                Packet::Exception { number, function } => {
+                    let name = crate::exception_name(number)
+                        .unwrap_or_else(|| format!("exception {}", number).into());
+                    let function = match function {
+                        Function::Entered => "entered",
+                        Function::Exited => "exited",
+                        Function::Returned => "returned to",
+                    };
+                    on_line(format!("exc> {} {}", function, name))?;
+                }
+
+                Packet::PcSample(Some(pc)) => {
+                    if let Some(profiler) = profiler.as_mut() {
+                        profiler.record(pc);
+                    }
+                }
+
+                // the processor was sleeping
+                Packet::PcSample(None) => {}
+
+                // reported by `Swo::drain`
+                Packet::Overflow => {}
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Must be called after the program stopped; returns lines to report
+    pub fn finish(
+        &mut self,
+        target: &mut impl Target,
+        elf: &Elf,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let mut lines = vec![];
+        if let Some((stack, _)) = self.stack.as_mut() {
+            lines.push(stack.report(target)?);
+        }
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            lines.push(profiler.report(elf)?);
+        }
+
+        Ok(lines)
+    }
+}
@@ -0,0 +1,128 @@
+//! Sampling profiler built on top of the DWT's Program Counter Sample Register or, when the trace
+//! output is captured over SWO, the DWT's periodic PC sample packets
+
+use core::fmt::Write as _;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use cm::dcb::{demcr, DEMCR};
+use log::info;
+
+use crate::{elf::Elf, target::Target};
+
+// Program Counter Sample Register; see 'DWT_PCSR' in the ARMv7-M ARM
+const DWT_PCSR: u32 = 0xE000_101C;
+
+// value read from `DWT_PCSR` when the processor is halted or the DWT is disabled
+const NO_SAMPLE: u32 = 0xFFFF_FFFF;
+
+/// Periodically samples the program counter of the running program
+pub struct Profiler {
+    /// where to write the folded stacks
+    folded: Option<PathBuf>,
+    last: Instant,
+    period: Duration,
+    /// program counter -> number of samples
+    samples: BTreeMap<u32, u64>,
+    start: Instant,
+}
+
+impl Profiler {
+    /// `rate` is the sampling frequency in Hz
+    pub fn new(rate: u32, folded: Option<PathBuf>) -> Self {
+        let now = Instant::now();
+        Self {
+            folded,
+            last: now,
+            period: Duration::from_secs(1) / rate.max(1),
+            samples: BTreeMap::new(),
+            start: now,
+        }
+    }
+
+    /// Enables the DWT
+    pub fn boot(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        let addr = DEMCR::address() as usize as u32;
+        let mut w = demcr::W::from(demcr::R::from(target.memory_read_word(addr)?));
+        w.TRCENA(1);
+        target.memory_write_word(addr, w.into())?;
+
+        self.start = Instant::now();
+        Ok(())
+    }
+
+    /// Takes a sample if it's time to do so
+    pub fn poll(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        if self.last.elapsed() < self.period {
+            return Ok(());
+        }
+        self.last = Instant::now();
+
+        let pc = target.memory_read_word(DWT_PCSR)?;
+        if pc != NO_SAMPLE {
+            self.record(pc);
+        }
+
+        Ok(())
+    }
+
+    /// Records a sample taken by other means, e.g. a DWT PC sample packet
+    pub fn record(&mut self, pc: u32) {
+        *self.samples.entry(pc & !1).or_default() += 1;
+    }
+
+    /// Aggregates the samples per function and returns a flat profile
+    ///
+    /// Also writes the folded stacks file, if one was requested
+    pub fn report(&mut self, elf: &Elf) -> Result<String, anyhow::Error> {
+        let total = self.samples.values().sum::<u64>();
+        if total == 0 {
+            return Ok("profile: no samples were collected".to_owned());
+        }
+
+        let mut functions = BTreeMap::<&str, u64>::new();
+        for (pc, n) in &self.samples {
+            *functions
+                .entry(elf.function(*pc).unwrap_or("<unknown>"))
+                .or_default() += n;
+        }
+        let mut functions = functions.into_iter().collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let secs = self.start.elapsed().as_secs_f64();
+        let mut report = String::new();
+        writeln!(
+            report,
+            "profile: {} samples in {:.1}s ({:.0} samples/s)",
+            total,
+            secs,
+            total as f64 / secs
+        )?;
+        writeln!(report, "     %  samples  function")?;
+        for (function, n) in &functions {
+            writeln!(
+                report,
+                "{:>6.2} {:>8}  {}",
+                *n as f64 * 100. / total as f64,
+                n,
+                function
+            )?;
+        }
+
+        if let Some(path) = self.folded.as_ref() {
+            // NOTE only the sampled function is known; the rest of the call stack is not
+            let mut folded = String::new();
+            for (function, n) in &functions {
+                writeln!(folded, "{} {}", function.replace(';', ":"), n)?;
+            }
+            fs::write(path, folded)?;
+            info!("wrote folded stacks to `{}`", path.display());
+        }
+
+        Ok(report)
+    }
+}
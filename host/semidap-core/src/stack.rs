@@ -0,0 +1,133 @@
+//! Stack usage measurement using a painted stack
+
+use core::{cmp, ops::Range};
+
+use anyhow::bail;
+use log::{debug, info};
+
+use crate::{elf::Elf, target::Target};
+
+// start of the SRAM region in the Cortex-M memory map; with the reverse memory layout (see
+// `flip-lld`) the stack starts here
+const SRAM_BASE: u32 = 0x2000_0000;
+
+// value written to every word of the stack before the program boots
+const PAINT: u32 = 0xaaaa_aaaa;
+
+// size of the chunks used to scan the stack
+const CHUNK: u32 = 4 * 1024;
+
+/// The program's stack
+pub struct Stack {
+    /// `bottom..top`
+    region: Range<u32>,
+    // usage above this address triggers a warning
+    threshold: u32,
+    warn: u8,
+    warned: bool,
+}
+
+impl Stack {
+    /// `warn` is the stack usage, in percent, above which a warning is reported
+    pub fn new(elf: &Elf, warn: u8) -> Result<Self, anyhow::Error> {
+        // the stack grows downwards from the initial value of the stack pointer (see
+        // `.vectors`) towards the end of the static data (`.bss`, `.uninit`, etc.) or, with the
+        // reverse memory layout, towards the start of RAM
+        let top = elf.vectors.sp & !3;
+        let bottom = elf
+            .allocations
+            .iter()
+            .filter(|range| range.end <= top)
+            .map(|range| (range.end + 3) & !3)
+            .fold(SRAM_BASE, cmp::max);
+
+        if bottom >= top {
+            bail!(
+                "couldn't locate the stack (initial SP = {:#010x})",
+                elf.vectors.sp
+            );
+        }
+
+        let size = top - bottom;
+        let threshold = (top - (u64::from(size) * u64::from(warn) / 100) as u32) & !3;
+        debug!(
+            "stack: {:#010x}..{:#010x} ({} B); warning threshold: {:#010x}",
+            bottom, top, size, threshold
+        );
+
+        Ok(Self {
+            region: bottom..top,
+            threshold: cmp::max(threshold, bottom),
+            warn,
+            warned: false,
+        })
+    }
+
+    /// Paints the stack
+    ///
+    /// NOTE the target must be halted and the program must not have booted yet
+    pub fn paint(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        let bytes = PAINT
+            .to_le_bytes()
+            .iter()
+            .cycle()
+            .take(self.size() as usize)
+            .cloned()
+            .collect::<Vec<_>>();
+        target.memory_write_bytes(self.region.start, &bytes)?;
+        self.warned = false;
+        info!("painted the stack ({} B)", self.size());
+
+        Ok(())
+    }
+
+    /// Checks whether the stack usage has crossed the warning threshold
+    ///
+    /// Returns a warning the first time that happens
+    pub fn poll(&mut self, target: &mut impl Target) -> Result<Option<String>, anyhow::Error> {
+        if self.warned || target.memory_read_word(self.threshold)? == PAINT {
+            return Ok(None);
+        }
+
+        self.warned = true;
+        Ok(Some(format!(
+            "warning: stack usage is above {}% of the stack ({} B)",
+            self.warn,
+            self.size()
+        )))
+    }
+
+    /// Scans the stack for its high-water mark and returns a summary of the peak stack usage
+    pub fn report(&mut self, target: &mut impl Target) -> Result<String, anyhow::Error> {
+        let mut hwm = self.region.end;
+        let mut addr = self.region.start;
+        'scan: while addr < self.region.end {
+            let n = cmp::min(CHUNK, self.region.end - addr) / 4;
+            for (i, word) in target.memory_read_words(addr, n)?.into_iter().enumerate() {
+                if word != PAINT {
+                    hwm = addr + 4 * i as u32;
+                    break 'scan;
+                }
+            }
+            addr += 4 * n;
+        }
+
+        let used = self.region.end - hwm;
+        let size = self.size();
+        Ok(format!(
+            "peak stack usage: {} of {} B ({}%){}",
+            used,
+            size,
+            u64::from(used) * 100 / u64::from(size),
+            if hwm == self.region.start {
+                " -- the stack may have overflowed"
+            } else {
+                ""
+            }
+        ))
+    }
+
+    fn size(&self) -> u32 {
+        self.region.end - self.region.start
+    }
+}
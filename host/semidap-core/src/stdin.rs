@@ -0,0 +1,141 @@
+//! Forwarding of the host's standard input to the target
+
+use core::cmp;
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use log::debug;
+
+use crate::{elf::Elf, hostio, target::Target};
+
+// Cortex-M: the Interrupt Set-Pending Registers; one bit per interrupt
+const NVIC_ISPR0: u32 = 0xE000_E200;
+// the number of interrupts an NVIC supports
+const NVIC_MAX_IRQS: u32 = 496;
+
+/// Host side state of the target's `SEMIDAP_STDIN_BUFFER`
+pub struct Stdin {
+    // data that has not yet been moved into the target's buffer
+    pending: VecDeque<u8>,
+    rx: Receiver<Vec<u8>>,
+    // `None` if the program doesn't read the standard input
+    buffer: Option<Buffer>,
+}
+
+struct Buffer {
+    bufferp: u32,
+    cursorp: u32,
+    irqp: u32,
+    len: u32,
+    // the target's write cursor; only the host modifies it
+    write: u32,
+}
+
+impl Stdin {
+    /// Spawns a thread that reads the host's standard input
+    pub fn spawn() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            let mut stdin = stdin.lock();
+            let mut buf = [0; 256];
+            loop {
+                match stdin.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if tx.send(buf[..n].to_owned()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            debug!("reached the end of the standard input");
+        });
+
+        Self {
+            pending: VecDeque::new(),
+            rx,
+            buffer: None,
+        }
+    }
+
+    /// Must be called every time the program (re)boots
+    pub fn boot(&mut self, elf: &Elf) {
+        self.buffer = if let (Some(cursorp), Some(irqp), Some((bufferp, len))) = (
+            elf.semidap_stdin_cursor,
+            elf.semidap_stdin_irq,
+            elf.semidap_stdin_buffer,
+        ) {
+            Some(Buffer {
+                bufferp,
+                cursorp,
+                irqp,
+                len,
+                // `Reset` zeroes the cursors
+                write: 0,
+            })
+        } else {
+            None
+        };
+    }
+
+    /// Must be called when attaching to a program that's already running
+    pub fn attach(&mut self, target: &mut impl Target, elf: &Elf) -> Result<(), anyhow::Error> {
+        self.boot(elf);
+
+        if let Some(buffer) = self.buffer.as_mut() {
+            buffer.write = target.memory_read_word(buffer.cursorp)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves as much pending data as possible into the target's buffer
+    pub fn feed(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        let buffer = if let Some(buffer) = self.buffer.as_mut() {
+            buffer
+        } else {
+            return Ok(());
+        };
+
+        while let Ok(bytes) = self.rx.try_recv() {
+            self.pending.extend(bytes);
+        }
+
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let read = target.memory_read_word(buffer.cursorp + 4)?;
+        let free = buffer.len - buffer.write.wrapping_sub(read);
+        let n = cmp::min(free as usize, self.pending.len());
+        if n == 0 {
+            return Ok(());
+        }
+
+        let bytes = self.pending.drain(..n).collect::<Vec<_>>();
+        let cursor = buffer.write % buffer.len;
+        let pivot = cmp::min(n, (buffer.len - cursor) as usize);
+        hostio::write_bytes(target, buffer.bufferp + cursor, &bytes[..pivot])?;
+        hostio::write_bytes(target, buffer.bufferp, &bytes[pivot..])?;
+
+        // NOTE the data must be written *before* the cursor is updated
+        buffer.write = buffer.write.wrapping_add(n as u32);
+        target.memory_write_word(buffer.cursorp, buffer.write)?;
+
+        // pend the interrupt the program asked for; this also wakes up the processor if it's
+        // sleeping in `WFE`
+        // NOTE the interrupt number is out of range (`NO_IRQ`) until the program calls `take`
+        let irq = target.memory_read_word(buffer.irqp)?;
+        if irq < NVIC_MAX_IRQS {
+            target.memory_write_word(NVIC_ISPR0 + 4 * (irq / 32), 1 << (irq % 32))?;
+        }
+
+        Ok(())
+    }
+}
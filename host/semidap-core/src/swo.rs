@@ -0,0 +1,174 @@
+//! Trace capture over the Serial Wire Output pin
+//!
+//! # References
+//!
+//! - (ARM) ARMv7-M Architecture Reference Manual (ARM DDI 0403E.b)
+//! - (TRM) Cortex-M4 r0p0 Technical Reference Manual (ARM DDI 0439B)
+//! - (PS) nRF52840 Product Specification v1.1
+
+use cm::{
+    dcb::{demcr, DEMCR},
+    dwt::{ctrl, CTRL},
+};
+use log::{info, warn};
+
+use crate::{
+    itm::{Decoder, Packet},
+    target::Target,
+};
+
+// Trace Enable Register; section C1.7.5 of (ARM)
+const ITM_TER: u32 = 0xE000_0E00;
+
+// Trace Privilege Register; section C1.7.6 of (ARM)
+const ITM_TPR: u32 = 0xE000_0E40;
+
+// Trace Control Register; section C1.7.7 of (ARM)
+const ITM_TCR: u32 = 0xE000_0E80;
+const ITM_TCR_ITMENA: u32 = 1;
+const ITM_TCR_SYNCENA: u32 = 1 << 2;
+const ITM_TCR_TXENA: u32 = 1 << 3;
+const ITM_TCR_TRACEBUSID: u32 = 1 << 16;
+
+// Current Parallel Port Size Register; section 11.2 of (TRM)
+const TPIU_CSPSR: u32 = 0xE004_0004;
+
+// Asynchronous Clock Prescaler Register; section 11.2 of (TRM)
+const TPIU_ACPR: u32 = 0xE004_0010;
+
+// Selected Pin Protocol Register; section 11.2 of (TRM)
+const TPIU_SPPR: u32 = 0xE004_00F0;
+const TPIU_SPPR_NRZ: u32 = 2;
+
+// Formatter and Flush Control Register; section 11.2 of (TRM)
+const TPIU_FFCR: u32 = 0xE004_0304;
+// NOTE the formatter must be disabled when using SWO
+const TPIU_FFCR_TRIGIN: u32 = 1 << 8;
+
+// nRF52840 specific: routes the trace output to the SWO pin; section 6.4.13.40 of (PS)
+const CLOCK_TRACECONFIG: u32 = 0x4000_055C;
+const CLOCK_TRACECONFIG_TRACEMUX_SERIAL: u32 = 1 << 16;
+// `TRACEPORTSPEED = 32 MHz`
+const TRACECLKIN: u32 = 32_000_000;
+
+// frequency of the processor clock; used to compute the PC sampling period
+const CPU_CLOCK: u32 = 64_000_000;
+
+/// SWO capture of the program's ITM and DWT output
+pub struct Swo {
+    baudrate: u32,
+    decoder: Decoder,
+    exceptions: bool,
+    overrun: bool,
+    /// number of stimulus ports to enable
+    ports: u64,
+    /// sampling frequency, in Hz
+    pc_sampling: Option<u32>,
+}
+
+impl Swo {
+    /// `ports` is the number of stimulus ports used by the program; `exceptions` enables DWT
+    /// exception tracing; `pc_sampling` enables DWT PC sampling at (approximately) that frequency
+    pub fn new(baudrate: u32, ports: u64, exceptions: bool, pc_sampling: Option<u32>) -> Self {
+        Self {
+            baudrate,
+            decoder: Decoder::default(),
+            exceptions,
+            overrun: false,
+            ports,
+            pc_sampling,
+        }
+    }
+
+    /// Whether the program counter is sampled by the DWT
+    pub fn samples_pc(&self) -> bool {
+        self.pc_sampling.is_some()
+    }
+
+    /// Configures the target's trace components and starts the capture
+    ///
+    /// NOTE the target must be halted
+    pub fn boot(&mut self, target: &mut impl Target) -> Result<(), anyhow::Error> {
+        let baudrate = target.swo_start(self.baudrate)?;
+
+        // enable the DWT and ITM
+        let addr = DEMCR::address() as usize as u32;
+        let mut w = demcr::W::from(demcr::R::from(target.memory_read_word(addr)?));
+        w.TRCENA(1);
+        target.memory_write_word(addr, w.into())?;
+
+        target.memory_write_word(CLOCK_TRACECONFIG, CLOCK_TRACECONFIG_TRACEMUX_SERIAL)?;
+
+        // TPIU: asynchronous (NRZ) output on the SWO pin at the baud rate the probe uses
+        target.memory_write_word(TPIU_CSPSR, 1)?;
+        target.memory_write_word(TPIU_ACPR, (TRACECLKIN / baudrate).max(1) - 1)?;
+        target.memory_write_word(TPIU_SPPR, TPIU_SPPR_NRZ)?;
+        target.memory_write_word(TPIU_FFCR, TPIU_FFCR_TRIGIN)?;
+
+        // ITM: unprivileged access to all the stimulus ports; forward the DWT packets
+        target.memory_write_word(
+            ITM_TCR,
+            ITM_TCR_TRACEBUSID | ITM_TCR_TXENA | ITM_TCR_SYNCENA | ITM_TCR_ITMENA,
+        )?;
+        target.memory_write_word(ITM_TPR, 0)?;
+        target.memory_write_word(ITM_TER, ((1u64 << self.ports.min(32)) - 1) as u32)?;
+
+        // DWT
+        let addr = CTRL::address() as usize as u32;
+        let mut w = ctrl::W::from(ctrl::R::from(target.memory_read_word(addr)?));
+        w.EXCTRCENA(self.exceptions as u8);
+        if let Some(rate) = self.pc_sampling {
+            // the DWT takes a sample every `(POSTPRESET + 1) * (64 or 1024)` cycles
+            let cycles = CPU_CLOCK / rate.max(1);
+            let (tap, preset) = if cycles <= 16 * 64 {
+                (0, cycles / 64)
+            } else {
+                (1, (cycles / 1024).min(16))
+            };
+            let preset = preset.max(1) - 1;
+            w.CYCTAP(tap);
+            w.POSTPRESET(preset as u8);
+            w.POSTINIT(preset as u8);
+            w.CYCCNTENA(1);
+            w.PCSAMPLENA(1);
+            info!(
+                "sampling the PC every {} cycles",
+                (preset + 1) * if tap == 0 { 64 } else { 1024 }
+            );
+        } else {
+            w.PCSAMPLENA(0);
+        }
+        target.memory_write_word(addr, w.into())?;
+
+        self.decoder = Decoder::default();
+        self.overrun = false;
+
+        Ok(())
+    }
+
+    /// Reads the captured data and passes each decoded packet to `f`
+    pub fn drain(
+        &mut self,
+        target: &mut impl Target,
+        mut f: impl FnMut(Packet) -> Result<(), anyhow::Error>,
+    ) -> Result</* observed_empty */ bool, anyhow::Error> {
+        let (overrun, bytes) = target.swo_data()?;
+
+        if overrun && !self.overrun {
+            warn!("the probe's SWO buffer overflowed; some trace data was lost");
+            self.overrun = true;
+        }
+
+        let mut packets = vec![];
+        self.decoder.feed(&bytes, |packet| packets.push(packet));
+        for packet in packets {
+            if packet == Packet::Overflow {
+                warn!("the ITM's FIFO overflowed; some trace data was lost");
+            } else {
+                f(packet)?;
+            }
+        }
+
+        Ok(bytes.is_empty())
+    }
+}
@@ -0,0 +1,269 @@
+//! Access to the target device
+
+use anyhow::bail;
+use cm::dcb::{dhcsr, DHCSR};
+use cmsis_dap::{cortex_m::Register, dap, Dap};
+
+/// The state of a halted program; either a live target or a core dump
+///
+/// This is what the post-mortem tools (e.g. `backtrace` and `prompt`) operate on
+pub trait Snapshot {
+    /// Reads a core register
+    fn read_core_register(&mut self, reg: Register) -> Result<u32, anyhow::Error>;
+
+    /// Reads `n` words of memory starting at `addr`, which must be 4-byte aligned
+    fn memory_read_words(&mut self, addr: u32, n: u32) -> Result<Vec<u32>, anyhow::Error>;
+
+    /// Reads `n` bytes of memory starting at `addr`
+    fn memory_read_bytes(&mut self, addr: u32, n: u32) -> Result<Vec<u8>, anyhow::Error>;
+
+    /// Reads a word of memory
+    fn memory_read_word(&mut self, addr: u32) -> Result<u32, anyhow::Error> {
+        Ok(self.memory_read_words(addr, 1)?[0])
+    }
+}
+
+/// A target device connected through a debug probe
+///
+/// This is all the front-ends (`semidap` and `semiprobe`) need to provide
+pub trait Target: Snapshot {
+    /// Writes a core register; the target must be halted
+    fn write_core_register(&mut self, reg: Register, val: u32) -> Result<(), anyhow::Error>;
+
+    /// Writes `bytes` into memory starting at `addr`, which must be 4-byte aligned
+    fn memory_write_bytes(&mut self, addr: u32, bytes: &[u8]) -> Result<(), anyhow::Error>;
+
+    /// Writes a word of memory
+    fn memory_write_word(&mut self, addr: u32, val: u32) -> Result<(), anyhow::Error>;
+
+    /// Halts the processor
+    fn halt(&mut self) -> Result<(), anyhow::Error>;
+
+    /// Resumes the execution of the halted processor
+    fn resume(&mut self) -> Result<(), anyhow::Error>;
+
+    /// Whether the processor is halted
+    fn is_halted(&mut self) -> Result<bool, anyhow::Error>;
+
+    /// Resets the target and halts the processor before it executes the first instruction
+    fn reset_halt(&mut self) -> Result<(), anyhow::Error>;
+
+    /// Enables halting debug so that `BKPT` instructions halt the processor
+    fn enable_debug(&mut self) -> Result<(), anyhow::Error> {
+        const DBGKEY: u16 = 0xA05F;
+
+        let addr = DHCSR::address() as usize as u32;
+        let r = dhcsr::R::from(self.memory_read_word(addr)?);
+        if r.C_DEBUGEN() == 0 {
+            let mut w = dhcsr::W::from(r);
+            w.DBGKEY(DBGKEY).C_DEBUGEN(1);
+            self.memory_write_word(addr, w.into())?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the half-word at `hwp`, the write cursor of a circular buffer, and then the new
+    /// bytes in that circular buffer
+    ///
+    /// - `bufp` points at the beginning of the circular buffer
+    /// - `cursor` is the read cursor; bytes are read starting at this position
+    /// - `len` is the length of the circular buffer; it must be a power of 2
+    ///
+    /// Fewer bytes than available may be returned; the rest can be read with a later call
+    fn read_circbuf(
+        &mut self,
+        hwp: u32,
+        bufp: u32,
+        cursor: u16,
+        len: u16,
+    ) -> Result<(u16, Vec<u8>), anyhow::Error> {
+        let write = memory_read_halfwords(self, hwp, 1)?[0];
+
+        // NOTE only the contiguous part of the new data is read
+        let end = write % len;
+        let n = if end >= cursor {
+            end - cursor
+        } else {
+            len - cursor
+        };
+        let bytes = self.memory_read_bytes(bufp + u32::from(cursor), n.into())?;
+
+        Ok((write, bytes))
+    }
+
+    /// Starts capturing the SWO output at (approximately) the given `baudrate`; returns the
+    /// actual baud rate
+    fn swo_start(&mut self, baudrate: u32) -> Result<u32, anyhow::Error> {
+        let _ = baudrate;
+        bail!("this probe doesn't support capturing the SWO output")
+    }
+
+    /// Returns the SWO data captured since the last call and whether some data was lost because
+    /// the probe's buffer overflowed
+    fn swo_data(&mut self) -> Result<(/* overrun */ bool, Vec<u8>), anyhow::Error> {
+        bail!("this probe doesn't support capturing the SWO output")
+    }
+}
+
+/// Reads `n` half-words starting at `addr`, which must be 2-byte aligned
+///
+/// NOTE uses word accesses so half-words written by the target in a single instruction are never
+/// observed half-updated
+pub(crate) fn memory_read_halfwords(
+    target: &mut (impl Snapshot + ?Sized),
+    addr: u32,
+    n: u32,
+) -> Result<Vec<u16>, anyhow::Error> {
+    assert_eq!(addr % 2, 0, "{:#010x} is not 2-byte aligned", addr);
+
+    if n == 0 {
+        return Ok(vec![]);
+    }
+
+    let start = addr & !0b11;
+    let end = addr + 2 * n;
+    let words = target.memory_read_words(start, (end - start + 3) / 4)?;
+    let skip = ((addr - start) / 2) as usize;
+
+    Ok(words
+        .iter()
+        .flat_map(|word| vec![*word as u16, (*word >> 16) as u16])
+        .skip(skip)
+        .take(n as usize)
+        .collect())
+}
+
+impl Snapshot for Dap {
+    fn read_core_register(&mut self, reg: Register) -> Result<u32, anyhow::Error> {
+        Dap::read_core_register(self, reg)
+    }
+
+    fn memory_read_words(&mut self, addr: u32, n: u32) -> Result<Vec<u32>, anyhow::Error> {
+        self.memory_read(addr, n)
+    }
+
+    fn memory_read_bytes(&mut self, addr: u32, n: u32) -> Result<Vec<u8>, anyhow::Error> {
+        self.memory_read(addr, n)
+    }
+
+    fn memory_read_word(&mut self, addr: u32) -> Result<u32, anyhow::Error> {
+        Dap::memory_read_word(self, addr)
+    }
+}
+
+impl Target for Dap {
+    fn write_core_register(&mut self, reg: Register, val: u32) -> Result<(), anyhow::Error> {
+        Dap::write_core_register(self, reg, val)
+    }
+
+    fn memory_write_bytes(&mut self, addr: u32, bytes: &[u8]) -> Result<(), anyhow::Error> {
+        self.memory_write(addr, bytes)
+    }
+
+    fn memory_write_word(&mut self, addr: u32, val: u32) -> Result<(), anyhow::Error> {
+        Dap::memory_write_word(self, addr, val)
+    }
+
+    fn halt(&mut self) -> Result<(), anyhow::Error> {
+        Dap::halt(self)
+    }
+
+    fn resume(&mut self) -> Result<(), anyhow::Error> {
+        Dap::resume(self)
+    }
+
+    fn is_halted(&mut self) -> Result<bool, anyhow::Error> {
+        Dap::is_halted(self)
+    }
+
+    fn reset_halt(&mut self) -> Result<(), anyhow::Error> {
+        self.sysresetreq(true)
+    }
+
+    fn enable_debug(&mut self) -> Result<(), anyhow::Error> {
+        self.set_debugen()
+    }
+
+    // NOTE all the reads are performed in a single DAP transaction
+    fn read_circbuf(
+        &mut self,
+        hwp: u32,
+        bufp: u32,
+        cursor: u16,
+        len: u16,
+    ) -> Result<(u16, Vec<u8>), anyhow::Error> {
+        self.read_hw_and_circbuf(hwp, bufp, cursor, len)
+    }
+
+    fn swo_start(&mut self, baudrate: u32) -> Result<u32, anyhow::Error> {
+        Dap::swo_start(self, baudrate)
+    }
+
+    fn swo_data(&mut self) -> Result<(bool, Vec<u8>), anyhow::Error> {
+        let (status, bytes) = Dap::swo_data(self)?;
+        Ok((status & dap::SWO_STATUS_OVERRUN != 0, bytes))
+    }
+}
+
+#[cfg(feature = "probe-rs")]
+mod probe {
+    use cmsis_dap::cortex_m::Register;
+    use probe_rs::{Core, MemoryInterface};
+
+    use super::{Snapshot, Target};
+
+    impl Snapshot for Core<'_> {
+        fn read_core_register(&mut self, reg: Register) -> Result<u32, anyhow::Error> {
+            Ok(self.read_core_reg(reg as u16)?)
+        }
+
+        fn memory_read_words(&mut self, addr: u32, n: u32) -> Result<Vec<u32>, anyhow::Error> {
+            let mut words = vec![0; n as usize];
+            self.read_32(addr, &mut words)?;
+            Ok(words)
+        }
+
+        fn memory_read_bytes(&mut self, addr: u32, n: u32) -> Result<Vec<u8>, anyhow::Error> {
+            let mut bytes = vec![0; n as usize];
+            self.read_8(addr, &mut bytes)?;
+            Ok(bytes)
+        }
+
+        fn memory_read_word(&mut self, addr: u32) -> Result<u32, anyhow::Error> {
+            Ok(self.read_word_32(addr)?)
+        }
+    }
+
+    impl Target for Core<'_> {
+        fn write_core_register(&mut self, reg: Register, val: u32) -> Result<(), anyhow::Error> {
+            Ok(self.write_core_reg((reg as u16).into(), val)?)
+        }
+
+        fn memory_write_bytes(&mut self, addr: u32, bytes: &[u8]) -> Result<(), anyhow::Error> {
+            Ok(self.write_8(addr, bytes)?)
+        }
+
+        fn memory_write_word(&mut self, addr: u32, val: u32) -> Result<(), anyhow::Error> {
+            Ok(self.write_word_32(addr, val)?)
+        }
+
+        fn halt(&mut self) -> Result<(), anyhow::Error> {
+            Core::halt(self)?;
+            Ok(())
+        }
+
+        fn resume(&mut self) -> Result<(), anyhow::Error> {
+            Ok(Core::run(self)?)
+        }
+
+        fn is_halted(&mut self) -> Result<bool, anyhow::Error> {
+            Ok(Core::core_halted(self)?)
+        }
+
+        fn reset_halt(&mut self) -> Result<(), anyhow::Error> {
+            Core::reset_and_halt(self)?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Test mode: runs programs to completion and compares their output against snapshots
+
+use core::fmt::Write as _;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use binfmt::Level;
+use colored::*;
+use structopt::StructOpt;
+
+use crate::{elf::Elf, hostio::HostIo, monitor::Monitor, target::Target, Output, RunOpts, Stop};
+
+#[derive(StructOpt)]
+pub struct Opts {
+    /// Overwrite the snapshots with the observed output instead of comparing against them
+    #[structopt(long)]
+    update: bool,
+
+    /// Directory that contains the snapshots
+    #[structopt(long, parse(from_os_str), default_value = "src/bin")]
+    snapshots: PathBuf,
+
+    /// Maximum time, in seconds, each program is allowed to run
+    #[structopt(long, default_value = "10")]
+    timeout: u64,
+
+    /// Write a JUnit XML report to this file
+    #[structopt(long, parse(from_os_str))]
+    junit: Option<PathBuf>,
+
+    #[structopt(name = "ELF", parse(from_os_str), required = true)]
+    elfs: Vec<PathBuf>,
+}
+
+/// Outcome of a single test
+struct Outcome {
+    name: String,
+    duration: Duration,
+    // `None` means the test passed
+    failure: Option<Failure>,
+}
+
+struct Failure {
+    // one line summary
+    message: String,
+    // e.g. a diff
+    details: String,
+}
+
+pub fn run(
+    target: &mut impl Target,
+    opts: &Opts,
+    verify: bool,
+    fs_root: Option<&PathBuf>,
+) -> Result<i32, anyhow::Error> {
+    let beginning = Instant::now();
+
+    println!("\nrunning {} tests", opts.elfs.len());
+
+    let mut outcomes = vec![];
+    for path in &opts.elfs {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow!("`{}` is not a valid file name", path.display()))?
+            .to_owned();
+
+        let start = Instant::now();
+        let failure = match run_one(target, path, &name, opts, verify, fs_root) {
+            Ok(failure) => failure,
+            // user pressed Ctrl-C
+            Err(Interrupted) => {
+                target.reset_halt()?;
+                break;
+            }
+        };
+        let duration = Instant::now() - start;
+
+        println!(
+            "test {} ... {}",
+            name,
+            if failure.is_none() {
+                "ok".green()
+            } else {
+                "FAILED".red()
+            }
+        );
+
+        outcomes.push(Outcome {
+            name,
+            duration,
+            failure,
+        });
+    }
+
+    let duration = Instant::now() - beginning;
+
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.failure.is_some())
+        .collect::<Vec<_>>();
+
+    if !failed.is_empty() {
+        println!("\nfailures:\n");
+        for outcome in &failed {
+            if let Some(failure) = &outcome.failure {
+                println!(
+                    "---- {} ----\n{}\n{}",
+                    outcome.name, failure.message, failure.details
+                );
+            }
+        }
+
+        println!("failures:");
+        for outcome in &failed {
+            println!("    {}", outcome.name);
+        }
+    }
+
+    let passed = outcomes.len() - failed.len();
+    println!(
+        "\ntest result: {}. {} passed; {} failed; finished in {:.2}s\n",
+        if failed.is_empty() {
+            "ok".green()
+        } else {
+            "FAILED".red()
+        },
+        passed,
+        failed.len(),
+        duration.as_secs_f64(),
+    );
+
+    if let Some(junit) = &opts.junit {
+        fs::write(junit, junit_report(&outcomes, duration))?;
+    }
+
+    Ok(if failed.is_empty() && outcomes.len() == opts.elfs.len() {
+        0
+    } else {
+        101
+    })
+}
+
+struct Interrupted;
+
+fn run_one(
+    target: &mut impl Target,
+    path: &Path,
+    name: &str,
+    opts: &Opts,
+    verify: bool,
+    fs_root: Option<&PathBuf>,
+) -> Result<Option<Failure>, Interrupted> {
+    let output = match execute(target, path, opts, verify, fs_root) {
+        Ok(Some(output)) => output,
+        Ok(None) => return Err(Interrupted),
+        Err(e) => {
+            return Ok(Some(Failure {
+                message: "error while running the program".to_string(),
+                details: format!("{:?}", e),
+            }))
+        }
+    };
+
+    let snapshot = opts.snapshots.join(format!("{}.snap", name));
+    if opts.update {
+        return Ok(fs::write(&snapshot, &output).err().map(|e| Failure {
+            message: format!("could not update snapshot `{}`", snapshot.display()),
+            details: e.to_string(),
+        }));
+    }
+
+    let expected = match fs::read_to_string(&snapshot) {
+        Ok(expected) => expected,
+        Err(e) => {
+            return Ok(Some(Failure {
+                message: format!(
+                    "could not read snapshot `{}` ({}); run with `--update` to create it",
+                    snapshot.display(),
+                    e
+                ),
+                details: output,
+            }))
+        }
+    };
+
+    if expected == output {
+        Ok(None)
+    } else {
+        Ok(Some(Failure {
+            message: "output does not match the snapshot".to_string(),
+            details: diff(&expected, &output)
+                .iter()
+                .map(|line| match line {
+                    Line::Both(s) => format!("  {}\n", s),
+                    Line::Expected(s) => format!("{}\n", format!("- {}", s).red()),
+                    Line::Observed(s) => format!("{}\n", format!("+ {}", s).green()),
+                })
+                .collect(),
+        }))
+    }
+}
+
+// Runs the program and returns its output in snapshot format
+//
+// Returns `Ok(None)` if the user pressed Ctrl-C
+fn execute(
+    target: &mut impl Target,
+    path: &Path,
+    opts: &Opts,
+    verify: bool,
+    fs_root: Option<&PathBuf>,
+) -> Result<Option<String>, anyhow::Error> {
+    let bytes = fs::read(path)?;
+    let elf = Elf::parse(&bytes)?;
+
+    crate::load(target, &elf, verify)?;
+    crate::boot(target, &elf.vectors)?;
+
+    let deadline = Instant::now() + Duration::from_secs(opts.timeout);
+    let mut output = String::new();
+    let mut io = HostIo::new(fs_root.cloned(), vec![], false);
+    let mut monitor = Monitor::new(&elf);
+    let stop = crate::run(
+        target,
+        &elf,
+        &RunOpts {
+            deadline: Some(deadline),
+            ..RunOpts::default()
+        },
+        &mut io,
+        &mut monitor,
+        |out| {
+            match out {
+                Output::Log(_, message) => {
+                    let level = match message.level {
+                        Level::Error => "ERROR",
+                        Level::Warn => "WARN",
+                        Level::Info => "INFO",
+                        Level::Debug => "DEBUG",
+                        Level::Trace => "TRACE",
+                    };
+                    // timestamps change from run to run so they are not part of the snapshot
+                    writeln!(output, "{:<5} {}", level, message.text())?;
+                }
+
+                Output::Harness(line) | Output::Notice(line) => {
+                    writeln!(output, "{}", strip_ansi(&line))?
+                }
+
+                // backtraces contain addresses, which change from build to build, so they are not
+                // part of the snapshot
+                Output::Report(line) => println!("{}", line),
+            }
+            Ok(())
+        },
+    )?;
+
+    match stop {
+        Stop::Exited(code) => {
+            writeln!(output, "(exit status: {})", code)?;
+            Ok(Some(output))
+        }
+
+        Stop::TimedOut => {
+            target.reset_halt()?;
+            Err(anyhow!(
+                "program timed out after {}s; output so far:\n{}",
+                opts.timeout,
+                output
+            ))
+        }
+
+        Stop::Interrupted => Ok(None),
+
+        Stop::Rebuilt => unreachable!(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Line<'a> {
+    Both(&'a str),
+    Expected(&'a str),
+    Observed(&'a str),
+}
+
+// line-based diff (longest common subsequence)
+fn diff<'a>(expected: &'a str, observed: &'a str) -> Vec<Line<'a>> {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let observed = observed.lines().collect::<Vec<_>>();
+
+    let (n, m) = (expected.len(), observed.len());
+    // `lcs[i][j]` = length of the LCS of `expected[i..]` and `observed[j..]`
+    let mut lcs = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == observed[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if expected[i] == observed[j] {
+            lines.push(Line::Both(expected[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(Line::Expected(expected[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Observed(observed[j]));
+            j += 1;
+        }
+    }
+    lines.extend(expected[i..].iter().map(|line| Line::Expected(line)));
+    lines.extend(observed[j..].iter().map(|line| Line::Observed(line)));
+
+    lines
+}
+
+fn junit_report(outcomes: &[Outcome], duration: Duration) -> String {
+    let failures = outcomes.iter().filter(|o| o.failure.is_some()).count();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<testsuites>\n");
+    xml.push_str(&format!(
+        "  <testsuite name=\"semidap\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        outcomes.len(),
+        failures,
+        duration.as_secs_f64()
+    ));
+    for outcome in outcomes {
+        let open = format!(
+            "    <testcase name=\"{}\" classname=\"semidap\" time=\"{:.3}\"",
+            escape(&outcome.name),
+            outcome.duration.as_secs_f64()
+        );
+
+        if let Some(failure) = &outcome.failure {
+            // the report is meant to be read by machines; strip the colors
+            let details = strip_ansi(&failure.details);
+            xml.push_str(&format!(
+                "{}>\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                open,
+                escape(&failure.message),
+                escape(&details)
+            ));
+        } else {
+            xml.push_str(&open);
+            xml.push_str("/>\n");
+        }
+    }
+    xml.push_str("  </testsuite>\n");
+    xml.push_str("</testsuites>\n");
+
+    xml
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// removes ANSI escape sequences (e.g. `\x1b[31m`)
+fn strip_ansi(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip until the final byte of the sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Line;
+
+    #[test]
+    fn diff() {
+        assert_eq!(
+            super::diff("a\nb\nc\n", "a\nc\nd\n"),
+            [
+                Line::Both("a"),
+                Line::Expected("b"),
+                Line::Both("c"),
+                Line::Observed("d"),
+            ]
+        );
+
+        assert_eq!(super::diff("a\n", "a\n"), [Line::Both("a")]);
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            super::escape("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&apos;&lt;/a&gt;"
+        );
+        assert_eq!(super::strip_ansi("\x1b[31m- a\x1b[0m"), "- a");
+    }
+}
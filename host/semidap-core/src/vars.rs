@@ -0,0 +1,176 @@
+//! Live view of the program's `static` variables
+
+use core::{convert::TryInto, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
+use crate::{elf::Elf, target::Target};
+
+/// A variable specified as `SYMBOL[:TYPE]`
+pub struct Var {
+    name: String,
+    address: u32,
+    size: u32,
+    ty: Type,
+    // last observed contents
+    last: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Type {
+    Bool,
+    F32,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    /// Raw bytes
+    Bytes,
+}
+
+impl FromStr for Type {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, anyhow::Error> {
+        Ok(match s {
+            "bool" => Type::Bool,
+            "f32" => Type::F32,
+            "i8" => Type::I8,
+            "i16" => Type::I16,
+            "i32" => Type::I32,
+            "i64" => Type::I64,
+            "u8" => Type::U8,
+            "u16" => Type::U16,
+            "u32" => Type::U32,
+            "u64" => Type::U64,
+            "bytes" => Type::Bytes,
+            _ => bail!(
+                "unknown type `{}`; expected one of: bool, f32, i8, i16, i32, i64, u8, u16, u32, \
+                 u64, bytes",
+                s
+            ),
+        })
+    }
+}
+
+impl Type {
+    fn size(self) -> u32 {
+        match self {
+            Type::Bool | Type::I8 | Type::U8 | Type::Bytes => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::F32 | Type::I32 | Type::U32 => 4,
+            Type::I64 | Type::U64 => 8,
+        }
+    }
+
+    // formats a single element of this type
+    fn format(self, bytes: &[u8]) -> String {
+        match self {
+            Type::Bool => (bytes[0] != 0).to_string(),
+            Type::F32 => f32::from_le_bytes(bytes.try_into().unwrap()).to_string(),
+            Type::I8 => (bytes[0] as i8).to_string(),
+            Type::I16 => i16::from_le_bytes(bytes.try_into().unwrap()).to_string(),
+            Type::I32 => i32::from_le_bytes(bytes.try_into().unwrap()).to_string(),
+            Type::I64 => i64::from_le_bytes(bytes.try_into().unwrap()).to_string(),
+            Type::U8 => bytes[0].to_string(),
+            Type::U16 => u16::from_le_bytes(bytes.try_into().unwrap()).to_string(),
+            Type::U32 => u32::from_le_bytes(bytes.try_into().unwrap()).to_string(),
+            Type::U64 => u64::from_le_bytes(bytes.try_into().unwrap()).to_string(),
+            Type::Bytes => format!("{:#04x}", bytes[0]),
+        }
+    }
+}
+
+// formats the contents of a variable; variables larger than `ty` are formatted as arrays
+fn format(ty: Type, bytes: &[u8]) -> String {
+    let elements = bytes
+        .chunks(ty.size() as usize)
+        .map(|chunk| ty.format(chunk))
+        .collect::<Vec<_>>();
+
+    if elements.len() == 1 {
+        elements.into_iter().next().unwrap()
+    } else {
+        format!("[{}]", elements.join(", "))
+    }
+}
+
+impl Var {
+    /// Resolves the `SYMBOL[:TYPE]` specification against the symbols of the program
+    pub fn new(spec: &str, elf: &Elf) -> Result<Self, anyhow::Error> {
+        let mut parts = spec.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let (address, size) = elf.object(name)?;
+
+        let ty = if let Some(ty) = parts.next() {
+            ty.parse()?
+        } else {
+            // assume an unsigned integer when the size matches one
+            match size {
+                1 => Type::U8,
+                2 => Type::U16,
+                4 => Type::U32,
+                8 => Type::U64,
+                _ => Type::Bytes,
+            }
+        };
+
+        if size % ty.size() != 0 {
+            return Err(anyhow!(
+                "`{}` is {} bytes in size; that's not a multiple of the size of `{:?}`",
+                name,
+                size,
+                ty
+            ));
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            address,
+            size,
+            ty,
+            last: None,
+        })
+    }
+
+    /// Must be called every time the program (re)boots
+    pub fn boot(&mut self) {
+        self.last = None;
+    }
+
+    /// Reads the variable; returns a line to report if its value changed
+    pub fn poll(&mut self, target: &mut impl Target) -> Result<Option<String>, anyhow::Error> {
+        let bytes = target.memory_read_bytes(self.address, self.size)?;
+        if self.last.as_ref() == Some(&bytes) {
+            return Ok(None);
+        }
+
+        let line = format!("var> {} = {}", self.name, format(self.ty, &bytes));
+        self.last = Some(bytes);
+        Ok(Some(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Type;
+
+    #[test]
+    fn format() {
+        assert_eq!(super::format(Type::U8, &[3]), "3");
+        assert_eq!(super::format(Type::I16, &[0xfe, 0xff]), "-2");
+        assert_eq!(super::format(Type::Bool, &[1]), "true");
+        assert_eq!(
+            super::format(Type::U32, &[1, 0, 0, 0, 2, 0, 0, 0]),
+            "[1, 2]"
+        );
+        assert_eq!(super::format(Type::Bytes, &[0xa, 0xb]), "[0x0a, 0x0b]");
+        assert_eq!(super::format(Type::F32, &1.5f32.to_le_bytes()), "1.5");
+
+        assert!("usize".parse::<Type>().is_err());
+    }
+}
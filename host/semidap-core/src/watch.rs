@@ -0,0 +1,289 @@
+//! Watch mode: rebuilds the program when its source changes and reloads it into the target
+
+use core::{
+    hash::Hasher,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail};
+use colored::*;
+use log::{debug, info};
+
+use crate::{elf::Elf, hostio::HostIo, monitor, target::Target, Output, RunOpts, Stop, CONTINUE};
+
+/// Set by the watcher thread after a successful rebuild; see `wait_for_halt`
+pub static REBUILT: AtomicBool = AtomicBool::new(false);
+
+// how often the source files are checked for changes
+const POLL_PERIOD: Duration = Duration::from_millis(500);
+
+/// Runs the program at `path`; rebuilds and reloads it every time a file in the current
+/// directory changes until the user presses Ctrl-C
+pub fn run(
+    target: &mut impl Target,
+    path: &Path,
+    verify: bool,
+    fs_root: Option<PathBuf>,
+    args: Vec<String>,
+    monitor: &monitor::Opts,
+) -> Result<i32, anyhow::Error> {
+    let mut build = build_command(path)?;
+    let root = PathBuf::from(".");
+    thread::spawn(move || {
+        let mut last = fingerprint(&root);
+        loop {
+            thread::sleep(POLL_PERIOD);
+
+            let current = fingerprint(&root);
+            if current == last {
+                continue;
+            }
+            last = current;
+
+            info!("change detected; running {:?}", build);
+            match build.status() {
+                Ok(status) if status.success() => REBUILT.store(true, Ordering::Relaxed),
+                Ok(_) => eprintln!("{}", "build failed; waiting for changes".red()),
+                Err(e) => eprintln!("couldn't run cargo: {}", e),
+            }
+        }
+    });
+
+    // (address, content hash) of the sections currently loaded into the target
+    let mut loaded = BTreeMap::new();
+    let mut hostio = None;
+    loop {
+        let bytes = fs::read(path)?;
+        let elf = Elf::parse(&bytes)?;
+        // NOTE the location of the stack may change from build to build
+        let mut monitor = monitor.build(&elf)?;
+
+        if loaded.is_empty() {
+            crate::load(target, &elf, verify)?;
+            for section in &elf.sections {
+                loaded.insert(
+                    section.name.to_owned(),
+                    (section.address, hash(section.bytes)),
+                );
+            }
+        } else {
+            reload(target, &elf, &mut loaded, verify)?;
+        }
+        monitor.boot(target)?;
+        crate::boot(target, &elf.vectors)?;
+
+        let hostio = hostio.get_or_insert_with(|| {
+            HostIo::new(
+                fs_root.clone(),
+                args.clone(),
+                elf.semidap_stdin_cursor.is_some(),
+            )
+        });
+
+        let stdout = io::stdout();
+        let stop = crate::run(
+            target,
+            &elf,
+            &RunOpts::default(),
+            hostio,
+            &mut monitor,
+            |output| {
+                let mut stdout = stdout.lock();
+                match output {
+                    Output::Log(src, message) => writeln!(stdout, "{}>{}", src, message)?,
+                    Output::Harness(line) | Output::Notice(line) | Output::Report(line) => {
+                        writeln!(stdout, "{}", line)?
+                    }
+                }
+                Ok(())
+            },
+        )?;
+
+        for line in monitor.finish(target, &elf)? {
+            println!("{}", line);
+        }
+
+        match stop {
+            Stop::Exited(code) => {
+                println!(
+                    "{}",
+                    format!("program exited with code {}; waiting for changes", code).dimmed()
+                );
+
+                while !REBUILT.swap(false, Ordering::Relaxed) {
+                    if !CONTINUE.load(Ordering::Relaxed) {
+                        return Ok(0);
+                    }
+                    thread::sleep(POLL_PERIOD / 5);
+                }
+            }
+
+            // `wait_for_halt` has already drained the logs
+            Stop::Rebuilt => target.halt()?,
+
+            Stop::Interrupted | Stop::TimedOut => {
+                target.reset_halt()?;
+                return Ok(0);
+            }
+        }
+
+        println!(
+            "{}",
+            format!("──── reloading `{}` ────", path.display()).dimmed()
+        );
+    }
+}
+
+/// Resets and halts the target and then loads the sections of `elf` that differ from the ones
+/// `loaded` into the target
+fn reload(
+    target: &mut impl Target,
+    elf: &Elf,
+    loaded: &mut BTreeMap<String, (u32, u64)>,
+    verify: bool,
+) -> Result<(), anyhow::Error> {
+    debug!("resetting and halting the target");
+    target.reset_halt()?;
+
+    let mut skipped = 0;
+    for section in &elf.sections {
+        let hash = hash(section.bytes);
+
+        // NOTE RAM is preserved across resets but the program may have modified its writable
+        // sections so those are always reloaded
+        if !section.writable && loaded.get(section.name) == Some(&(section.address, hash)) {
+            debug!("section `{}` is unchanged", section.name);
+            skipped += 1;
+            continue;
+        }
+
+        target.memory_write_bytes(section.address, section.bytes)?;
+        info!("loaded `{}` ({} B)", section.name, section.bytes.len());
+
+        if verify {
+            let bytes = target.memory_read_bytes(section.address, section.bytes.len() as u32)?;
+            if bytes != section.bytes {
+                bail!("verification of section `{}` failed", section.name);
+            }
+        }
+
+        loaded.insert(section.name.to_owned(), (section.address, hash));
+    }
+    info!("skipped {} unchanged section(s)", skipped);
+
+    if let Some(next) = elf.harness_next {
+        // start from the first test
+        target.memory_write_word(next, 0)?;
+    }
+
+    Ok(())
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+// Number of files and most recent modification time of the files in `dir`; build artifacts and
+// hidden files are ignored
+fn fingerprint(dir: &Path) -> (usize, Option<SystemTime>) {
+    fn visit(dir: &Path, fp: &mut (usize, Option<SystemTime>)) {
+        let entries = if let Ok(entries) = fs::read_dir(dir) {
+            entries
+        } else {
+            return;
+        };
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') || name == "target" {
+                continue;
+            }
+
+            let path = entry.path();
+            if path.is_dir() {
+                visit(&path, fp);
+            } else if let Ok(modified) = entry.metadata().and_then(|md| md.modified()) {
+                fp.0 += 1;
+                fp.1 = fp.1.max(Some(modified));
+            }
+        }
+    }
+
+    let mut fp = (0, None);
+    visit(dir, &mut fp);
+    fp
+}
+
+// Derives the `cargo build` invocation that produces `elf` from its path, e.g.
+// `target/thumbv7em-none-eabi/release/examples/foo` is produced by `cargo build --target
+// thumbv7em-none-eabi --release --example foo`
+fn build_command(elf: &Path) -> Result<Command, anyhow::Error> {
+    let unknown = || anyhow!("couldn't tell how `{}` was built", elf.display());
+    let name = |path: &Path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_owned)
+    };
+
+    let binary = name(elf).ok_or_else(unknown)?;
+    let mut dir = elf.parent().ok_or_else(unknown)?;
+    let kind = if name(dir).as_ref().map(|s| &s[..]) == Some("examples") {
+        dir = dir.parent().ok_or_else(unknown)?;
+        "--example"
+    } else {
+        "--bin"
+    };
+
+    let mut cmd = Command::new("cargo");
+    cmd.arg("build");
+    if let Some(triple) = dir
+        .parent()
+        .and_then(name)
+        .filter(|name| name.contains('-'))
+    {
+        cmd.args(&["--target", &triple]);
+    }
+    match name(dir).as_ref().map(|s| &s[..]) {
+        Some("debug") => {}
+        Some("release") => {
+            cmd.arg("--release");
+        }
+        _ => return Err(unknown()),
+    }
+    cmd.args(&[kind, &binary]);
+
+    Ok(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    #[test]
+    fn build_command() {
+        let cmd = |path| format!("{:?}", super::build_command(Path::new(path)).unwrap());
+
+        assert_eq!(
+            cmd("/firmware/target/thumbv7em-none-eabi/release/examples/hello"),
+            r#""cargo" "build" "--target" "thumbv7em-none-eabi" "--release" "--example" "hello""#
+        );
+        assert_eq!(
+            cmd("target/thumbv7em-none-eabi/debug/stdin"),
+            r#""cargo" "build" "--target" "thumbv7em-none-eabi" "--bin" "stdin""#
+        );
+        assert_eq!(cmd("target/debug/app"), r#""cargo" "build" "--bin" "app""#);
+        assert!(super::build_command(Path::new("app")).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Project configuration (`semidap.toml`) and probe selection
+
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use anyhow::{anyhow, bail};
+use cmsis_dap::{Dap, Probe};
+use log::debug;
+use serde::{Deserialize, Deserializer};
+
+const FILE_NAME: &str = "semidap.toml";
+
+/// Contents of the `semidap.toml` file
+///
+/// Command line flags take precedence over the values in this file
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Default vendor ID
+    #[serde(default, deserialize_with = "hex")]
+    vendor: Option<u16>,
+
+    /// Default product ID
+    #[serde(default, deserialize_with = "hex")]
+    product: Option<u16>,
+
+    /// Default probe: a board name, a serial number or an index into `semidap list`
+    probe: Option<String>,
+
+    /// Verify the program after loading it
+    #[serde(default)]
+    pub verify: bool,
+
+    /// Directory the program can access through the host I/O system calls; relative to the
+    /// directory that contains `semidap.toml`
+    pub fs_root: Option<PathBuf>,
+
+    /// `static` variables to watch, in `SYMBOL[:TYPE]` format; see `--watch-var`
+    #[serde(default)]
+    pub watch_vars: Vec<String>,
+
+    /// Friendly board names and the serial number of the probe attached to each board
+    #[serde(default)]
+    boards: BTreeMap<String, String>,
+}
+
+/// A probe selected from the command line and / or the configuration file
+#[derive(Debug, PartialEq)]
+pub struct Selection {
+    pub vendor: u16,
+    pub product: u16,
+    pub sn: Option<String>,
+}
+
+fn hex<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    crate::parse_hex(&s)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl Config {
+    /// Looks for `semidap.toml` in the current directory and its ancestors
+    ///
+    /// Returns the default configuration if there's no such file
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let cwd = env::current_dir()?;
+        let path = if let Some(path) = cwd
+            .ancestors()
+            .map(|dir| dir.join(FILE_NAME))
+            .find(|path| path.is_file())
+        {
+            path
+        } else {
+            return Ok(Config::default());
+        };
+
+        debug!("using configuration file `{}`", path.display());
+        let mut config = Self::parse(&fs::read_to_string(&path)?)
+            .map_err(|e| anyhow!("`{}`: {}", path.display(), e))?;
+        if let (Some(fs_root), Some(dir)) = (config.fs_root.as_mut(), path.parent()) {
+            *fs_root = dir.join(&*fs_root);
+        }
+
+        Ok(config)
+    }
+
+    fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Returns the name of the board the probe with serial number `sn` is attached to
+    pub fn board_name(&self, sn: &str) -> Option<&str> {
+        self.boards
+            .iter()
+            .find(|(_, board_sn)| *board_sn == sn)
+            .map(|(name, _)| &name[..])
+    }
+
+    /// Selects a probe
+    ///
+    /// `vendor`, `product` and `probe` come from the command line and take precedence over the
+    /// configuration file. `probe` can be a board name, a serial number or an index into the
+    /// list of connected probes
+    pub fn select(
+        &self,
+        vendor: Option<u16>,
+        product: Option<u16>,
+        probe: Option<&str>,
+    ) -> Result<Selection, anyhow::Error> {
+        self.select_from(vendor, product, probe, &Dap::list()?)
+    }
+
+    fn select_from(
+        &self,
+        vendor: Option<u16>,
+        product: Option<u16>,
+        probe: Option<&str>,
+        probes: &[Probe],
+    ) -> Result<Selection, anyhow::Error> {
+        let vendor = vendor.or(self.vendor);
+        let product = product.or(self.product);
+        let matches = |probe: &&Probe| {
+            vendor.map(|vendor| probe.vendor == vendor) != Some(false)
+                && product.map(|product| probe.product == product) != Some(false)
+        };
+        let selection = |probe: &Probe| Selection {
+            vendor: probe.vendor,
+            product: probe.product,
+            sn: probe.serial_number.clone(),
+        };
+
+        if let Some(probe) = probe.or_else(|| self.probe.as_ref().map(|s| &s[..])) {
+            let sn = self.boards.get(probe).map(|sn| &sn[..]).unwrap_or(probe);
+
+            if let Some(found) = probes
+                .iter()
+                .filter(matches)
+                .find(|probe| probe.serial_number.as_ref().map(|s| &s[..]) == Some(sn))
+            {
+                return Ok(selection(found));
+            }
+
+            // NOTE serial numbers can be numeric (e.g. `0001`); those are not indices
+            if let Some(found) = probe
+                .parse::<usize>()
+                .ok()
+                .filter(|i| i.to_string() == probe)
+                .and_then(|i| probes.get(i))
+            {
+                return Ok(selection(found));
+            }
+
+            if let (Some(vendor), Some(product)) = (vendor, product) {
+                // the probe may not identify itself as a CMSIS-DAP device; let `Dap::open` try
+                return Ok(Selection {
+                    vendor,
+                    product,
+                    sn: Some(sn.to_owned()),
+                });
+            }
+
+            bail!(
+                "no probe matches `{}`; use `semidap list` to list the connected probes",
+                probe
+            )
+        }
+
+        if let (Some(vendor), Some(product)) = (vendor, product) {
+            // NOTE `Dap::open` reports an error if more than one probe matches
+            return Ok(Selection {
+                vendor,
+                product,
+                sn: None,
+            });
+        }
+
+        let candidates = probes.iter().filter(matches).collect::<Vec<_>>();
+        match candidates.len() {
+            0 => bail!("no CMSIS-DAP probe was found"),
+            1 => Ok(selection(candidates[0])),
+            n => bail!(
+                "{} probes were found; select one with `--probe` or in `{}` \
+                 (use `semidap list` to list them)",
+                n,
+                FILE_NAME
+            ),
+        }
+    }
+}
+
+/// Prints the connected probes
+pub fn list(config: &Config) -> Result<(), anyhow::Error> {
+    let probes = Dap::list()?;
+    if probes.is_empty() {
+        println!("no CMSIS-DAP probe was found");
+    }
+
+    for (i, probe) in probes.iter().enumerate() {
+        let sn = probe.serial_number.as_ref().map(|s| &s[..]);
+        print!(
+            "{:>2}: {:04x}:{:04x} S/N {} -- {}",
+            i,
+            probe.vendor,
+            probe.product,
+            sn.unwrap_or("?"),
+            probe.product_string.as_ref().map(|s| &s[..]).unwrap_or("?"),
+        );
+        if let Some(name) = sn.and_then(|sn| config.board_name(sn)) {
+            print!(" ({})", name);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cmsis_dap::Probe;
+
+    use super::{Config, Selection};
+
+    fn probe(vendor: u16, sn: &str) -> Probe {
+        Probe {
+            vendor,
+            product: 0x0204,
+            serial_number: Some(sn.to_owned()),
+            product_string: Some("DAPLink CMSIS-DAP".to_owned()),
+        }
+    }
+
+    fn selection(vendor: u16, sn: Option<&str>) -> Selection {
+        Selection {
+            vendor,
+            product: 0x0204,
+            sn: sn.map(|s| s.to_owned()),
+        }
+    }
+
+    #[test]
+    fn select() {
+        let config = Config::parse(
+            r#"
+verify = true
+
+[boards]
+tx = "1026000012345678"
+"#,
+        )
+        .unwrap();
+        assert!(config.verify);
+
+        let probes = [probe(0x0d28, "1026000012345678"), probe(0x0d28, "0001")];
+
+        // by board name, serial number and index
+        assert_eq!(
+            config.select_from(None, None, Some("tx"), &probes).unwrap(),
+            selection(0x0d28, Some("1026000012345678"))
+        );
+        assert_eq!(
+            config
+                .select_from(None, None, Some("0001"), &probes)
+                .unwrap(),
+            selection(0x0d28, Some("0001"))
+        );
+        assert_eq!(
+            config.select_from(None, None, Some("1"), &probes).unwrap(),
+            selection(0x0d28, Some("0001"))
+        );
+        assert!(config.select_from(None, None, Some("rx"), &probes).is_err());
+
+        // ambiguous
+        assert!(config.select_from(None, None, None, &probes).is_err());
+        assert_eq!(
+            config.select_from(None, None, None, &probes[..1]).unwrap(),
+            selection(0x0d28, Some("1026000012345678"))
+        );
+
+        // defaults from the configuration file
+        let config = Config::parse(
+            r#"
+vendor = "1366"
+product = "0204"
+probe = "0001"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.select_from(None, None, None, &probes).unwrap(),
+            selection(0x1366, Some("0001"))
+        );
+        assert_eq!(
+            config
+                .select_from(Some(0x0d28), None, None, &probes)
+                .unwrap(),
+            selection(0x0d28, Some("0001"))
+        );
+
+        assert!(Config::parse("speed = 4").is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! Multi-target mode: runs programs on several targets at once and merges their output
+
+use core::str::FromStr;
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use cmsis_dap::Dap;
+use log::info;
+use semidap_core::{elf::Elf, hostio::HostIo, monitor::Monitor, Output, RunOpts, Stop};
+
+use crate::config::Selection;
+
+/// A target, specified as `NAME:PROBE:ELF` on the command line
+pub struct Board {
+    name: String,
+    /// a board name, a serial number or an index; see `Config::select`
+    pub probe: String,
+    elf: PathBuf,
+}
+
+impl FromStr for Board {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, anyhow::Error> {
+        // NOTE the ELF path goes last because it may contain colons
+        let mut parts = s.splitn(3, ':');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some(probe), Some(elf))
+                if !name.is_empty() && !probe.is_empty() && !elf.is_empty() =>
+            {
+                Ok(Board {
+                    name: name.to_owned(),
+                    probe: probe.to_owned(),
+                    elf: elf.into(),
+                })
+            }
+            _ => Err(anyhow!("expected `NAME:PROBE:ELF`, found `{}`", s)),
+        }
+    }
+}
+
+// A line of output produced by one of the boards
+struct Line {
+    board: usize,
+    // when the line was received, relative to `beginning`
+    time: Duration,
+    text: String,
+}
+
+/// Loads and runs the programs on all `boards` in parallel until all of them exit
+///
+/// Returns the exit code of the first board, in command line order, that exited with a non-zero
+/// code
+pub fn run(
+    boards: Vec<(Board, Selection)>,
+    verify: bool,
+    fs_root: Option<PathBuf>,
+    beginning: Instant,
+) -> Result<i32, anyhow::Error> {
+    let units = boards
+        .iter()
+        .map(|(board, selection)| {
+            let sn = selection.sn.as_ref().ok_or_else(|| {
+                anyhow!("the probe of board `{}` has no serial number", board.name)
+            })?;
+            Ok((selection.vendor, selection.product, &sn[..]))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    let daps = Dap::open_all(&units)?;
+    let boards = boards
+        .into_iter()
+        .map(|(board, _)| board)
+        .collect::<Vec<_>>();
+
+    let width = boards
+        .iter()
+        .map(|board| board.name.len())
+        .max()
+        .unwrap_or(0);
+    let names = boards
+        .iter()
+        .map(|board| board.name.clone())
+        .collect::<Vec<_>>();
+
+    let (tx, rx) = mpsc::channel();
+    let threads = boards
+        .into_iter()
+        .zip(daps)
+        .enumerate()
+        .map(|(i, (board, dap))| {
+            let tx = tx.clone();
+            let fs_root = fs_root.clone();
+            thread::spawn(move || {
+                run_board(dap, i, &board, verify, fs_root, beginning, &tx)
+                    .map_err(|e| e.context(format!("board `{}`", board.name)))
+            })
+        })
+        .collect::<Vec<_>>();
+    // all the senders must be dropped for the loop below to end
+    drop(tx);
+
+    // lines are printed in the order in which the host received them
+    let stdout = io::stdout();
+    for line in rx {
+        // NOTE lock per line; holding the lock while waiting on `rx` would block other threads
+        writeln!(
+            stdout.lock(),
+            "{:>4}.{:06} {:<width$} | {}",
+            line.time.as_secs(),
+            line.time.subsec_micros(),
+            names[line.board],
+            line.text,
+            width = width,
+        )?;
+    }
+
+    let mut exit_code = 0;
+    for thread in threads {
+        let code = thread
+            .join()
+            .map_err(|_| anyhow!("a board thread panicked"))??;
+        if exit_code == 0 {
+            exit_code = code;
+        }
+    }
+
+    Ok(exit_code)
+}
+
+fn run_board(
+    mut dap: Dap,
+    i: usize,
+    board: &Board,
+    verify: bool,
+    fs_root: Option<PathBuf>,
+    beginning: Instant,
+    tx: &Sender<Line>,
+) -> Result<i32, anyhow::Error> {
+    crate::configure(&mut dap)?;
+
+    let bytes = fs::read(&board.elf)?;
+    let elf = Elf::parse(&bytes)?;
+
+    semidap_core::load(&mut dap, &elf, verify)?;
+    info!(
+        "booting program on board `{}` (start to end: {:?})",
+        board.name,
+        Instant::now() - beginning
+    );
+    semidap_core::boot(&mut dap, &elf.vectors)?;
+
+    // NOTE the standard input is not forwarded; it's not clear which board should receive it
+    let mut io = HostIo::new(fs_root, vec![], false);
+    let mut monitor = Monitor::new(&elf);
+    let send = |text| {
+        // NOTE the receiver only goes away if the main thread failed
+        let _ = tx.send(Line {
+            board: i,
+            time: Instant::now() - beginning,
+            text,
+        });
+    };
+    let stop = semidap_core::run(
+        &mut dap,
+        &elf,
+        &RunOpts::default(),
+        &mut io,
+        &mut monitor,
+        |output| {
+            send(match output {
+                Output::Log(src, message) => format!("{}>{}", src, message),
+                Output::Harness(line) | Output::Notice(line) | Output::Report(line) => line,
+            });
+            Ok(())
+        },
+    )?;
+
+    for line in monitor.finish(&mut dap, &elf)? {
+        send(line);
+    }
+
+    match stop {
+        Stop::Exited(code) => Ok(code),
+
+        Stop::Rebuilt => unreachable!(),
+
+        Stop::Interrupted | Stop::TimedOut => {
+            dap.sysresetreq(true)?;
+            Ok(0)
+        }
+    }
+}
@@ -1,15 +1,69 @@
 use proc_macro::TokenStream;
 
-use proc_macro2::{Ident as Ident2, Span as Span2, TokenStream as TokenStream2};
+use proc_macro2::{Ident as Ident2, Span as Span2, TokenStream as TokenStream2, TokenTree};
 use quote::{format_ident, quote};
 use std::time::SystemTime;
 use syn::{
     parse::{self, ParseStream},
     parse_macro_input,
     spanned::Spanned,
-    Block, Expr, ItemMod, ItemUse, ReturnType, Stmt, Type, Visibility,
+    Attribute, Block, Expr, ItemMod, ItemUse, Lit, Meta, ReturnType, Stmt, Type, Visibility,
 };
 
+// the nRF52840 interrupts and their position in the vector table
+const INTERRUPTS: &[(&str, u8)] = &[
+    ("POWER_CLOCK", 0),
+    ("RADIO", 1),
+    ("UARTE0_UART0", 2),
+    ("SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0", 3),
+    ("SPIM1_SPIS1_TWIM1_TWIS1_SPI1_TWI1", 4),
+    ("NFCT", 5),
+    ("GPIOTE", 6),
+    ("SAADC", 7),
+    ("TIMER0", 8),
+    ("TIMER1", 9),
+    ("TIMER2", 10),
+    ("RTC0", 11),
+    ("TEMP", 12),
+    ("RNG", 13),
+    ("ECB", 14),
+    ("CCM_AAR", 15),
+    ("WDT", 16),
+    ("RTC1", 17),
+    ("QDEC", 18),
+    ("COMP_LPCOMP", 19),
+    ("SWI0_EGU0", 20),
+    ("SWI1_EGU1", 21),
+    ("SWI2_EGU2", 22),
+    ("SWI3_EGU3", 23),
+    ("SWI4_EGU4", 24),
+    ("SWI5_EGU5", 25),
+    ("TIMER3", 26),
+    ("TIMER4", 27),
+    ("PWM0", 28),
+    ("PDM", 29),
+    ("MWU", 32),
+    ("PWM1", 33),
+    ("PWM2", 34),
+    ("SPIM2_SPIS2_SPI2", 35),
+    ("RTC2", 36),
+    ("I2S", 37),
+    ("FPU", 38),
+    ("USBD", 39),
+    ("UARTE1", 40),
+    ("QSPI", 41),
+    ("CRYPTOCELL", 42),
+    ("PWM3", 45),
+    ("SPIM3", 47),
+];
+
+// number of priority bits implemented by the nRF52840
+const NVIC_PRIO_BITS: u8 = 3;
+
+// the priority of the functions that don't declare one; this is the reset value of the NVIC
+// priority registers
+const DEFAULT_PRIORITY: u8 = 1 << NVIC_PRIO_BITS;
+
 #[proc_macro_attribute]
 pub fn declare(args: TokenStream, input: TokenStream) -> TokenStream {
     if !args.is_empty() {
@@ -20,16 +74,110 @@ pub fn declare(args: TokenStream, input: TokenStream) -> TokenStream {
 
     let input = parse_macro_input!(input as Input);
 
+    match expand(input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(mut input: Input) -> parse::Result<TokenStream2> {
     let mut items = vec![];
     let mut citems = vec![];
-    let shared_args = &to_args(&input.statics).collect::<Vec<_>>();
-    let shared_params = &to_params(&input.statics, false).collect::<Vec<_>>();
+
+    // `init` sets the priorities so there must be one
+    if !input.fns.iter().any(|f| f.name == "init") && input.fns.iter().any(|f| f.priority.is_some())
+    {
+        input.fns.push(Fn {
+            locals: vec![],
+            name: format_ident!("init"),
+            output: ReturnType::Default,
+            priority: None,
+            stmts: vec![],
+        });
+    }
 
     citems.extend(to_statics(&input.statics));
 
+    // the functions that use each shared static
+    let users = input
+        .statics
+        .iter()
+        .map(|s| {
+            input
+                .fns
+                .iter()
+                .filter(|f| f.name != "init" && f.uses(&s.name))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut is_send = false;
+    for (s, users) in input.statics.iter().zip(&users) {
+        if users.iter().any(|f| f.priority() != users[0].priority()) {
+            // the static is accessed from different execution contexts
+            let ty = &s.ty;
+            citems.push(quote!(
+                const _: fn() = is_send::<#ty>;
+            ));
+            is_send = true;
+        }
+    }
+
+    if is_send {
+        citems.push(quote!(
+            fn is_send<T: Send>() {}
+        ));
+    }
+
     for (i, f) in input.fns.iter().enumerate() {
         let name = &f.name;
         let is_init = *name == "init";
+
+        let mut shared_args = vec![];
+        let mut shared_params = vec![];
+        for (s, users) in input.statics.iter().zip(&users) {
+            if !is_init && !f.uses(&s.name) {
+                continue;
+            }
+
+            let sname = &s.name;
+            let ty = &s.ty;
+            let ceiling = users.iter().map(|f| f.priority()).max().unwrap_or(0);
+            if is_init || f.priority() == ceiling {
+                shared_args.push(quote!(&mut #sname));
+                shared_params.push(quote!(
+                    #[allow(unused_variables)]
+                    #[allow(non_snake_case)]
+                    #sname: &mut #ty
+                ));
+            } else {
+                // mask the higher priority users while the lock is held
+                let mut masks = [0u32; 2];
+                for user in users.iter().filter(|u| u.priority() > f.priority()) {
+                    let nr = interrupt(&user.name).ok_or_else(|| {
+                        parse::Error::new(
+                            user.name.span(),
+                            format!(
+                                "`{}` shares `{}` with lower priority functions so it must be an \
+                                 nRF52840 interrupt handler",
+                                user.name, sname
+                            ),
+                        )
+                    })?;
+                    masks[usize::from(nr / 32)] |= 1 << (nr % 32);
+                }
+                let (mask0, mask1) = (masks[0], masks[1]);
+
+                shared_args.push(quote!(tasks::Lock::new(&mut #sname, [#mask0, #mask1])));
+                shared_params.push(quote!(
+                    #[allow(unused_mut)]
+                    #[allow(unused_variables)]
+                    #[allow(non_snake_case)]
+                    mut #sname: tasks::Lock<'_, #ty>
+                ));
+            }
+        }
+
         let params = to_params(&f.locals, is_init);
         let stmts = &f.stmts;
         let output = &f.output;
@@ -42,9 +190,21 @@ pub fn declare(args: TokenStream, input: TokenStream) -> TokenStream {
         ));
 
         let mut no_mangle = quote!(#[no_mangle]);
+        let mut priorities = vec![];
         let static_ = if is_init {
             no_mangle = quote!();
             let section = format!(".init.{}.{}", input.name, pseudo_rand());
+
+            for f in &input.fns {
+                if let Some(priority) = f.priority {
+                    // NOTE `priority` has been validated and `f` is an interrupt
+                    let nr = interrupt(&f.name).unwrap();
+                    // higher logical priority = lower hardware value = more urgent
+                    let hw_priority = ((1u8 << NVIC_PRIO_BITS) - priority) << (8 - NVIC_PRIO_BITS);
+                    priorities.push(quote!(tasks::set_priority(#nr, #hw_priority);));
+                }
+            }
+
             quote! (
                 #[link_section = #section]
                 #[used]
@@ -62,6 +222,8 @@ pub fn declare(args: TokenStream, input: TokenStream) -> TokenStream {
             unsafe extern "C" fn #name() {
                 #(#locals)*
 
+                #(#priorities)*
+
                 drop(#i(#(#shared_args,)* #(#args),*))
             }
         ))
@@ -70,7 +232,7 @@ pub fn declare(args: TokenStream, input: TokenStream) -> TokenStream {
     let name = &input.name;
     let uses = &input.uses;
 
-    quote!(
+    Ok(quote!(
         mod #name {
             #(#uses)*
 
@@ -80,8 +242,78 @@ pub fn declare(args: TokenStream, input: TokenStream) -> TokenStream {
                 #(#citems)*
             };
         }
-    )
-    .into()
+    ))
+}
+
+fn interrupt(name: &Ident2) -> Option<u8> {
+    INTERRUPTS
+        .iter()
+        .find(|(interrupt, _)| name == interrupt)
+        .map(|(_, nr)| *nr)
+}
+
+// does `tokens` contain the identifier `name`?
+//
+// NOTE this is a token scan, not name resolution: shadowing bindings (`let COUNT = ..`) and path
+// segments (`foo::COUNT`) also match. Such false positives only make the analysis more
+// conservative (higher ceilings, extra `Send` checks); there are no false negatives because the
+// statics are only reachable through the parameters named after them
+fn mentions(tokens: TokenStream2, name: &Ident2) -> bool {
+    tokens.into_iter().any(|tt| match tt {
+        TokenTree::Ident(ident) => ident == *name,
+        TokenTree::Group(group) => mentions(group.stream(), name),
+        _ => false,
+    })
+}
+
+// parses the `#[priority = N]` attribute
+fn priority(attrs: Vec<Attribute>, name: &Ident2) -> parse::Result<Option<u8>> {
+    let mut priority = None;
+    for attr in attrs {
+        let span = attr.span();
+        let meta = attr.parse_meta()?;
+        let lit = match meta {
+            Meta::NameValue(ref nv) if nv.path.is_ident("priority") && priority.is_none() => {
+                &nv.lit
+            }
+            _ => {
+                return Err(parse::Error::new(
+                    span,
+                    "function must have no attributes other than `#[priority = N]`",
+                ))
+            }
+        };
+
+        let value = match lit {
+            Lit::Int(int) => int.base10_parse::<u8>()?,
+            _ => return Err(parse::Error::new(lit.span(), "expected an integer")),
+        };
+
+        if value == 0 || value > DEFAULT_PRIORITY {
+            return Err(parse::Error::new(
+                lit.span(),
+                format!("priority must be in the range 1..={}", DEFAULT_PRIORITY),
+            ));
+        }
+
+        if name == "init" {
+            return Err(parse::Error::new(
+                span,
+                "`init` runs before interrupts are enabled; it can't have a priority",
+            ));
+        }
+
+        if interrupt(name).is_none() {
+            return Err(parse::Error::new(
+                span,
+                "only nRF52840 interrupt handlers can have a priority",
+            ));
+        }
+
+        priority = Some(value);
+    }
+
+    Ok(priority)
 }
 
 struct Input {
@@ -113,13 +345,6 @@ impl parse::Parse for Input {
             for item in items {
                 match item {
                     syn::Item::Fn(f) => {
-                        if !f.attrs.is_empty() {
-                            return Err(parse::Error::new(
-                                f.span(),
-                                "function must have no attributes",
-                            ));
-                        }
-
                         if f.vis != Visibility::Inherited {
                             return Err(parse::Error::new(f.span(), "function must be private"));
                         }
@@ -140,12 +365,14 @@ impl parse::Parse for Input {
                             ));
                         }
 
+                        let priority = priority(f.attrs, &f.sig.ident)?;
                         let (locals, stmts) = split(*f.block)?;
 
                         fns.push(Fn {
                             locals,
                             name: f.sig.ident,
                             output: f.sig.output,
+                            priority,
                             stmts,
                         });
                     }
@@ -295,12 +522,113 @@ struct Fn {
     locals: Vec<Static>,
     name: Ident2,
     output: ReturnType,
+    priority: Option<u8>,
     stmts: Vec<Stmt>,
 }
 
+impl Fn {
+    fn priority(&self) -> u8 {
+        self.priority.unwrap_or(DEFAULT_PRIORITY)
+    }
+
+    // does this function use the shared static `name`?
+    fn uses(&self, name: &Ident2) -> bool {
+        let stmts = &self.stmts;
+        mentions(quote!(#(#stmts)*), name)
+    }
+}
+
 struct Static {
     expr: Box<Expr>,
     name: Ident2,
     ty: Box<Type>,
     uninit: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use proc_macro2::TokenStream as TokenStream2;
+    use quote::quote;
+
+    fn expand(input: TokenStream2) -> Result<String, String> {
+        syn::parse2(input)
+            .and_then(super::expand)
+            .map(|ts| ts.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn not_send() {
+        let ty = quote!(Cell<u32>);
+        let is_send = quote!(const _: fn() = is_send::<#ty>;).to_string();
+
+        // rustc rejects the expansion if a static shared across priorities is not `Send`
+        let ts = expand(quote!(
+            mod task {
+                static mut SHARED: Cell<u32> = Cell::new(0);
+
+                #[priority = 1]
+                fn RTC0() {
+                    SHARED.lock(|shared| shared.get());
+                }
+
+                #[priority = 2]
+                fn TIMER0() {
+                    SHARED.set(1);
+                }
+            }
+        ))
+        .unwrap();
+        assert!(ts.contains(&is_send));
+
+        // no check if all the users run at the same priority
+        let ts = expand(quote!(
+            mod task {
+                static mut SHARED: Cell<u32> = Cell::new(0);
+
+                fn RTC0() {
+                    SHARED.get();
+                }
+
+                fn TIMER0() {
+                    SHARED.set(1);
+                }
+            }
+        ))
+        .unwrap();
+        assert!(!ts.contains(&is_send));
+    }
+
+    #[test]
+    fn init_priority() {
+        let e = expand(quote!(
+            mod task {
+                #[priority = 1]
+                fn init() {}
+            }
+        ))
+        .unwrap_err();
+        assert!(e.contains("it can't have a priority"));
+    }
+
+    #[test]
+    fn higher_priority_not_interrupt() {
+        // `idle` runs at the default priority, which is higher than `RTC0`'s
+        let e = expand(quote!(
+            mod task {
+                static mut SHARED: u32 = 0;
+
+                #[priority = 1]
+                fn RTC0() {
+                    SHARED.lock(|shared| *shared);
+                }
+
+                fn idle() {
+                    *SHARED += 1;
+                }
+            }
+        ))
+        .unwrap_err();
+        assert!(e.contains("`idle` shares `SHARED` with lower priority functions"));
+    }
+}